@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS `auth_log` (
+    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT COMMENT '主键',
+    `event` TINYINT(1) UNSIGNED NOT NULL COMMENT '1账号锁定，2验证码作废，3IP限制',
+    `scope` VARCHAR(20) NOT NULL COMMENT 'admin user phone ip',
+    `principal` VARCHAR(200) NOT NULL COMMENT '账号、手机号或IP',
+    `ip` VARCHAR(64) NOT NULL COMMENT '请求的IP',
+    `desc` VARCHAR(200) NOT NULL COMMENT '详情',
+    `create_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    PRIMARY KEY (`id`),
+    KEY `principal` (`scope`, `principal`),
+    KEY `create_at` (`create_at`)
+) ENGINE = InnoDB COMMENT = '登陆安全日志';
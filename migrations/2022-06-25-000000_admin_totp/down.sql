@@ -0,0 +1,6 @@
+ALTER TABLE `admin`
+    DROP COLUMN `totp_secret`,
+    DROP COLUMN `totp_enabled`,
+    DROP COLUMN `recovery_codes`;
+
+DROP TABLE sys_setting;
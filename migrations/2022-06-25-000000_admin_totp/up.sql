@@ -0,0 +1,11 @@
+ALTER TABLE `admin`
+    ADD COLUMN `totp_secret` VARCHAR(200) NULL COMMENT '加密后的 TOTP 密钥',
+    ADD COLUMN `totp_enabled` BOOL DEFAULT FALSE NOT NULL COMMENT '是否开启两步验证',
+    ADD COLUMN `recovery_codes` VARCHAR(1000) NULL COMMENT '恢复码的 sha256，json 数组';
+
+CREATE TABLE IF NOT EXISTS `sys_setting` (
+    `name` VARCHAR(100) NOT NULL COMMENT '设置项',
+    `value` VARCHAR(1000) NOT NULL COMMENT '值',
+    `update_at` DATETIME ON UPDATE CURRENT_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '更新时间',
+    PRIMARY KEY (`name`)
+) ENGINE = InnoDB COMMENT = '系统设置';
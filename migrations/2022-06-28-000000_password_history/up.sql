@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS `password_history` (
+    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT COMMENT '主键',
+    `scope` VARCHAR(20) NOT NULL COMMENT 'admin user',
+    `principal_id` BIGINT UNSIGNED NOT NULL COMMENT '管理员或用户id',
+    `password` VARCHAR(200) NOT NULL COMMENT '加密后的密码',
+    `create_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    PRIMARY KEY (`id`),
+    KEY `principal` (`scope`, `principal_id`)
+) ENGINE = InnoDB COMMENT = '用过的密码';
@@ -0,0 +1,10 @@
+ALTER TABLE `admin_log`
+    DROP KEY `object`,
+    DROP KEY `create_at`,
+    DROP COLUMN `object_type`,
+    DROP COLUMN `object_id`,
+    DROP COLUMN `changes`,
+    DROP COLUMN `ip`;
+
+ALTER TABLE `admin_log`
+    ADD FOREIGN KEY (`admin_id`) REFERENCES `admin`(`id`) ON DELETE CASCADE ON UPDATE CASCADE;
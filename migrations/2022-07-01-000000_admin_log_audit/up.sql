@@ -0,0 +1,10 @@
+-- 删除管理员后保留他的操作日志
+ALTER TABLE `admin_log` DROP FOREIGN KEY `admin_log_ibfk_1`;
+
+ALTER TABLE `admin_log`
+    ADD COLUMN `object_type` VARCHAR(50) NOT NULL DEFAULT '' COMMENT '操作的对象类型，例如 admin user',
+    ADD COLUMN `object_id` BIGINT UNSIGNED NULL COMMENT '操作的对象id',
+    ADD COLUMN `changes` TEXT NULL COMMENT '修改前后的值，json {"字段": [修改前, 修改后]}',
+    ADD COLUMN `ip` VARCHAR(64) NOT NULL DEFAULT '' COMMENT '请求的IP',
+    ADD KEY `object` (`object_type`, `object_id`),
+    ADD KEY `create_at` (`create_at`);
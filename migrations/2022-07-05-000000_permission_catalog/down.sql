@@ -0,0 +1,2 @@
+ALTER TABLE `auth_permission`
+    DROP COLUMN `orphaned`;
@@ -0,0 +1,2 @@
+ALTER TABLE `auth_permission`
+    ADD COLUMN `orphaned` BOOL DEFAULT FALSE NOT NULL COMMENT '代码中已没有声明，启动时同步';
@@ -0,0 +1,5 @@
+ALTER TABLE `posts` DROP KEY `ft_title_content`;
+
+ALTER TABLE `posts`
+    MODIFY `title` VARCHAR(20) NOT NULL COMMENT '标题',
+    MODIFY `content` VARCHAR(300) NOT NULL COMMENT '内容';
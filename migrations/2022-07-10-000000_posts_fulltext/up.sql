@@ -0,0 +1,7 @@
+ALTER TABLE `posts`
+    MODIFY `title` VARCHAR(200) NOT NULL COMMENT '标题',
+    MODIFY `content` TEXT NOT NULL COMMENT '内容';
+
+-- ngram 支持中文分词，最小词长由 ngram_token_size 配置(默认2)
+ALTER TABLE `posts`
+    ADD FULLTEXT KEY `ft_title_content` (`title`, `content`) WITH PARSER ngram;
@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS `orders` (
+    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT COMMENT '主键',
+    `order_no` VARCHAR(32) NOT NULL COMMENT '订单号，get_order_id! 生成',
+    `user_id` BIGINT UNSIGNED NOT NULL COMMENT '下单用户',
+    `amount` DECIMAL(10, 2) NOT NULL COMMENT '应付金额',
+    `status` TINYINT(1) UNSIGNED DEFAULT '1' NOT NULL COMMENT '1待支付，2已支付，3已取消，4已退款',
+    `pay_channel` VARCHAR(20) NOT NULL DEFAULT '' COMMENT '支付渠道 alipay wxpay',
+    `trade_no` VARCHAR(64) NOT NULL DEFAULT '' COMMENT '支付平台的交易号',
+    `expire_at` DATETIME NOT NULL COMMENT '超过时间未支付自动取消',
+    `paid_at` DATETIME NULL COMMENT '支付时间',
+    `closed_at` DATETIME NULL COMMENT '取消或退款时间',
+    `create_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    `update_at` DATETIME ON UPDATE CURRENT_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '更新时间',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `order_no` (`order_no`),
+    KEY `user_id` (`user_id`),
+    KEY `status` (`status`, `expire_at`),
+    FOREIGN KEY (`user_id`) REFERENCES `users`(`id`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE = InnoDB COMMENT = '订单表';
+
+CREATE TABLE IF NOT EXISTS `order_items` (
+    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT COMMENT '主键',
+    `order_id` BIGINT UNSIGNED NOT NULL COMMENT '订单',
+    `book_id` BIGINT UNSIGNED NOT NULL COMMENT '书，删除后订单仍然保留',
+    `name` VARCHAR(20) NOT NULL COMMENT '下单时的书名',
+    `price` DECIMAL(8, 2) NOT NULL COMMENT '下单时的单价',
+    `num` INT UNSIGNED NOT NULL COMMENT '数量',
+    `create_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    PRIMARY KEY (`id`),
+    KEY `order_id` (`order_id`),
+    KEY `book_id` (`book_id`),
+    FOREIGN KEY (`order_id`) REFERENCES `orders`(`id`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE = InnoDB COMMENT = '订单明细';
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS `spike_events` (
+    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT COMMENT '主键',
+    `title` VARCHAR(50) NOT NULL COMMENT '活动名称',
+    `book_id` BIGINT UNSIGNED NOT NULL COMMENT '秒杀的书',
+    `price` DECIMAL(8, 2) NOT NULL COMMENT '秒杀价',
+    `stock` INT UNSIGNED NOT NULL COMMENT '秒杀的数量，开始前预热到 redis',
+    `per_user_limit` INT UNSIGNED NOT NULL DEFAULT '1' COMMENT '每人最多购买的数量',
+    `begin_at` DATETIME NOT NULL COMMENT '开始时间',
+    `end_at` DATETIME NOT NULL COMMENT '结束时间',
+    `create_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    `update_at` DATETIME ON UPDATE CURRENT_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '更新时间',
+    PRIMARY KEY (`id`),
+    KEY `book_id` (`book_id`),
+    KEY `end_at` (`end_at`),
+    FOREIGN KEY (`book_id`) REFERENCES `books`(`id`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE = InnoDB COMMENT = '秒杀活动';
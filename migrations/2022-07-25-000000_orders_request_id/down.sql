@@ -0,0 +1,3 @@
+ALTER TABLE `orders`
+    DROP INDEX `user_request`,
+    DROP COLUMN `request_id`;
@@ -0,0 +1,3 @@
+ALTER TABLE `orders`
+    ADD COLUMN `request_id` VARCHAR(64) NULL COMMENT '客户端的请求id，同一个用户只能下一次单，秒杀订单使用',
+    ADD UNIQUE KEY `user_request` (`user_id`, `request_id`);
@@ -0,0 +1,3 @@
+ALTER TABLE `orders`
+    DROP INDEX `spike_event_id`,
+    DROP COLUMN `spike_event_id`;
@@ -0,0 +1,3 @@
+ALTER TABLE `orders`
+    ADD COLUMN `spike_event_id` BIGINT UNSIGNED NULL COMMENT '秒杀活动，取消或退款时归还活动的库存和限购名额',
+    ADD KEY `spike_event_id` (`spike_event_id`);
@@ -0,0 +1,2 @@
+ALTER TABLE `orders`
+    MODIFY COLUMN `status` TINYINT(1) UNSIGNED DEFAULT '1' NOT NULL COMMENT '1待支付，2已支付，3已取消，4已退款';
@@ -0,0 +1,5 @@
+ALTER TABLE `users`
+    DROP COLUMN `locale`;
+
+ALTER TABLE `admin`
+    DROP COLUMN `locale`;
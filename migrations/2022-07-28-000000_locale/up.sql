@@ -0,0 +1,5 @@
+ALTER TABLE `users`
+    ADD COLUMN `locale` VARCHAR(10) NULL COMMENT '保存的语言 zh-CN en，登陆后的请求和通知优先使用';
+
+ALTER TABLE `admin`
+    ADD COLUMN `locale` VARCHAR(10) NULL COMMENT '保存的语言 zh-CN en，登陆后的请求和通知优先使用';
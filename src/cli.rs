@@ -0,0 +1,159 @@
+/**
+ * 命令行参数和子命令
+ *
+ * ```bash
+ * server                                   # 启动服务
+ * server permissions                       # 打印权限目录
+ * server admin create --username admin --superadmin
+ * server admin reset-password --username admin
+ * server admin list
+ * ```
+ */
+use crate::{config::AppConfig, models::admin::Admin, prelude::*, utils};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// 命令行参数
+#[derive(Parser, Debug)]
+#[clap(name = "server", version, about = "actix web 服务")]
+pub struct Cli {
+    /// 配置文件路径，支持 .toml 和 .env 格式，也可以使用环境变量 APP_CONFIG 指定
+    #[clap(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// 覆盖配置项，可重复使用，例如: --set ACTIX_PORT=8080
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// 不指定时启动服务
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 子命令
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 打印代码中声明的权限目录
+    Permissions,
+
+    /// 管理员账号
+    #[clap(subcommand)]
+    Admin(AdminCommand),
+}
+
+/// 管理员子命令，直接操作数据库，不需要启动服务
+#[derive(Subcommand, Debug)]
+pub enum AdminCommand {
+    /// 创建管理员，没有指定密码时随机生成并打印
+    Create {
+        #[clap(long)]
+        username: String,
+
+        /// 会保存在 shell 历史中，建议不指定
+        #[clap(long)]
+        password: Option<String>,
+
+        /// 超级管理员，拥有所有权限
+        #[clap(long)]
+        superadmin: bool,
+
+        #[clap(long)]
+        realname: Option<String>,
+
+        #[clap(long)]
+        email: Option<String>,
+    },
+
+    /// 重置密码，没有指定时随机生成并打印，已登陆的设备需要重新登陆
+    ResetPassword {
+        #[clap(long)]
+        username: String,
+
+        #[clap(long)]
+        password: Option<String>,
+    },
+
+    /// 列出所有管理员
+    List,
+}
+
+/// 执行管理员子命令
+pub async fn admin(cmd: &AdminCommand, config: &AppConfig) -> R<()> {
+    let db_pool = web::Data::new(utils::init::mysql_pool(config).await?);
+
+    match cmd {
+        AdminCommand::Create {
+            username,
+            password,
+            superadmin,
+            realname,
+            email,
+        } => {
+            let (password, generated) = password_or_generate(password);
+            let mut data = ser::admin::NewAdmin {
+                username: username.clone(),
+                password: password.clone(),
+                realname: realname.clone(),
+                email: email.clone(),
+                remark: None,
+                is_active: Some(true),
+                is_superadmin: Some(*superadmin),
+            };
+            data.validate()?;
+
+            data.password = pwd_encode!(&password);
+            let hash = data.password.clone();
+            let pk = Admin::create(db_pool.clone(), data).await?;
+            serv::password::remember(db_pool, Audience::Admin, pk, hash).await?;
+
+            println!("已创建管理员 {} id={}", username, pk);
+            if generated {
+                println!("密码: {}", password);
+            }
+        }
+
+        AdminCommand::ResetPassword { username, password } => {
+            let admin = Admin::obj_with_username(db_pool.clone(), username.clone()).await?;
+            let (password, generated) = password_or_generate(password);
+            utils::password::check(&password)?;
+            serv::password::set(db_pool, Audience::Admin, admin.id, password.clone()).await?;
+
+            let redis_pool = utils::init::redis_pool(config).await?;
+            serv::auth::revoke_sessions(&redis_pool, Audience::Admin, admin.id).await?;
+
+            println!("已重置管理员 {} 的密码", username);
+            if generated {
+                println!("密码: {}", password);
+            }
+        }
+
+        AdminCommand::List => {
+            println!(
+                "{:<8}{:<24}{:<12}{:<8}{:<8}{}",
+                "id", "username", "superadmin", "active", "2fa", "last_login"
+            );
+            for x in Admin::list_all(db_pool).await? {
+                println!(
+                    "{:<8}{:<24}{:<12}{:<8}{:<8}{}",
+                    x.id,
+                    x.username,
+                    x.is_superadmin,
+                    x.is_active,
+                    x.totp_enabled,
+                    x.last_login
+                        .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 没有指定密码时随机生成，返回 (密码, 是否随机生成)
+fn password_or_generate(password: &Option<String>) -> (String, bool) {
+    match password {
+        Some(x) => (x.clone(), false),
+        None => (utils::password::generate(), true),
+    }
+}
@@ -0,0 +1,628 @@
+/**
+ * 运行时配置
+ *
+ * 启动时从 配置文件(.toml/.env)、环境变量、命令行 加载，校验一次后全局只读
+ *
+ * 优先级: 命令行 --set > 环境变量 > 配置文件 > 默认值
+ */
+use crate::{cli::Cli, utils::i18n::Locale};
+use anyhow::{anyhow, bail, Result as R};
+use once_cell::sync::OnceCell;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+static CONFIG: OnceCell<AppConfig> = OnceCell::new();
+
+/// 获取全局配置，必须在 [init] 之后调用
+pub fn conf() -> &'static AppConfig {
+    CONFIG.get().expect("配置尚未初始化")
+}
+
+/// 配置可能尚未初始化时使用
+pub fn try_conf() -> Option<&'static AppConfig> {
+    CONFIG.get()
+}
+
+/// 加载并校验配置，只能调用一次
+pub fn init(cli: &Cli) -> R<&'static AppConfig> {
+    let config = AppConfig::load(cli)?;
+    CONFIG
+        .set(config)
+        .map_err(|_| anyhow!("配置只能初始化一次"))?;
+    Ok(conf())
+}
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// 应用程序的名称，用作 redis key 的前缀
+    pub app_name: String,
+
+    /// 日志级别 error warn info debug trace
+    pub rust_log: String,
+
+    /// 网站的域名
+    pub site_name: String,
+
+    /// 请求没有指定语言时使用的语言 zh-CN en
+    pub default_locale: Locale,
+
+    /// 指向静态文件的URL
+    pub static_url: String,
+
+    /// 指向media的URL
+    pub media_url: String,
+
+    pub actix_port: u16,
+
+    /// 监听方式，见 [ListenConfig]
+    pub listen: ListenConfig,
+
+    pub database_url: String,
+    pub redis_url: String,
+
+    /// 启动时 mysql/redis 不可用的重试次数
+    pub startup_retries: u32,
+
+    /// 第一次重试的间隔毫秒，之后每次翻倍
+    pub startup_retry_ms: u64,
+
+    /// 停机时等待请求和后台任务结束的秒数
+    pub shutdown_timeout: u64,
+
+    /// 订单超过这个秒数未支付自动取消，归还库存
+    pub order_pay_timeout: u64,
+
+    /// 加密 TOTP 密钥的默认密钥，旧版本用作所有密码的盐
+    pub pwd_secret: String,
+
+    /// 加密 TOTP 密钥，为空时使用 PWD_SECRET，修改后已开启两步验证的管理员需要重置
+    pub totp_key: String,
+
+    pub pwd: PasswordConfig,
+    pub jwt: JwtConfig,
+    pub qiniu: QiniuConfig,
+    pub mail: MailConfig,
+    pub sms: SmsConfig,
+    pub wx: WxConfig,
+    pub wxmp: WxmpConfig,
+    pub ali: AliConfig,
+}
+
+/// 监听方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenMode {
+    /// http，监听 LISTEN_HOST:ACTIX_PORT
+    Tcp,
+
+    /// unix socket，给 nginx 反向代理使用
+    Uds,
+
+    /// https，监听 LISTEN_HOST:ACTIX_PORT
+    Tls,
+}
+
+impl FromStr for ListenMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Ok(Self::Tcp),
+            "uds" => Ok(Self::Uds),
+            "tls" => Ok(Self::Tls),
+            _ => Err(format!("{}，可选值 tcp uds tls", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ListenConfig {
+    pub mode: ListenMode,
+    pub host: String,
+
+    /// socket 文件路径，默认 /tmp/{APP_NAME}.socket
+    pub uds_path: String,
+
+    /// socket 文件权限，八进制，nginx 需要有读写权限
+    pub uds_mode: u32,
+
+    /// 证书链和私钥，PEM 格式
+    pub tls_cert: String,
+    pub tls_key: String,
+
+    /// 在反向代理后面，从 Forwarded/X-Forwarded-For 获取客户端IP，否则使用连接的地址
+    pub trust_proxy: bool,
+}
+
+/// 密码策略和 argon2 参数，见 [crate::utils::password]
+#[derive(Debug, Clone)]
+pub struct PasswordConfig {
+    pub min_len: usize,
+    pub max_len: usize,
+
+    /// 至少包含几种字符: 小写字母 大写字母 数字 符号
+    pub min_classes: usize,
+
+    /// 额外的常用密码列表，一行一个，内置列表之外的补充
+    pub deny_file: String,
+
+    /// 不能使用最近几次用过的密码，0 不限制
+    pub history: usize,
+
+    /// argon2id 内存，单位 KiB
+    pub mem_cost: u32,
+
+    /// argon2id 迭代次数
+    pub time_cost: u32,
+
+    /// argon2id 并行度
+    pub lanes: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    /// 签名算法 HS256 RS256 ES256
+    pub alg: jsonwebtoken::Algorithm,
+
+    /// HS256 使用的密匙
+    pub secret: String,
+
+    /// RS256/ES256 的密钥目录，每个 `{kid}.pem` 是一个私钥或公钥，见 [crate::utils::jwt::init]
+    pub key_dir: String,
+
+    /// 当前用于签名的密钥，`{key_dir}/{kid}.pem` 必须是私钥
+    pub kid: String,
+
+    /// access token 过期时间，单位秒
+    pub exp: i64,
+
+    /// refresh token 过期时间，单位秒
+    pub refresh_exp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct QiniuConfig {
+    pub access_key: String,
+    pub secret_key: String,
+    pub bucket_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub from: String,
+    pub user: String,
+    pub pwd: String,
+    pub host: String,
+}
+
+/// 短信平台
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmsProvider {
+    /// 只打印到日志
+    Console,
+
+    /// POST json 到 SMS_URL
+    Http,
+}
+
+impl FromStr for SmsProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "console" => Ok(Self::Console),
+            "http" => Ok(Self::Http),
+            _ => Err(format!("{}，可选值 console http", s)),
+        }
+    }
+}
+
+/// 短信，见 [crate::modules::sms]
+#[derive(Debug, Clone)]
+pub struct SmsConfig {
+    pub provider: SmsProvider,
+    pub url: String,
+    pub api_key: String,
+
+    /// 短信签名
+    pub sign_name: String,
+
+    /// 每种用途在短信平台的模板id
+    pub template_register: String,
+    pub template_login: String,
+    pub template_reset_password: String,
+}
+
+/// 微信公众号
+#[derive(Debug, Clone)]
+pub struct WxConfig {
+    pub app_id: String,
+    pub appsecret: String,
+    pub token: String,
+    pub encoding_aes_key: String,
+}
+
+/// 微信小程序
+#[derive(Debug, Clone)]
+pub struct WxmpConfig {
+    pub app_id: String,
+    pub appsecret: String,
+}
+
+/// 支付宝，证书字段都是文件路径
+#[derive(Debug, Clone)]
+pub struct AliConfig {
+    pub merchant_pid: String,
+    pub appid: String,
+    pub gateway_addr: String,
+    pub content_encrypt: String,
+    pub public_key_cert: String,
+    pub private_key: String,
+    pub alipay_public_key: String,
+    pub alipay_public_key_cert: String,
+    pub alipay_root_cert: String,
+
+    /// 支付结果异步通知的地址，{SITE_NAME}/api/orders/notify/alipay
+    pub notify_url: String,
+}
+
+impl AppConfig {
+    /// 按优先级合并所有配置来源，并校验
+    pub fn load(cli: &Cli) -> R<Self> {
+        let mut src = Source {
+            file: read_file(cli.config.clone())?,
+            overrides: parse_overrides(&cli.overrides)?,
+            errors: Vec::new(),
+        };
+
+        // 默认的 socket 文件名依赖 APP_NAME
+        let app_name = src.required("APP_NAME");
+        let listen = ListenConfig {
+            mode: src.parse("LISTEN", ListenMode::Tcp),
+            host: src.str("LISTEN_HOST", "0.0.0.0"),
+            uds_path: src.str("UDS_PATH", &format!("/tmp/{}.socket", app_name)),
+            uds_mode: src.octal("UDS_MODE", 0o666),
+            tls_cert: src.str("TLS_CERT", ""),
+            tls_key: src.str("TLS_KEY", ""),
+            trust_proxy: src.parse("TRUST_PROXY", false),
+        };
+
+        let config = Self {
+            app_name,
+            rust_log: src.str("RUST_LOG", "info"),
+            site_name: src.str("SITE_NAME", ""),
+            default_locale: src.parse("DEFAULT_LOCALE", Locale::ZhCn),
+            static_url: src.str("STATIC_URL", "/svr-static"),
+            media_url: src.str("MEDIA_URL", "/media"),
+            actix_port: src.parse("ACTIX_PORT", 7788),
+            listen,
+            database_url: src.required("DATABASE_URL"),
+            redis_url: src.required("REDIS_URL"),
+            startup_retries: src.parse("STARTUP_RETRIES", 5),
+            startup_retry_ms: src.parse("STARTUP_RETRY_MS", 500),
+            shutdown_timeout: src.parse("SHUTDOWN_TIMEOUT", 30),
+            order_pay_timeout: src.parse("ORDER_PAY_TIMEOUT", 60 * 15),
+            pwd_secret: src.required("PWD_SECRET"),
+            totp_key: src.str("TOTP_KEY", ""),
+            pwd: PasswordConfig {
+                min_len: src.parse("PWD_MIN_LEN", 8),
+                max_len: src.parse("PWD_MAX_LEN", 64),
+                min_classes: src.parse("PWD_MIN_CLASSES", 2),
+                deny_file: src.str("PWD_DENY_FILE", ""),
+                history: src.parse("PWD_HISTORY", 5),
+                mem_cost: src.parse("PWD_MEM_COST", 19456),
+                time_cost: src.parse("PWD_TIME_COST", 2),
+                lanes: src.parse("PWD_LANES", 1),
+            },
+            jwt: JwtConfig {
+                alg: src.parse("JWT_ALG", jsonwebtoken::Algorithm::HS256),
+                secret: src.str("JWT_SECRET", ""),
+                key_dir: src.str("JWT_KEY_DIR", "keys/jwt"),
+                kid: src.str("JWT_KID", ""),
+                exp: src.parse("JWT_EXP", 60 * 15),
+                refresh_exp: src.parse("JWT_REFRESH_EXP", 60 * 60 * 24 * 30),
+            },
+            qiniu: QiniuConfig {
+                access_key: src.str("QN_ACCESS_KEY", ""),
+                secret_key: src.str("QN_SECRET_KEY", ""),
+                bucket_name: src.str("QN_BUCKET_NAME", ""),
+            },
+            mail: MailConfig {
+                from: src.str("MAIL_FROM", ""),
+                user: src.str("MAIL_USER", ""),
+                pwd: src.str("MAIL_PWD", ""),
+                host: src.str("MAIL_HOST", ""),
+            },
+            sms: SmsConfig {
+                provider: src.parse("SMS_PROVIDER", SmsProvider::Console),
+                url: src.str("SMS_URL", ""),
+                api_key: src.str("SMS_API_KEY", ""),
+                sign_name: src.str("SMS_SIGN_NAME", ""),
+                template_register: src.str("SMS_TEMPLATE_REGISTER", ""),
+                template_login: src.str("SMS_TEMPLATE_LOGIN", ""),
+                template_reset_password: src.str("SMS_TEMPLATE_RESET_PASSWORD", ""),
+            },
+            wx: WxConfig {
+                app_id: src.str("WX_APP_ID", ""),
+                appsecret: src.str("WX_APPSECRET", ""),
+                token: src.str("WX_TOKEN", ""),
+                encoding_aes_key: src.str("WX_ENCODING_AES_KEY", ""),
+            },
+            wxmp: WxmpConfig {
+                app_id: src.str("WXMP_APP_ID", ""),
+                appsecret: src.str("WXMP_APPSECRET", ""),
+            },
+            ali: AliConfig {
+                merchant_pid: src.str("ALI_MERCHANT_PID", ""),
+                appid: src.str("ALI_APPID", ""),
+                gateway_addr: src.str("ALI_GATEWAY_ADDR", "https://openapi.alipay.com/gateway.do"),
+                content_encrypt: src.str("ALI_CONTENT_ENCRYPT", ""),
+                public_key_cert: src.str("ALI_PUBLIC_KEY_CERT", ""),
+                private_key: src.str("ALI_PRIVATE_KEY", ""),
+                alipay_public_key: src.str("ALI_ALIPAY_PUBLIC_KEY", ""),
+                alipay_public_key_cert: src.str("ALI_ALIPAY_PUBLIC_KEY_CERT", ""),
+                alipay_root_cert: src.str("ALI_ALIPAY_ROOT_CERT", ""),
+                notify_url: src.str("ALI_NOTIFY_URL", ""),
+            },
+        };
+
+        config.validate(&mut src.errors);
+
+        if !src.errors.is_empty() {
+            bail!("配置错误:\n  {}", src.errors.join("\n  "));
+        }
+
+        Ok(config)
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.app_name.contains(char::is_whitespace) {
+            errors.push("APP_NAME 不能包含空格".into());
+        }
+
+        if !self.database_url.is_empty() && !self.database_url.starts_with("mysql://") {
+            errors.push("DATABASE_URL 必须是 mysql:// 开头".into());
+        }
+
+        if !self.redis_url.is_empty() {
+            match url::Url::parse(&self.redis_url) {
+                Ok(u) if u.scheme() == "redis" || u.scheme() == "rediss" => (),
+                _ => errors.push("REDIS_URL 必须是 redis:// 或 rediss:// 开头".into()),
+            }
+        }
+
+        if !self.site_name.is_empty() && url::Url::parse(&self.site_name).is_err() {
+            errors.push("SITE_NAME 必须是完整的URL".into());
+        }
+
+        if self.listen.mode == ListenMode::Tls {
+            for (key, path) in [
+                ("TLS_CERT", &self.listen.tls_cert),
+                ("TLS_KEY", &self.listen.tls_key),
+            ] {
+                if path.is_empty() {
+                    errors.push(format!("缺少配置: {}", key));
+                } else if !Path::new(path).is_file() {
+                    errors.push(format!("{} 文件不存在: {}", key, path));
+                }
+            }
+        }
+
+        if self.pwd.min_len == 0 || self.pwd.min_len > self.pwd.max_len {
+            errors.push("PWD_MIN_LEN 必须大于0并且不能大于 PWD_MAX_LEN".into());
+        }
+
+        if self.order_pay_timeout == 0 {
+            errors.push("ORDER_PAY_TIMEOUT 必须大于0".into());
+        }
+
+        if self.pwd.min_classes > 4 {
+            errors.push("PWD_MIN_CLASSES 最大为4".into());
+        }
+
+        if !self.pwd.deny_file.is_empty() && !Path::new(&self.pwd.deny_file).is_file() {
+            errors.push(format!("PWD_DENY_FILE 文件不存在: {}", self.pwd.deny_file));
+        }
+
+        if self.pwd.time_cost == 0 || self.pwd.lanes == 0 || self.pwd.mem_cost < 8 * self.pwd.lanes
+        {
+            errors.push(
+                "PWD_TIME_COST、PWD_LANES 必须大于0，PWD_MEM_COST 至少为 8 * PWD_LANES".into(),
+            );
+        }
+
+        match self.jwt.alg {
+            jsonwebtoken::Algorithm::HS256 => {
+                if self.jwt.secret.is_empty() {
+                    errors.push("缺少配置: JWT_SECRET".into());
+                }
+            }
+            jsonwebtoken::Algorithm::RS256 | jsonwebtoken::Algorithm::ES256 => {
+                if self.jwt.kid.is_empty() {
+                    errors.push("缺少配置: JWT_KID".into());
+                } else {
+                    let path = Path::new(&self.jwt.key_dir).join(format!("{}.pem", self.jwt.kid));
+                    if !path.is_file() {
+                        errors.push(format!("JWT_KID 密钥文件不存在: {}", path.display()));
+                    }
+                }
+            }
+            alg => errors.push(format!(
+                "JWT_ALG 不支持 {:?}，可选值 HS256 RS256 ES256",
+                alg
+            )),
+        }
+
+        if self.jwt.exp <= 0 {
+            errors.push("JWT_EXP 必须大于0".into());
+        }
+
+        if self.jwt.refresh_exp <= self.jwt.exp {
+            errors.push("JWT_REFRESH_EXP 必须大于 JWT_EXP".into());
+        }
+
+        if self.sms.provider == SmsProvider::Http && url::Url::parse(&self.sms.url).is_err() {
+            errors.push("SMS_PROVIDER=http 时 SMS_URL 必须是完整的URL".into());
+        }
+
+        if cfg!(feature = "mail") {
+            if !validator::validate_email(&self.mail.from) {
+                errors.push("MAIL_FROM 邮箱格式错误".into());
+            }
+            if self.mail.host.is_empty() {
+                errors.push("缺少配置: MAIL_HOST".into());
+            }
+        }
+    }
+}
+
+/// 配置来源
+struct Source {
+    file: HashMap<String, String>,
+    overrides: HashMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl Source {
+    fn get(&self, key: &str) -> Option<String> {
+        self.overrides
+            .get(key)
+            .cloned()
+            .or_else(|| std::env::var(key).ok())
+            .or_else(|| self.file.get(key).cloned())
+    }
+
+    fn str(&self, key: &str, default: &str) -> String {
+        self.get(key).unwrap_or_else(|| default.to_owned())
+    }
+
+    /// 必填项，缺少或为空时记录错误
+    fn required(&mut self, key: &str) -> String {
+        match self.get(key) {
+            Some(v) if !v.trim().is_empty() => v,
+            _ => {
+                self.errors.push(format!("缺少配置: {}", key));
+                String::new()
+            }
+        }
+    }
+
+    fn parse<T>(&mut self, key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.get(key) {
+            Some(v) if !v.trim().is_empty() => match v.trim().parse::<T>() {
+                Ok(v) => v,
+                Err(err) => {
+                    self.errors.push(format!("{} 格式错误: {}", key, err));
+                    default
+                }
+            },
+            _ => default,
+        }
+    }
+
+    /// 八进制数字，例如文件权限 660
+    fn octal(&mut self, key: &str, default: u32) -> u32 {
+        match self.get(key) {
+            Some(v) if !v.trim().is_empty() => {
+                let v = v.trim();
+                let v = v.strip_prefix("0o").unwrap_or(v);
+                match u32::from_str_radix(v, 8) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        self.errors.push(format!("{} 格式错误: {}", key, err));
+                        default
+                    }
+                }
+            }
+            _ => default,
+        }
+    }
+}
+
+/// 解析命令行中的 KEY=VALUE
+fn parse_overrides(items: &[String]) -> R<HashMap<String, String>> {
+    items
+        .iter()
+        .map(|item| {
+            let (k, v) = item
+                .split_once('=')
+                .ok_or_else(|| anyhow!("--set 参数格式错误: {}，应为 KEY=VALUE", item))?;
+            Ok((k.trim().to_uppercase(), v.to_owned()))
+        })
+        .collect()
+}
+
+/// 读取配置文件
+///
+/// 未指定时使用环境变量 APP_CONFIG，再没有则按特性使用 .env 或 prod.env，默认文件不存在时忽略
+fn read_file(path: Option<PathBuf>) -> R<HashMap<String, String>> {
+    let path = path.or_else(|| std::env::var_os("APP_CONFIG").map(PathBuf::from));
+
+    let path = match path {
+        Some(p) => p,
+        None => {
+            let p = PathBuf::from(if cfg!(feature = "dev") {
+                ".env"
+            } else {
+                "prod.env"
+            });
+            if !p.exists() {
+                return Ok(HashMap::new());
+            }
+            p
+        }
+    };
+
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("toml") => read_toml(&path),
+        _ => read_dotenv(&path),
+    }
+}
+
+// from_path_iter 是唯一只读取、不写入环境变量的方式
+#[allow(deprecated)]
+fn read_dotenv(path: &Path) -> R<HashMap<String, String>> {
+    dotenv::from_path_iter(path)
+        .map_err(|e| anyhow!("读取配置文件 {} 失败: {}", path.display(), e))?
+        .map(|item| item.map_err(|e| anyhow!("解析配置文件 {} 失败: {}", path.display(), e)))
+        .collect()
+}
+
+/// toml 中的表会展开为 `表名_键名`，例如 `[mail] host = ""` 对应 MAIL_HOST
+fn read_toml(path: &Path) -> R<HashMap<String, String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("读取配置文件 {} 失败: {}", path.display(), e))?;
+    let table: toml::value::Table = toml::from_str(&content)
+        .map_err(|e| anyhow!("解析配置文件 {} 失败: {}", path.display(), e))?;
+
+    let mut out = HashMap::new();
+    flatten_toml("", table, &mut out);
+    Ok(out)
+}
+
+fn flatten_toml(prefix: &str, table: toml::value::Table, out: &mut HashMap<String, String>) {
+    for (k, v) in table {
+        let key = if prefix.is_empty() {
+            k.to_uppercase()
+        } else {
+            format!("{}_{}", prefix, k.to_uppercase())
+        };
+
+        match v {
+            toml::Value::Table(t) => flatten_toml(&key, t, out),
+            toml::Value::String(s) => {
+                out.insert(key, s);
+            }
+            v => {
+                out.insert(key, v.to_string());
+            }
+        }
+    }
+}
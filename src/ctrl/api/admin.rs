@@ -1,7 +1,5 @@
-use crate::prelude::*;
-use models::admin::Admin;
-
-use schema::*;
+use crate::{perm, prelude::*};
+use models::{admin::Admin, admin_log::ActionFlag};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(list)
@@ -10,165 +8,536 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(create)
         .service(update)
         .service(change_pwd)
-        .service(del);
+        .service(set_locale)
+        .service(del)
+        .service(permissions)
+        .service(grant_groups)
+        .service(revoke_groups)
+        .service(grant_permissions)
+        .service(revoke_permissions)
+        .service(totp_enroll)
+        .service(totp_verify)
+        .service(totp_recovery_codes)
+        .service(totp_disable)
+        .service(totp_force)
+        .service(totp_reset);
 }
 
-/// 列表
-#[http_get("list")]
-pub async fn list(
-    data: web::Query<ser::admin::ListQuery>,
-    db_pool: web::Data<DbPool>,
-) -> HttpResult {
-    let data = data.into_inner();
-
-    let mut q = admin::table
-        .filter(admin::is_superadmin.eq(false))
-        .into_boxed();
-
-    if let Some(ref value) = data.admin.email {
-        if !value.is_empty() {
-            q = q.filter(admin::email.like(format!("%{}%", value)));
-        }
-    }
-
-    if let Some(ref value) = data.admin.username {
-        if !value.is_empty() {
-            q = q.filter(admin::username.like(format!("%{}%", value)));
-        }
-    }
-
-    if let Some(ref value) = data.admin.realname {
-        if !value.is_empty() {
-            q = q.filter(admin::realname.like(format!("%{}%", value)));
-        }
-    }
-
-    if let Some(value) = data.admin.is_active {
-        q = q.filter(admin::is_active.eq(value));
-    }
-
-    if let Some(ref value) = data.admin.remark {
-        if !value.is_empty() {
-            q = q.filter(admin::remark.like(format!("%{}%", value)));
-        }
-    }
-
-    if let Some(ref value) = data.admin.last_login {
-        if !value.is_empty() {
-            let v: Vec<&str> = value.split('~').collect();
-            match v.len() {
-                1 => {
-                    q =
-                        q.filter(admin::last_login.eq(
-                            NaiveDateTime::parse_from_str(v[0], "%Y-%m-%d %H:%M:%S").map_err(ej)?,
-                        ));
-                }
-                2 => {
-                    q = q.filter(admin::last_login.between(
-                        NaiveDateTime::parse_from_str(v[0], "%Y-%m-%d %H:%M:%S").map_err(ej)?,
-                        NaiveDateTime::parse_from_str(v[1], "%Y-%m-%d %H:%M:%S").map_err(ej)?,
-                    ));
-                }
-                _ => (),
-            }
-        }
-    }
-
-    if data.limit.is_some() {
-        let res = web::block(move || -> R<_> {
-            let mut conn = db_pool.get()?;
-            let (limit, offset) = get_limit_offset!(&data);
-
-            // let total: i64 = admin::table.count().get_result(&mut conn)?;
-            // let total: i64 = q.select(dsl::count_star()).first(&mut conn)?;
-            let total: i64 = 0;
-
-            q = q.limit(limit).offset(offset);
-            let list = q.load::<Admin>(&mut conn)?;
-
-            Ok((list, total))
-        })
-        .await
-        .map_err(ej)?
-        .map_err(ej)?;
-
-        res_ok!(res.0, res.1)
-    } else {
-        let res = web::block(move || -> R<_> {
-            let mut conn = db_pool.get()?;
-            let list = q.load::<Admin>(&mut conn)?;
-            Ok(list)
-        })
-        .await
-        .map_err(ej)?
-        .map_err(ej)?;
-        res_ok!(res)
-    }
+/// 分页列表，见 [crate::utils::page]
+#[http_get("list", wrap = "perm::ADMIN_LIST")]
+pub async fn list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(Admin::page(db_pool, page).await.map_err(ej)?)
 }
 
 /// 当前登陆admin信息
 #[http_get("current")]
-pub async fn current(
-    db_pool: web::Data<DbPool>,
-    claims: Option<web::ReqData<Claims>>,
-) -> HttpResult {
-    let claims = claims.ok_or("请求错误").map_err(ej)?;
-    let res: Admin = Admin::obj(db_pool, claims.id).await.map_err(ej)?;
-    res_ok!(res)
+pub async fn current(admin: AuthAdmin) -> HttpResult {
+    res_ok!(admin.admin)
 }
 
 /// 使用id获取信息
-#[http_get("get/{pk}")]
+#[http_get("get/{pk}", wrap = "perm::ADMIN_VIEW")]
 pub async fn obj(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
     let res: Admin = Admin::obj(db_pool, pk.into_inner()).await.map_err(ej)?;
     res_ok!(res)
 }
 
 /// 创建一个非超级管理员账号
-#[http_post("/create")]
+#[http_post("/create", wrap = "perm::ADMIN_CREATE")]
 pub async fn create(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
     data: HttpBody<ser::admin::NewAdmin>,
 ) -> HttpResult {
     let mut data = data.into_inner();
     data.validate().map_err(OkErr::ev)?;
-    data.password = pwd_encode!(&data.password);
-    let new_id = Admin::create(db_pool, data).await.map_err(ej)?;
+    data.password = serv::password::hash(data.password).await.map_err(ej)?;
+    let hash = data.password.clone();
+    let new_id = Admin::create(db_pool.clone(), data).await.map_err(ej)?;
+    serv::password::remember(db_pool.clone(), Audience::Admin, new_id, hash)
+        .await
+        .map_err(ej)?;
+
+    let after = Admin::obj(db_pool.clone(), new_id).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::ADD,
+        "admin.create",
+        "admin",
+        Some(new_id),
+    );
+    log.desc = format!("创建管理员 {}", after.username);
+    log.changes = serv::audit::diff(None, Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
     res_ok!(new_id)
 }
 
 /// 批量删除
-#[http_post("del")]
+#[http_post("del", wrap = "perm::ADMIN_DELETE")]
 pub async fn del(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
     web::Json(data): web::Json<ser::admin::DelMany>,
 ) -> HttpResult {
-    let rows = Admin::del(db_pool, data.ids).await.map_err(ej)?;
+    let before = Admin::objs(db_pool.clone(), data.ids.clone())
+        .await
+        .map_err(ej)?;
+    if before.iter().any(|x| !manageable(&admin, x)) {
+        return res_err!(Forbidden, "auth.forbidden");
+    }
+    let rows = Admin::del(db_pool.clone(), data.ids.clone(), admin.is_superadmin)
+        .await
+        .map_err(ej)?;
+    for pk in data.ids {
+        serv::auth::revoke_sessions(&redis_pool, Audience::Admin, pk)
+            .await
+            .map_err(ej)?;
+        serv::rbac::invalidate(&redis_pool, pk).await.map_err(ej)?;
+    }
+
+    let actor = serv::audit::Actor::new(&admin, &req);
+    let logs = before
+        .iter()
+        .map(|x| {
+            let mut log = actor.entry(ActionFlag::DEL, "admin.del", "admin", Some(x.id));
+            log.desc = format!("删除管理员 {}", x.username);
+            log.changes = serv::audit::diff(Some(x), None);
+            log
+        })
+        .collect();
+    serv::audit::record(db_pool, logs).await;
+    res_ok!(rows)
+}
+
+/// 保存当前登录admin的语言，登陆后的请求使用这个语言
+#[http_post("locale")]
+pub async fn set_locale(
+    db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::SetLocale>,
+) -> HttpResult {
+    data.validate().map_err(OkErr::ev)?;
+    let rows = Admin::set_locale(db_pool, admin.id, data.tag())
+        .await
+        .map_err(ej)?;
     res_ok!(rows)
 }
 
 /// 当前登录admin修改密码
 #[http_post("change_pwd")]
 pub async fn change_pwd(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
-    claims: Option<web::ReqData<Claims>>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
     web::Json(data): web::Json<ser::ChangePwd>,
 ) -> HttpResult {
     data.validate().map_err(OkErr::ev)?;
-    let pk = claims.ok_or("请先登录").map_err(ej)?.id;
-    let rows = Admin::change_pwd(db_pool, pk, data.pwd, data.new_pwd, data.new_pwd2)
+    let pk = admin.id;
+    let rows = serv::admin::change_pwd(db_pool.clone(), pk, data.pwd, data.new_pwd)
         .await
         .map_err(ej)?;
+
+    // 其他设备需要重新登陆
+    serv::auth::revoke_sessions(&redis_pool, Audience::Admin, pk)
+        .await
+        .map_err(ej)?;
+
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.change_pwd",
+        "admin",
+        Some(pk),
+    );
+    log.desc = "修改密码".to_owned();
+    log.changes = Some(json!({"password": ["***", "***"]}).to_string());
+    serv::audit::record(db_pool, vec![log]).await;
     res_ok!(rows)
 }
 
 /// 更新
-#[http_post("update")]
+#[http_post("update", wrap = "perm::ADMIN_UPDATE")]
 pub async fn update(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
     web::Json(data): web::Json<ser::admin::UpdateAdmin>,
 ) -> HttpResult {
     data.validate().map_err(OkErr::ev)?;
-    let rows = Admin::update(db_pool, data).await.map_err(ej)?;
+    let (pk, deactivated) = (data.id, data.is_active == Some(false));
+    let before = Admin::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    if !manageable(&admin, &before) {
+        return res_err!(Forbidden, "auth.forbidden");
+    }
+    let rows = Admin::update(db_pool.clone(), data, admin.is_superadmin)
+        .await
+        .map_err(ej)?;
+
+    if deactivated {
+        serv::auth::revoke_sessions(&redis_pool, Audience::Admin, pk)
+            .await
+            .map_err(ej)?;
+    }
+
+    let after = Admin::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.update",
+        "admin",
+        Some(pk),
+    );
+    log.desc = format!("修改管理员 {}", after.username);
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
     res_ok!(rows)
 }
+
+/// 管理员的组、直接分配的权限和有效权限
+#[http_get("get/{pk}/permissions", wrap = "perm::ADMIN_VIEW")]
+pub async fn permissions(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    let res = serv::rbac::admin_detail(db_pool, &redis_pool, pk.into_inner())
+        .await
+        .map_err(ej)?;
+    res_ok!(res)
+}
+
+/// 给管理员分配组
+#[http_post("grant_groups/{pk}", wrap = "perm::ADMIN_GRANT")]
+pub async fn grant_groups(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = group_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::rbac::grant_admin_groups(db_pool.clone(), &redis_pool, &admin, pk, data.ids)
+        .await
+        .map_err(ej)?;
+
+    let after = group_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.grant_groups",
+        "admin",
+        Some(pk),
+    );
+    log.desc = "分配组".to_owned();
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 取消管理员的组
+#[http_post("revoke_groups/{pk}", wrap = "perm::ADMIN_GRANT")]
+pub async fn revoke_groups(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = group_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::rbac::revoke_admin_groups(db_pool.clone(), &redis_pool, pk, data.ids)
+        .await
+        .map_err(ej)?;
+
+    let after = group_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.revoke_groups",
+        "admin",
+        Some(pk),
+    );
+    log.desc = "取消组".to_owned();
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 给管理员直接分配权限
+#[http_post("grant_permissions/{pk}", wrap = "perm::ADMIN_GRANT")]
+pub async fn grant_permissions(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows =
+        serv::rbac::grant_admin_permissions(db_pool.clone(), &redis_pool, &admin, pk, data.ids)
+            .await
+            .map_err(ej)?;
+
+    let after = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.grant_permissions",
+        "admin",
+        Some(pk),
+    );
+    log.desc = "分配权限".to_owned();
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 取消管理员直接分配的权限
+#[http_post("revoke_permissions/{pk}", wrap = "perm::ADMIN_GRANT")]
+pub async fn revoke_permissions(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::rbac::revoke_admin_permissions(db_pool.clone(), &redis_pool, pk, data.ids)
+        .await
+        .map_err(ej)?;
+
+    let after = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.revoke_permissions",
+        "admin",
+        Some(pk),
+    );
+    log.desc = "取消权限".to_owned();
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 绑定两步验证，返回密钥和 otpauth URI
+#[http_post("2fa/enroll")]
+pub async fn totp_enroll(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
+) -> HttpResult {
+    let res = serv::totp::enroll(db_pool.clone(), &admin)
+        .await
+        .map_err(ej)?;
+    record_totp(
+        db_pool,
+        &admin,
+        &req,
+        "admin.totp_enroll",
+        "绑定两步验证",
+        None,
+    )
+    .await;
+    res_ok!(res)
+}
+
+/// 验证绑定的验证码，开启两步验证，返回恢复码
+#[http_post("2fa/verify")]
+pub async fn totp_verify(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::admin::TotpCode>,
+) -> HttpResult {
+    let codes = serv::totp::activate(db_pool.clone(), &redis_pool, &admin, &data.code)
+        .await
+        .map_err(ej)?;
+    record_totp(
+        db_pool,
+        &admin,
+        &req,
+        "admin.totp_verify",
+        "开启两步验证",
+        Some((false, true)),
+    )
+    .await;
+    res_ok!(codes)
+}
+
+/// 重新生成恢复码
+#[http_post("2fa/recovery_codes")]
+pub async fn totp_recovery_codes(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::admin::TotpCode>,
+) -> HttpResult {
+    let codes =
+        serv::totp::regenerate_recovery_codes(db_pool.clone(), &redis_pool, &admin, &data.code)
+            .await
+            .map_err(ej)?;
+    record_totp(
+        db_pool,
+        &admin,
+        &req,
+        "admin.totp_recovery_codes",
+        "重新生成恢复码",
+        None,
+    )
+    .await;
+    res_ok!(codes)
+}
+
+/// 关闭两步验证
+#[http_post("2fa/disable")]
+pub async fn totp_disable(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::admin::TotpDisable>,
+) -> HttpResult {
+    serv::totp::disable(
+        db_pool.clone(),
+        &redis_pool,
+        &admin,
+        data.code.as_deref(),
+        data.recovery_code.as_deref(),
+    )
+    .await
+    .map_err(ej)?;
+    record_totp(
+        db_pool,
+        &admin,
+        &req,
+        "admin.totp_disable",
+        "关闭两步验证",
+        Some((true, false)),
+    )
+    .await;
+    res_ok!()
+}
+
+/// 超级管理员设置所有管理员必须开启两步验证
+#[http_post("2fa/force")]
+pub async fn totp_force(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::admin::ForceTotp>,
+) -> HttpResult {
+    if !admin.is_superadmin {
+        return res_err!(Forbidden, "auth.forbidden");
+    }
+    let before = serv::totp::forced(db_pool.clone()).await.map_err(ej)?;
+    serv::totp::set_forced(db_pool.clone(), data.enabled)
+        .await
+        .map_err(ej)?;
+
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.totp_force",
+        "sys_setting",
+        None,
+    );
+    log.desc = if data.enabled {
+        "强制开启两步验证".to_owned()
+    } else {
+        "取消强制两步验证".to_owned()
+    };
+    log.changes = serv::audit::diff(
+        Some(&json!({ "enabled": before })),
+        Some(&json!({ "enabled": data.enabled })),
+    );
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!()
+}
+
+/// 超级管理员重置其他管理员的两步验证
+#[http_post("2fa/reset/{pk}")]
+pub async fn totp_reset(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    if !admin.is_superadmin {
+        return res_err!(Forbidden, "auth.forbidden");
+    }
+    let pk = pk.into_inner();
+    let before = Admin::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::totp::reset(db_pool.clone(), &redis_pool, pk)
+        .await
+        .map_err(ej)?;
+
+    let after = Admin::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "admin.totp_reset",
+        "admin",
+        Some(pk),
+    );
+    log.desc = format!("重置管理员 {} 的两步验证", after.username);
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 管理员自己操作两步验证的日志，changes 为 totp_enabled 修改前后的值
+async fn record_totp(
+    db_pool: web::Data<DbPool>,
+    admin: &AuthAdmin,
+    req: &HttpRequest,
+    action: &str,
+    desc: &str,
+    changes: Option<(bool, bool)>,
+) {
+    let mut log = serv::audit::Actor::new(admin, req).entry(
+        ActionFlag::UPDATE,
+        action,
+        "admin",
+        Some(admin.id),
+    );
+    log.desc = desc.to_owned();
+    if let Some((before, after)) = changes {
+        log.changes = serv::audit::diff(
+            Some(&json!({ "totp_enabled": before })),
+            Some(&json!({ "totp_enabled": after })),
+        );
+    }
+    serv::audit::record(db_pool, vec![log]).await;
+}
+
+/// 超级管理员只能由超级管理员修改、删除
+fn manageable(operator: &AuthAdmin, target: &Admin) -> bool {
+    operator.is_superadmin || !target.is_superadmin
+}
+
+/// 管理员的组名，记录分配前后的差异
+async fn group_names(db_pool: web::Data<DbPool>, pk: PK) -> R<serde_json::Value> {
+    let names = models::auth_group::Group::of_admin(db_pool, pk)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect::<Vec<_>>();
+    Ok(json!({ "groups": names }))
+}
+
+/// 管理员直接分配的权限名，记录分配前后的差异
+async fn permission_names(db_pool: web::Data<DbPool>, pk: PK) -> R<serde_json::Value> {
+    let names = models::auth_permission::Permission::of_admin(db_pool, pk)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect::<Vec<_>>();
+    Ok(json!({ "permissions": names }))
+}
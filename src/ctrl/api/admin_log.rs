@@ -0,0 +1,28 @@
+use crate::{models::admin_log::AdminLog, perm, prelude::*};
+use actix_web::http::header;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list).service(export);
+}
+
+/// 分页列表，支持 cursor 分页，见 [crate::utils::page]
+#[http_get("", wrap = "perm::ADMIN_LOG_LIST")]
+pub async fn list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(AdminLog::page(db_pool, page).await.map_err(ej)?)
+}
+
+/// 导出 csv，查询条件和列表相同，忽略分页
+#[http_get("export", wrap = "perm::ADMIN_LOG_EXPORT")]
+pub async fn export(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    let logs = AdminLog::export(db_pool, page, serv::audit::EXPORT_MAX)
+        .await
+        .map_err(ej)?;
+    let filename = format!("admin_log_{}.csv", Local::now().format("%Y%m%d%H%M%S"));
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .body(serv::audit::to_csv(&logs)))
+}
@@ -1,27 +1,21 @@
 use crate::{
     models::user::{NewAccountUser, User},
+    modules::sms::SmsPurpose,
     prelude::*,
+    serv::guard,
 };
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(account_register)
         .service(phone_register)
         .service(login)
         .service(admin_login)
+        .service(admin_login_2fa)
+        .service(admin_login_2fa_enroll)
         .service(get_phone_captcha)
-        .service(cmd);
-}
-
-/// 执行特殊命令的接口
-#[http_get("/cmd/{cmd}")]
-pub async fn cmd(c: web::Path<String>) -> HttpResult {
-    return match c.into_inner().as_str() {
-        // 创建加密后的密码
-        "pwd" => {
-            res_ok!(pwd_encode!("123456"))
-        }
-
-        _ => Err(actix_web::error::ErrorNotFound("")),
-    };
+        .service(forgot_password)
+        .service(reset_password)
+        .service(refresh)
+        .service(logout);
 }
 
 /// AccountUser 注册
@@ -33,15 +27,22 @@ pub async fn account_register(
     let mut data = data.into_inner();
     data.validate().map_err(OkErr::ev)?;
 
-    data.password = pwd_encode!(&data.password);
+    data.password = serv::password::hash(data.password).await.map_err(ej)?;
+    let hash = data.password.clone();
 
-    User::create_account_user(db_pool, data).await.map_err(ej)?;
+    let pk = User::create_account_user(db_pool.clone(), data)
+        .await
+        .map_err(ej)?;
+    serv::password::remember(db_pool, Audience::User, pk, hash)
+        .await
+        .map_err(ej)?;
     res_ok!()
 }
 
 /// PhoneUser 注册
 #[http_post("/phone_register")]
 pub async fn phone_register(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
     redis_pool: web::Data<RedisPool>,
     data: HttpBody<ser::auth::PhoneUserRegister>,
@@ -50,9 +51,16 @@ pub async fn phone_register(
     data.validate().map_err(OkErr::ev)?;
     data.user.validate().map_err(OkErr::ev)?;
 
-    serv::auth::verify_phone_code(&redis_pool, &data.user.phone, &data.captcha)
-        .await
-        .map_err(ej)?;
+    serv::auth::verify_phone_code(
+        &redis_pool,
+        &db_pool,
+        SmsPurpose::Register,
+        &data.user.phone,
+        &data.captcha,
+        guard::client_ip(&req).as_deref(),
+    )
+    .await
+    .map_err(ej)?;
 
     User::create_phone_user(db_pool, data.user)
         .await
@@ -74,57 +82,171 @@ type: "mobile"
 */
 #[http_post("login")]
 pub async fn login(
+    req: HttpRequest,
     data: HttpBody<ser::auth::LoginAccount>,
     db_pool: web::Data<DbPool>,
     redis_pool: web::Data<RedisPool>,
 ) -> HttpResult {
     let data = data.into_inner();
     data.validate().map_err(OkErr::ev)?;
+    let ip = guard::client_ip(&req);
 
-    let access_token: String = match data.r#type.as_str() {
+    let tokens = match data.r#type.as_str() {
         "account" => {
             let username = data.username.unwrap_or_default();
             let password = data.password.unwrap_or_default();
-            serv::auth::account_login(username, password, db_pool)
+            serv::auth::account_login(username, password, ip.as_deref(), db_pool, redis_pool)
                 .await
                 .map_err(ej)?
         }
         "mobile" => {
             let mobile = data.mobile.unwrap_or_default();
             let captcha = data.captcha.unwrap_or_default();
-            serv::auth::phone_login(mobile, captcha, db_pool, redis_pool)
+            serv::auth::phone_login(mobile, captcha, ip.as_deref(), db_pool, redis_pool)
                 .await
                 .map_err(ej)?
         }
-        _ => return res_err!("调用接口错误"),
+        _ => return res_err!("auth.bad_login_type"),
     };
 
-    res_ok!(json!({ "access_token": access_token }))
+    res_ok!(tokens)
 }
 
 #[http_post("admin_login")]
 pub async fn admin_login(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
     data: HttpBody<ser::admin::Login>,
 ) -> HttpResult {
     let data = data.into_inner();
-    let access_token = models::admin::Admin::login(db_pool, data.username, data.password)
+    let ip = guard::client_ip(&req);
+    let res = serv::auth::admin_login(
+        data.username,
+        data.password,
+        ip.as_deref(),
+        db_pool,
+        redis_pool,
+    )
+    .await
+    .map_err(ej)?;
+    res_ok!(res)
+}
+
+/// 管理员两步验证，body `{"mfa_token": "", "code": ""}` 或 `{"mfa_token": "", "recovery_code": ""}`
+#[http_post("admin_login/2fa")]
+pub async fn admin_login_2fa(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    data: HttpBody<ser::auth::AdminLogin2fa>,
+) -> HttpResult {
+    let ip = guard::client_ip(&req);
+    let res = serv::auth::admin_login_2fa(data.into_inner(), ip.as_deref(), db_pool, redis_pool)
+        .await
+        .map_err(ej)?;
+    res_ok!(res)
+}
+
+/// 强制开启两步验证时，还没有绑定的管理员获取密钥
+#[http_post("admin_login/2fa/enroll")]
+pub async fn admin_login_2fa_enroll(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    data: HttpBody<ser::auth::MfaToken>,
+) -> HttpResult {
+    let res = serv::auth::admin_login_2fa_enroll(data.into_inner().mfa_token, db_pool, redis_pool)
+        .await
+        .map_err(ej)?;
+    res_ok!(res)
+}
+
+/// 忘记密码，发送重置链接到邮箱或验证码到手机
+#[http_post("password/forgot")]
+pub async fn forgot_password(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    data: HttpBody<ser::auth::ForgotPassword>,
+) -> HttpResult {
+    let data = data.into_inner();
+    data.validate().map_err(OkErr::ev)?;
+    serv::auth::forgot_password(db_pool, redis_pool, data, guard::client_ip(&req).as_deref())
+        .await
+        .map_err(ej)?;
+    res_ok!()
+}
+
+/// 重置密码
+#[http_post("password/reset")]
+pub async fn reset_password(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    data: HttpBody<ser::auth::ResetPassword>,
+) -> HttpResult {
+    let data = data.into_inner();
+    data.validate().map_err(OkErr::ev)?;
+    serv::auth::reset_password(db_pool, redis_pool, data, guard::client_ip(&req).as_deref())
+        .await
+        .map_err(ej)?;
+    res_ok!()
+}
+
+/// 使用 refresh token 换一对新的 token，每个 refresh token 只能使用一次
+#[http_post("refresh")]
+pub async fn refresh(
+    redis_pool: web::Data<RedisPool>,
+    data: HttpBody<ser::auth::RefreshToken>,
+) -> HttpResult {
+    let data = data.into_inner();
+    let tokens = serv::auth::refresh_tokens(&redis_pool, &data.refresh_token)
         .await
         .map_err(ej)?;
-    res_ok!(json!({ "access_token": access_token }))
+    res_ok!(tokens)
+}
+
+/// 退出登陆，请求头中的 access token 和 body 中的 refresh token 都会失效
+///
+/// access token 已过期时也可以调用
+#[http_post("logout")]
+pub async fn logout(
+    req: HttpRequest,
+    redis_pool: web::Data<RedisPool>,
+    data: Option<web::Json<ser::auth::Logout>>,
+) -> HttpResult {
+    let claims = req
+        .headers()
+        .get("Authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .and_then(|token| Claims::decode(token, &[Audience::Admin, Audience::User]).ok())
+        .map(|x| x.claims);
+    let refresh_token = data.and_then(|x| x.into_inner().refresh_token);
+
+    serv::auth::logout(&redis_pool, claims.as_ref(), refresh_token.as_deref())
+        .await
+        .map_err(ej)?;
+    res_ok!()
 }
 
 /// 使用手机号发送验证码
 #[http_get("get_phone_captcha")]
 pub async fn get_phone_captcha(
+    req: HttpRequest,
     data: web::Query<ser::auth::SendPhoneCaptcha>,
     redis_pool: web::Data<RedisPool>,
 ) -> HttpResult {
     let data = data.into_inner();
     data.validate().map_err(OkErr::ev)?;
 
-    serv::auth::send_phone_captcha(redis_pool, data.phone)
-        .await
-        .map_err(ej)?;
+    serv::auth::send_phone_captcha(
+        redis_pool,
+        data.purpose,
+        data.phone,
+        guard::client_ip(&req).as_deref(),
+    )
+    .await
+    .map_err(ej)?;
     res_ok!()
 }
@@ -1,23 +1,36 @@
 use crate::{
-    models::book::{Book, BookNew, BookUpdate},
+    models::{
+        book::{Book, BookNew, BookUpdate},
+        order::{OrderItemNew, OrderSource},
+    },
+    perm,
     prelude::*,
 };
 use schema::*;
 
+/// 不需要登陆
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list).service(list_all).service(retrieve);
+}
+
+/// 用户或管理员登陆后
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(create)
-        .service(list)
-        .service(list_all)
         .service(pay)
         .service(update)
-        .service(del)
-        .service(retrieve);
+        .service(del);
 }
 
+/// 当前用户创建
 #[http_post("")]
-pub async fn create(db_pool: web::Data<DbPool>, form: HttpBody<BookNew>) -> HttpResult {
-    let form = form.into_inner();
+pub async fn create(
+    db_pool: web::Data<DbPool>,
+    user: AuthUser,
+    form: HttpBody<BookNew>,
+) -> HttpResult {
+    let mut form = form.into_inner();
     form.validate().map_err(OkErr::ev)?;
+    form.user_id = user.id;
 
     res_ok!(Book::create(db_pool, form).await.map_err(ej)?)
 }
@@ -27,49 +40,46 @@ pub async fn retrieve(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResu
     res_ok!(Book::obj(db_pool, pk.into_inner(),).await.map_err(ej)?)
 }
 
-#[http_post("update")]
-pub async fn update(db_pool: web::Data<DbPool>, form: HttpBody<BookUpdate>) -> HttpResult {
+/// 用户只能修改自己的书，管理员需要 book.update 权限
+#[http_post("{pk}/update")]
+pub async fn update(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    claims: web::ReqData<Claims>,
+    pk: web::Path<PK>,
+    form: HttpBody<BookUpdate>,
+) -> HttpResult {
     let form = form.into_inner();
     form.validate().map_err(OkErr::ev)?;
 
-    res_ok!(Book::update(db_pool, form.id, form).await.map_err(ej)?)
+    let owner = serv::rbac::owner_scope(db_pool.clone(), &redis_pool, &claims, perm::BOOK_UPDATE.0)
+        .await
+        .map_err(ej)?;
+    res_ok!(Book::update(db_pool, pk.into_inner(), owner, form)
+        .await
+        .map_err(ej)?)
 }
 
+/// 用户只能删除自己的书，管理员需要 book.delete 权限
 #[http_post("{pk}/del")]
-pub async fn del(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
-    res_ok!(Book::del(db_pool, pk.into_inner()).await.map_err(ej)?)
-}
-
-#[http_get("")]
-pub async fn list(query: web::Query<ListRequest>, db_pool: web::Data<DbPool>) -> HttpResult {
-    let query = query.into_inner();
-
-    if query.page.is_none() || query.limit.is_none() {
-        res_ok!(web::block(move || -> R<_> {
-            let mut conn = db_pool.get()?;
-            Ok(books::table
-                .order_by(books::price.desc())
-                .load::<models::book::Book>(&mut conn)?)
-        })
+pub async fn del(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    claims: web::ReqData<Claims>,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    let owner = serv::rbac::owner_scope(db_pool.clone(), &redis_pool, &claims, perm::BOOK_DELETE.0)
         .await
-        .map_err(ej)?
-        .map_err(ej)?)
-    } else {
-        res_ok!(web::block(move || -> R<_> {
-            let mut conn = db_pool.get()?;
-            let (limit, offset) = get_limit_offset!(&query);
-            let data = books::table
-                .limit(limit)
-                .offset(offset)
-                .load::<models::book::Book>(&mut conn)?;
-            let total: i64 = books::table.count().get_result(&mut conn)?;
-
-            Ok((data, total))
-        })
+        .map_err(ej)?;
+    res_ok!(Book::del(db_pool, pk.into_inner(), owner)
         .await
-        .map_err(ej)?
         .map_err(ej)?)
-    }
+}
+
+/// 分页列表，见 [crate::utils::page]
+#[http_get("")]
+pub async fn list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(Book::page(db_pool, page).await.map_err(ej)?)
 }
 
 /// 正向链表查询
@@ -89,42 +99,24 @@ pub async fn list_all(db_pool: web::Data<DbPool>) -> HttpResult {
     res_ok!(res)
 }
 
-/// 购买
+/// 购买，创建一本书的订单，见 [crate::serv::order]
 #[http_post("/pay")]
-pub async fn pay(db_pool: web::Data<DbPool>, form: HttpBody<BookPay>) -> HttpResult {
+pub async fn pay(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    form: HttpBody<OrderItemNew>,
+) -> HttpResult {
     let form = form.into_inner();
+    form.validate().map_err(OkErr::ev)?;
 
-    let res = web::block(move || -> Result<_, String> {
-        let mut conn = db_pool.get().map_err(es)?;
-        let rows = diesel::update(
-            books::table.filter(books::id.eq(form.book_id).and(books::stock.ge(form.num))),
-        )
-        .set(books::stock.eq(books::stock - form.num))
-        .execute(&mut conn)
-        .map_err(es)?;
-
-        if rows == 0 {
-            Err("购买失败".into())
-        } else {
-            Ok("购买成功")
-        }
-    })
-    .await?
-    .map_err(ej)?;
-
-    res_ok!(res)
-}
-
-#[derive(Debug, Deserialize)]
-pub struct ListRequest {
-    /// page，从1开始
-    pub page: Option<i64>,
-    /// limit 需要多少条数据
-    pub limit: Option<i64>,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct BookPay {
-    pub book_id: PK,
-    pub num: u32,
+    res_ok!(serv::order::place(
+        db_pool,
+        &redis_pool,
+        user.id,
+        vec![form],
+        OrderSource::default()
+    )
+    .await
+    .map_err(ej)?)
 }
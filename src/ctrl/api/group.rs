@@ -0,0 +1,191 @@
+use crate::{
+    models::{
+        admin_log::ActionFlag,
+        auth_group::{Group, GroupNew, GroupUpdate},
+        auth_permission::Permission,
+    },
+    perm,
+    prelude::*,
+};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(obj)
+        .service(create)
+        .service(update)
+        .service(del)
+        .service(grant)
+        .service(revoke);
+}
+
+/// 列表
+#[http_get("list", wrap = "perm::GROUP_LIST")]
+pub async fn list(db_pool: web::Data<DbPool>) -> HttpResult {
+    res_ok!(Group::list(db_pool).await.map_err(ej)?)
+}
+
+/// 使用id获取组和组的权限
+#[http_get("get/{pk}", wrap = "perm::GROUP_VIEW")]
+pub async fn obj(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
+    let pk = pk.into_inner();
+    let res = ser::rbac::GroupDetail {
+        group: Group::obj(db_pool.clone(), pk).await.map_err(ej)?,
+        permissions: Permission::of_group(db_pool, pk).await.map_err(ej)?,
+    };
+    res_ok!(res)
+}
+
+/// 创建
+#[http_post("create", wrap = "perm::GROUP_CREATE")]
+pub async fn create(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
+    data: HttpBody<GroupNew>,
+) -> HttpResult {
+    let data = data.into_inner();
+    data.validate().map_err(OkErr::ev)?;
+    let new_id = Group::create(db_pool.clone(), data).await.map_err(ej)?;
+
+    let after = Group::obj(db_pool.clone(), new_id).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::ADD,
+        "group.create",
+        "auth_group",
+        Some(new_id),
+    );
+    log.desc = format!("创建组 {}", after.name);
+    log.changes = serv::audit::diff(None, Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(new_id)
+}
+
+/// 更新
+#[http_post("update/{pk}", wrap = "perm::GROUP_UPDATE")]
+pub async fn update(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    data: HttpBody<GroupUpdate>,
+) -> HttpResult {
+    let data = data.into_inner();
+    data.validate().map_err(OkErr::ev)?;
+    let pk = pk.into_inner();
+    let before = Group::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = Group::update(db_pool.clone(), pk, data).await.map_err(ej)?;
+
+    let after = Group::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "group.update",
+        "auth_group",
+        Some(pk),
+    );
+    log.desc = format!("修改组 {}", after.name);
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 批量删除
+#[http_post("del", wrap = "perm::GROUP_DELETE")]
+pub async fn del(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let before = Group::list(db_pool.clone())
+        .await
+        .map_err(ej)?
+        .into_iter()
+        .filter(|x| data.ids.contains(&x.id))
+        .collect::<Vec<_>>();
+    let rows = serv::rbac::del_groups(db_pool.clone(), &redis_pool, data.ids)
+        .await
+        .map_err(ej)?;
+
+    let actor = serv::audit::Actor::new(&admin, &req);
+    let logs = before
+        .iter()
+        .map(|x| {
+            let mut log = actor.entry(ActionFlag::DEL, "group.del", "auth_group", Some(x.id));
+            log.desc = format!("删除组 {}", x.name);
+            log.changes = serv::audit::diff(Some(x), None);
+            log
+        })
+        .collect();
+    serv::audit::record(db_pool, logs).await;
+    res_ok!(rows)
+}
+
+/// 给组分配权限
+#[http_post("grant/{pk}", wrap = "perm::GROUP_GRANT")]
+pub async fn grant(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows =
+        serv::rbac::grant_group_permissions(db_pool.clone(), &redis_pool, &admin, pk, data.ids)
+            .await
+            .map_err(ej)?;
+
+    let after = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "group.grant",
+        "auth_group",
+        Some(pk),
+    );
+    log.desc = "给组分配权限".to_owned();
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 取消组的权限
+#[http_post("revoke/{pk}", wrap = "perm::GROUP_GRANT")]
+pub async fn revoke(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::rbac::revoke_group_permissions(db_pool.clone(), &redis_pool, pk, data.ids)
+        .await
+        .map_err(ej)?;
+
+    let after = permission_names(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "group.revoke",
+        "auth_group",
+        Some(pk),
+    );
+    log.desc = "取消组的权限".to_owned();
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 组的权限名，记录分配前后的差异
+async fn permission_names(db_pool: web::Data<DbPool>, pk: PK) -> R<serde_json::Value> {
+    let names = Permission::of_group(db_pool, pk)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect::<Vec<_>>();
+    Ok(json!({ "permissions": names }))
+}
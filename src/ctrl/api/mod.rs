@@ -2,9 +2,15 @@ use crate::middleware::JWTAuth;
 use actix_web::web;
 
 pub mod admin;
+pub mod admin_log;
 pub mod auth;
 pub mod book;
+pub mod group;
+pub mod order;
+pub mod permission;
+pub mod post;
 pub mod redis;
+pub mod spike;
 pub mod upload;
 pub mod user;
 
@@ -22,18 +28,47 @@ mod ali;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/auth").configure(auth::config));
-    cfg.service(web::scope("/user").wrap(JWTAuth).configure(user::config));
+    cfg.service(web::scope("/user").wrap(JWTAuth::user()).configure(user::config));
     cfg.service(
         web::scope("/upload")
-            .wrap(JWTAuth)
+            .wrap(JWTAuth::any())
             .configure(upload::config),
     );
-    cfg.service(web::scope("/book").configure(book::config));
+    cfg.service(
+        web::scope("/book")
+            .configure(book::public_config)
+            .service(web::scope("").wrap(JWTAuth::any()).configure(book::config)),
+    );
+    cfg.service(
+        web::scope("/orders")
+            .configure(order::public_config)
+            .service(web::scope("").wrap(JWTAuth::user()).configure(order::config)),
+    );
+    cfg.service(
+        web::scope("/spike")
+            .configure(spike::public_config)
+            .service(web::scope("").wrap(JWTAuth::user()).configure(spike::config)),
+    );
+    cfg.service(
+        web::scope("/posts")
+            .configure(post::public_config)
+            .service(web::scope("").wrap(JWTAuth::any()).configure(post::config)),
+    );
 
     // redis 示例代码
     cfg.service(web::scope("/redis").configure(redis::config));
 
-    cfg.service(web::scope("/admin").wrap(JWTAuth).configure(admin::config));
+    cfg.service(
+        web::scope("/admin")
+            .wrap(JWTAuth::admin())
+            .configure(admin::config)
+            .service(web::scope("/logs").configure(admin_log::config))
+            .service(web::scope("/users").configure(user::admin_config))
+            .service(web::scope("/orders").configure(order::admin_config))
+            .service(web::scope("/spike").configure(spike::admin_config))
+            .service(web::scope("/groups").configure(group::config))
+            .service(web::scope("/permissions").configure(permission::config)),
+    );
 
     #[cfg(feature = "wx")]
     cfg.service(web::scope("/wx").configure(wx::config));
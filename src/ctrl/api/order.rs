@@ -0,0 +1,206 @@
+use crate::{
+    models::{
+        admin_log::ActionFlag,
+        order::{Order, OrderItemNew, OrderSource},
+    },
+    perm,
+    prelude::*,
+};
+
+/// 不需要登陆
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(notify);
+}
+
+/// 用户登陆后
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(create)
+        .service(list)
+        .service(retrieve)
+        .service(cancel);
+
+    #[cfg(feature = "alipay")]
+    cfg.service(pay_alipay);
+}
+
+/// 管理员接口，在 /api/admin/orders 下
+pub fn admin_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(admin_list)
+        .service(admin_retrieve)
+        .service(refund);
+}
+
+#[derive(Validate, Deserialize, Debug)]
+pub struct OrderNew {
+    #[validate]
+    pub items: Vec<OrderItemNew>,
+}
+
+/// 下单，超过 ORDER_PAY_TIMEOUT 秒未支付自动取消
+#[http_post("")]
+pub async fn create(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    form: HttpBody<OrderNew>,
+) -> HttpResult {
+    let form = form.into_inner();
+    form.validate().map_err(OkErr::ev)?;
+
+    res_ok!(serv::order::place(
+        db_pool,
+        &redis_pool,
+        user.id,
+        form.items,
+        OrderSource::default()
+    )
+    .await
+    .map_err(ej)?)
+}
+
+/// 当前用户的订单，见 [crate::utils::page]
+#[http_get("")]
+pub async fn list(db_pool: web::Data<DbPool>, user: AuthUser, page: PageQuery) -> HttpResult {
+    res_page!(Order::page(db_pool, page, Some(user.id))
+        .await
+        .map_err(ej)?)
+}
+
+#[http_get("{order_no}")]
+pub async fn retrieve(
+    db_pool: web::Data<DbPool>,
+    user: AuthUser,
+    order_no: web::Path<String>,
+) -> HttpResult {
+    let order = own_order(db_pool.clone(), &user, order_no.into_inner()).await?;
+    res_ok!(Order::detail(db_pool, order.id).await.map_err(ej)?)
+}
+
+/// 取消待支付的订单
+#[http_post("{order_no}/cancel")]
+pub async fn cancel(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    order_no: web::Path<String>,
+) -> HttpResult {
+    let order_no = order_no.into_inner();
+    if !serv::order::cancel(db_pool, &redis_pool, order_no.clone(), Some(user.id))
+        .await
+        .map_err(ej)?
+    {
+        return Err(ErrKind::BadRequest
+            .err("order.invalid_status")
+            .arg("order_no", order_no)
+            .actix());
+    }
+    res_ok!()
+}
+
+/// 支付宝网页支付，返回自动提交的表单
+#[cfg(feature = "alipay")]
+#[http_post("{order_no}/alipay")]
+pub async fn pay_alipay(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    client: web::Data<awc::Client>,
+    user: AuthUser,
+    order_no: web::Path<String>,
+) -> HttpResult {
+    let order = own_order(db_pool, &user, order_no.into_inner()).await?;
+    if order.status != models::order::OrderStatus::Created as u8 {
+        return Err(ErrKind::BadRequest
+            .err("order.invalid_status")
+            .arg("order_no", order.order_no)
+            .actix());
+    }
+
+    let biz_content = json!({
+        "out_trade_no": order.order_no,
+        "total_amount": order.amount,
+        "subject": format!("订单 {}", order.order_no),
+        "product_code": "FAST_INSTANT_TRADE_PAY",
+        "time_expire": order.expire_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+    });
+    let form_html_str = alipay::trade_page_pay(&redis_pool, &client, &biz_content)
+        .await
+        .map_err(ej)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(actix_web::http::header::ContentType::html())
+        .body(form_html_str))
+}
+
+/// 支付平台的异步通知，见 [serv::order::PayNotify]
+#[http_post("notify/{channel}")]
+pub async fn notify(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    channel: web::Path<String>,
+    body: web::Bytes,
+) -> HttpResult {
+    let channel = channel.into_inner();
+    let notifier = match serv::order::notifier(&channel) {
+        Some(x) => x,
+        None => return res_err!(NotFound, "common.not_found"),
+    };
+
+    let res = match notifier.verify(&req, &body) {
+        Ok(Some(payment)) => serv::order::complete(db_pool, payment).await,
+        Ok(None) => Ok(()),
+        Err(err) => Err(err),
+    };
+    if let Err(ref err) = res {
+        log::error!("支付通知处理失败: {} {:?}", channel, err);
+    }
+    Ok(notifier.reply(res.is_ok()))
+}
+
+/// 所有用户的订单
+#[http_get("", wrap = "perm::ORDER_LIST")]
+pub async fn admin_list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(Order::page(db_pool, page, None).await.map_err(ej)?)
+}
+
+#[http_get("{pk}", wrap = "perm::ORDER_VIEW")]
+pub async fn admin_retrieve(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
+    res_ok!(Order::detail(db_pool, pk.into_inner()).await.map_err(ej)?)
+}
+
+/// 标记为已退款并归还库存，需要先在支付平台退款
+#[http_post("{pk}/refund", wrap = "perm::ORDER_REFUND")]
+pub async fn refund(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let order = serv::order::refund(db_pool.clone(), &redis_pool, pk)
+        .await
+        .map_err(ej)?;
+
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "order.refund",
+        "order",
+        Some(pk),
+    );
+    log.desc = format!("订单退款 {} {}", order.order_no, order.amount);
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(order)
+}
+
+/// 当前用户的订单，别人的订单返回 NotFound
+async fn own_order(
+    db_pool: web::Data<DbPool>,
+    user: &AuthUser,
+    order_no: String,
+) -> Result<Order, OkErr> {
+    let order = Order::obj_by_no(db_pool, order_no).await.map_err(ej)?;
+    if order.user_id != user.id {
+        return Err(ErrKind::NotFound.err("common.not_found"));
+    }
+    Ok(order)
+}
@@ -0,0 +1,126 @@
+use crate::{
+    models::{
+        admin_log::ActionFlag,
+        auth_permission::{Permission, PermissionNew, PermissionUpdate},
+    },
+    perm,
+    prelude::*,
+};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(obj)
+        .service(create)
+        .service(update)
+        .service(del);
+}
+
+/// 列表
+#[http_get("list", wrap = "perm::PERMISSION_LIST")]
+pub async fn list(db_pool: web::Data<DbPool>) -> HttpResult {
+    res_ok!(Permission::list(db_pool).await.map_err(ej)?)
+}
+
+/// 使用id获取信息
+#[http_get("get/{pk}", wrap = "perm::PERMISSION_VIEW")]
+pub async fn obj(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
+    res_ok!(Permission::obj(db_pool, pk.into_inner())
+        .await
+        .map_err(ej)?)
+}
+
+/// 创建
+#[http_post("create", wrap = "perm::PERMISSION_CREATE")]
+pub async fn create(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    admin: AuthAdmin,
+    data: HttpBody<PermissionNew>,
+) -> HttpResult {
+    let data = data.into_inner();
+    data.validate().map_err(OkErr::ev)?;
+    let new_id = Permission::create(db_pool.clone(), data)
+        .await
+        .map_err(ej)?;
+
+    let after = Permission::obj(db_pool.clone(), new_id).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::ADD,
+        "permission.create",
+        "auth_permission",
+        Some(new_id),
+    );
+    log.desc = format!("创建权限 {}", after.name);
+    log.changes = serv::audit::diff(None, Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(new_id)
+}
+
+/// 更新
+#[http_post("update/{pk}", wrap = "perm::PERMISSION_UPDATE")]
+pub async fn update(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    data: HttpBody<PermissionUpdate>,
+) -> HttpResult {
+    let data = data.into_inner();
+    data.validate().map_err(OkErr::ev)?;
+    let pk = pk.into_inner();
+    let before = Permission::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::rbac::update_permission(db_pool.clone(), &redis_pool, pk, data)
+        .await
+        .map_err(ej)?;
+
+    let after = Permission::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "permission.update",
+        "auth_permission",
+        Some(pk),
+    );
+    log.desc = format!("修改权限 {}", after.name);
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 批量删除
+#[http_post("del", wrap = "perm::PERMISSION_DELETE")]
+pub async fn del(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    web::Json(data): web::Json<ser::rbac::Ids>,
+) -> HttpResult {
+    let before = Permission::list(db_pool.clone())
+        .await
+        .map_err(ej)?
+        .into_iter()
+        .filter(|x| data.ids.contains(&x.id))
+        .collect::<Vec<_>>();
+    let rows = serv::rbac::del_permissions(db_pool.clone(), &redis_pool, data.ids)
+        .await
+        .map_err(ej)?;
+
+    let actor = serv::audit::Actor::new(&admin, &req);
+    let logs = before
+        .iter()
+        .map(|x| {
+            let mut log = actor.entry(
+                ActionFlag::DEL,
+                "permission.del",
+                "auth_permission",
+                Some(x.id),
+            );
+            log.desc = format!("删除权限 {}", x.name);
+            log.changes = serv::audit::diff(Some(x), None);
+            log
+        })
+        .collect();
+    serv::audit::record(db_pool, logs).await;
+    res_ok!(rows)
+}
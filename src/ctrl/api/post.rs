@@ -0,0 +1,80 @@
+use crate::{
+    models::post::{Post, PostNew, PostUpdate},
+    prelude::*,
+};
+
+/// 不需要登陆
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list).service(retrieve);
+}
+
+/// 用户或管理员登陆后
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(create).service(update).service(del);
+}
+
+/// 分页列表，包含作者，`q` 全文搜索标题和内容，见 [crate::utils::page]
+#[http_get("")]
+pub async fn list(db_pool: web::Data<DbPool>, mut page: PageQuery) -> HttpResult {
+    let search = page.take_param("q");
+    res_page!(Post::page(db_pool, page, search).await.map_err(ej)?)
+}
+
+#[http_get("{pk}")]
+pub async fn retrieve(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
+    res_ok!(Post::obj_with_author(db_pool, pk.into_inner())
+        .await
+        .map_err(ej)?)
+}
+
+/// 当前用户发帖
+#[http_post("")]
+pub async fn create(
+    db_pool: web::Data<DbPool>,
+    user: AuthUser,
+    form: HttpBody<PostNew>,
+) -> HttpResult {
+    let mut form = form.into_inner();
+    form.validate().map_err(OkErr::ev)?;
+    form.user_id = user.id;
+
+    res_ok!(Post::create(db_pool, form).await.map_err(ej)?)
+}
+
+/// 作者或者有 post.update 权限的管理员
+#[http_post("{pk}/update")]
+pub async fn update(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    claims: web::ReqData<Claims>,
+    pk: web::Path<PK>,
+    form: HttpBody<PostUpdate>,
+) -> HttpResult {
+    let form = form.into_inner();
+    form.validate().map_err(OkErr::ev)?;
+
+    let ip = serv::guard::client_ip(&req).unwrap_or_default();
+    res_ok!(
+        serv::post::update(db_pool, &redis_pool, &claims, ip, pk.into_inner(), form)
+            .await
+            .map_err(ej)?
+    )
+}
+
+/// 作者或者有 post.delete 权限的管理员
+#[http_post("{pk}/del")]
+pub async fn del(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    claims: web::ReqData<Claims>,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    let ip = serv::guard::client_ip(&req).unwrap_or_default();
+    res_ok!(
+        serv::post::del(db_pool, &redis_pool, &claims, ip, pk.into_inner())
+            .await
+            .map_err(ej)?
+    )
+}
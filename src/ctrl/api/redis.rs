@@ -3,6 +3,7 @@
  *
  * 更新缓存：数据变动时，先操作数据库，然后在删除缓存(手动更新缓存工作量太大)，在下次查询时更新缓存
  *
+ * 模型按 pk 缓存使用 [crate::utils::cache]，这里只是示例
  */
 use crate::prelude::*;
 use schema::*;
@@ -13,9 +14,8 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(pipe)
         .service(hash)
         .service(cache_list)
-        .service(test)
-        // .service(xwxpay)
-        .service(spike);
+        .service(test);
+    // .service(xwxpay)
 }
 
 #[http_get("/test")]
@@ -204,50 +204,3 @@ pub async fn cache_list(redis_pool: web::Data<RedisPool>) -> HttpResult {
         .map_err(ej)?;
     res_ok!(x)
 }
-
-#[derive(Debug)]
-struct SpikeProduce {
-    begin_time: chrono::DateTime<Local>,
-    end_time: chrono::DateTime<Local>,
-    count: u64,
-}
-
-/// 秒杀
-/// 1. 库存限量
-/// 2. 指定时间内才能购买
-#[http_get("/spike")]
-pub async fn spike(redis_pool: web::Data<RedisPool>) -> HttpResult {
-    let _id = 1; // 秒杀商品id
-    let mut con = redis_pool.get().await.map_err(ej)?;
-
-    // 1. 查询商品
-    let mut p = SpikeProduce {
-        begin_time: Local
-            .datetime_from_str("2012-10-01 12:00:00", "%Y-%m-%d %H:%M:%S")
-            .map_err(ej)?,
-        end_time: Local
-            .datetime_from_str("2022-10-07 12:00:00", "%Y-%m-%d %H:%M:%S")
-            .map_err(ej)?,
-        count: 100,
-    };
-    log::info!("{:?}", p);
-    // 2. 秒杀时间是否开始，是否结束
-    let now = Local::now();
-    if now < p.begin_time {
-        return res_err!("尚未开始");
-    }
-    if now > p.end_time {
-        return res_err!("已经结束");
-    }
-
-    // 3. 是否还有库存
-    if p.count < 1 {
-        return res_err!("库存不足");
-    }
-    // 4. 减少库存
-    p.count -= 1;
-    // 5. 创建订单，向数据库订单表写入订单数据
-    let order = get_order_id!(con, "spike");
-    // 6. 返回订单信息
-    res_ok!(order)
-}
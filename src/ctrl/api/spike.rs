@@ -0,0 +1,131 @@
+use crate::{
+    models::{
+        admin_log::ActionFlag,
+        book::Book,
+        spike_event::{SpikeEvent, SpikeEventNew},
+    },
+    perm,
+    prelude::*,
+    serv::spike::Grab,
+};
+
+/// 不需要登陆
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list);
+}
+
+/// 用户登陆后
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(grab).service(ticket);
+}
+
+/// 管理员接口，在 /api/admin/spike 下
+pub fn admin_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(admin_list).service(create).service(del);
+}
+
+/// 未结束的活动
+#[http_get("")]
+pub async fn list(db_pool: web::Data<DbPool>) -> HttpResult {
+    res_ok!(SpikeEvent::active(db_pool).await.map_err(ej)?)
+}
+
+/// 抢购，成功后排队创建订单，使用 tickets/{request_id} 查询结果
+#[http_post("{pk}")]
+pub async fn grab(
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    pk: web::Path<PK>,
+    form: HttpBody<Grab>,
+) -> HttpResult {
+    let form = form.into_inner();
+    form.validate().map_err(OkErr::ev)?;
+
+    res_ok!(
+        serv::spike::grab(&redis_pool, user.id, pk.into_inner(), form)
+            .await
+            .map_err(ej)?
+    )
+}
+
+/// 抢购结果，排队中、已生成订单或失败
+#[http_get("tickets/{request_id}")]
+pub async fn ticket(
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    request_id: web::Path<String>,
+) -> HttpResult {
+    res_ok!(serv::spike::ticket(&redis_pool, user.id, &request_id)
+        .await
+        .map_err(ej)?)
+}
+
+/// 所有活动，见 [crate::utils::page]
+#[http_get("", wrap = "perm::SPIKE_LIST")]
+pub async fn admin_list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(SpikeEvent::page(db_pool, page).await.map_err(ej)?)
+}
+
+/// 创建活动并预热到 redis
+#[http_post("", wrap = "perm::SPIKE_CREATE")]
+pub async fn create(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    form: HttpBody<SpikeEventNew>,
+) -> HttpResult {
+    let form = form.into_inner();
+    form.validate().map_err(OkErr::ev)?;
+    if form.begin_at >= form.end_at {
+        return Err(ErrKind::BadRequest
+            .err("validation.invalid")
+            .arg("field", "end_at")
+            .actix());
+    }
+    Book::obj(db_pool.clone(), form.book_id).await.map_err(ej)?;
+
+    let event = SpikeEvent::create(db_pool.clone(), form)
+        .await
+        .map_err(ej)?;
+    serv::spike::preload(&redis_pool, &event)
+        .await
+        .map_err(ej)?;
+
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::ADD,
+        "spike.create",
+        "spike_event",
+        Some(event.id),
+    );
+    log.changes = serv::audit::diff(None, Some(&event));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(event)
+}
+
+/// 删除活动，已经生成的订单不受影响
+#[http_post("{pk}/del", wrap = "perm::SPIKE_DELETE")]
+pub async fn del(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = SpikeEvent::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::spike::del(db_pool.clone(), &redis_pool, pk)
+        .await
+        .map_err(ej)?;
+
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::DEL,
+        "spike.del",
+        "spike_event",
+        Some(pk),
+    );
+    log.desc = format!("删除秒杀活动 {}", before.title);
+    log.changes = serv::audit::diff(Some(&before), None);
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
@@ -107,17 +107,17 @@ pub async fn upload_local(mut payload: Multipart, redis_pool: web::Data<RedisPoo
             };
 
             // 返回的时候拼接域名
-            let media_uri = Url::parse(env!("SITE_NAME"))
+            let media_uri = Url::parse(&conf!().site_name)
                 .unwrap()
                 .join(&file_path)
                 .unwrap()
                 .to_string();
-                
+
             return res_ok!(media_uri);
         }
     }
 
-    res_err!("上传失败")
+    res_err!(Upstream, "upload.failed")
 }
 
 /// 上传到七牛云
@@ -126,9 +126,9 @@ pub async fn upload_local(mut payload: Multipart, redis_pool: web::Data<RedisPoo
 pub async fn upload_qiniu(mut payload: Multipart, redis_pool: web::Data<RedisPool>) -> HttpResult {
     use std::time::Duration;
 
-    let access_key = env!("QN_ACCESS_KEY");
-    let secret_key = env!("QN_SECRET_KEY");
-    let bucket_name = env!("QN_BUCKET_NAME");
+    let access_key = conf!().qiniu.access_key.as_str();
+    let secret_key = conf!().qiniu.secret_key.as_str();
+    let bucket_name = conf!().qiniu.bucket_name.as_str();
 
     while let Some(mut field) = payload.try_next().await? {
         if field.name() == "file" {
@@ -221,7 +221,11 @@ pub async fn upload_qiniu(mut payload: Multipart, redis_pool: web::Data<RedisPoo
 
                     // 检查一下 etag 是否计算正确
                     if etag != res.hash {
-                        return res_err!(format!("ETag 计算错误，预计{}，实际{}", etag, res.hash));
+                        return Err(ErrKind::Upstream
+                            .err("upload.etag_mismatch")
+                            .arg("expected", etag)
+                            .arg("actual", res.hash)
+                            .actix());
                     }
 
                     // 将结果缓存为json字符串
@@ -235,5 +239,5 @@ pub async fn upload_qiniu(mut payload: Multipart, redis_pool: web::Data<RedisPoo
         }
     }
 
-    res_err!("上传失败")
+    res_err!(Upstream, "upload.failed")
 }
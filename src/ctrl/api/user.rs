@@ -1,145 +1,54 @@
 use crate::{
     models::{
+        admin_log::ActionFlag,
         auth_group::Group,
         auth_permission::Permission,
         book::Book,
         m2m_admin_group::AdminGroup,
         m2m_admin_permission::AdminPerm,
         post::Post,
-        user::{UpdateUser, User},
+        user::{ManageUser, UpdateUser, User},
     },
+    perm,
     prelude::*,
-    ser::user::Paging,
 };
 
-use schema::*;
-
-pub fn config(cfg: &mut web::ServiceConfig) {
+/// 管理员接口，在 /api/admin/users 下
+pub fn admin_config(cfg: &mut web::ServiceConfig) {
     cfg.service(list)
         .service(sub_account_list)
         .service(list_all)
-        .service(current_user)
-        .service(change_pwd)
         .service(del_many)
-        .service(update_user);
-
-    cfg.service(
-        web::scope("/{pk}")
-            .route(
-                "",
-                web::get().to(|db_pool: web::Data<DbPool>, pk: web::Path<PK>| async {
-                    res_ok!(User::obj(db_pool, pk.into_inner()).await.map_err(ej)?)
-                }),
-            )
-            .route(
-                "del",
-                web::post().to(|db_pool: web::Data<DbPool>, pk: web::Path<PK>| async {
-                    let rows = User::del(db_pool, pk.into_inner()).await.map_err(ej)?;
-                    res_ok!(rows)
-                }),
-            )
-            .route(
-                "update",
-                web::post().to(
-                    |db_pool: web::Data<DbPool>,
-                     pk: web::Path<PK>,
-                     web::Json(data): web::Json<UpdateUser>| async {
-                        data.validate().map_err(OkErr::ev)?;
-                        let rows = serv::user::update_user(db_pool, pk.into_inner(), data)
-                            .await
-                            .map_err(ej)?;
-                        res_ok!(rows)
-                    },
-                ),
-            ),
-    );
+        .service(obj)
+        .service(del)
+        .service(update);
 }
 
-#[http_get("current_user")]
-pub async fn current_user(
-    db_pool: web::Data<DbPool>,
-    claims: Option<web::ReqData<Claims>>,
-) -> HttpResult {
-    let claims = claims.ok_or("请求错误").map_err(ej)?;
-    let user = user!(db_pool, claims.id);
-    res_ok!(user)
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(current_user)
+        .service(change_pwd)
+        .service(update_user)
+        .service(set_locale);
 }
 
-#[http_get("list")]
-pub async fn list(
-    data: web::Query<ser::user::UserListQuery>,
-    db_pool: web::Data<DbPool>,
-) -> HttpResult {
-    let data = data.into_inner();
-
-    if data.has_page() {
-        let res = web::block(move || -> R<_> {
-            let mut conn = db_pool.get()?;
-            let (limit, offset) = get_limit_offset!(&data);
-
-            // let mut q = users::table.into_boxed();
-            let mut q = users::table.limit(limit).offset(offset).into_boxed();
-
-            if let Some(email) = data.user.email {
-                if !email.is_empty() {
-                    q = q.filter(users::email.like(format!("%{}%", email)));
-                }
-            }
-
-            let list = q.load::<User>(&mut conn)?;
-            let total: i64 = users::table.count().get_result(&mut conn)?;
-
-            Ok((list, total))
-        })
-        .await
-        .map_err(ej)?
-        .map_err(ej)?;
-
-        res_ok!(res.0, res.1)
-    } else {
-        res_ok!(User::list(db_pool).await.map_err(ej)?)
-    }
+#[http_get("current_user")]
+pub async fn current_user(user: AuthUser) -> HttpResult {
+    res_ok!(user.user)
 }
 
-#[http_get("sub_account_list")]
-pub async fn sub_account_list(
-    data: web::Query<ser::user::UserListQuery>,
-    db_pool: web::Data<DbPool>,
-) -> HttpResult {
-    let data = data.into_inner();
-
-    if data.has_page() {
-        let res = web::block(move || -> R<_> {
-            let mut conn = db_pool.get()?;
-            let (limit, offset) = get_limit_offset!(&data);
-
-            // let mut q = users::table.into_boxed();
-            let mut q = users::table.limit(limit).offset(offset).into_boxed();
-
-            if let Some(email) = data.user.email {
-                if !email.is_empty() {
-                    q = q.filter(users::email.like(format!("%{}%", email)));
-                }
-            }
-
-            // q = q.filter(users::is_staff.eq(true).and(users::is_superadmin.eq(false)));
-            let list_data: Vec<User> = q.load::<User>(&mut conn)?;
-            let total: i64 = users::table.count().get_result(&mut conn)?;
-
-            Ok((list_data, total))
-        })
-        .await
-        .map_err(ej)?
-        .map_err(ej)?;
+/// 分页列表，见 [crate::utils::page]
+#[http_get("list", wrap = "perm::USER_LIST")]
+pub async fn list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(User::page(db_pool, page).await.map_err(ej)?)
+}
 
-        res_ok!(res.0, res.1)
-    } else {
-        res_ok!(User::list(db_pool).await.map_err(ej)?)
-    }
+#[http_get("sub_account_list", wrap = "perm::USER_LIST")]
+pub async fn sub_account_list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+    res_page!(User::page(db_pool, page).await.map_err(ej)?)
 }
 
 /// 反向查询
-#[http_get("all")]
+#[http_get("all", wrap = "perm::USER_LIST")]
 pub async fn list_all(db_pool: web::Data<DbPool>) -> HttpResult {
     res_ok!()
     // let res = web::block(move || -> Result<_, anyhow::Error> {
@@ -184,12 +93,25 @@ pub async fn list_all(db_pool: web::Data<DbPool>) -> HttpResult {
 #[http_post("update")]
 pub async fn update_user(
     db_pool: web::Data<DbPool>,
-    claims: Option<web::ReqData<Claims>>,
+    user: AuthUser,
     web::Json(data): web::Json<UpdateUser>,
 ) -> HttpResult {
     data.validate().map_err(OkErr::ev)?;
-    let pk = claims.unwrap().id;
-    let rows = serv::user::update_user(db_pool, pk, data)
+    let rows = serv::user::update_user(db_pool, user.id, data)
+        .await
+        .map_err(ej)?;
+    res_ok!(rows)
+}
+
+/// 保存当前登录用户的语言，登陆后的请求和短信、邮件使用这个语言
+#[http_post("locale")]
+pub async fn set_locale(
+    db_pool: web::Data<DbPool>,
+    user: AuthUser,
+    web::Json(data): web::Json<ser::SetLocale>,
+) -> HttpResult {
+    data.validate().map_err(OkErr::ev)?;
+    let rows = User::set_locale(db_pool, user.id, data.tag())
         .await
         .map_err(ej)?;
     res_ok!(rows)
@@ -199,29 +121,134 @@ pub async fn update_user(
 #[http_post("change_pwd")]
 pub async fn change_pwd(
     db_pool: web::Data<DbPool>,
-    claims: Option<web::ReqData<Claims>>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
     web::Json(data): web::Json<ser::user::ChangePwd>,
 ) -> HttpResult {
     data.validate().map_err(OkErr::ev)?;
 
     if data.new_pwd != data.new_pwd2 {
-        return res_err!("两次密码不一样");
+        return res_err!("user.password_mismatch");
     }
 
-    let pk = claims.ok_or("请先登录").map_err(ej)?.id;
+    let pk = user.id;
 
     let rows = serv::user::change_pwd(db_pool, pk, data.pwd, data.new_pwd)
         .await
         .map_err(ej)?;
+
+    // 其他设备需要重新登陆
+    serv::auth::revoke_sessions(&redis_pool, Audience::User, pk)
+        .await
+        .map_err(ej)?;
     res_ok!(rows)
 }
 
-/// 批量删除
-#[http_post("del_many")]
+/// 管理员批量删除
+#[http_post("del_many", wrap = "perm::USER_DELETE")]
 pub async fn del_many(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
     web::Json(data): web::Json<ser::user::DelMany>,
 ) -> HttpResult {
-    let rows = User::del_many(db_pool, data.ids).await.map_err(ej)?;
+    let before = User::objs(db_pool.clone(), data.ids.clone())
+        .await
+        .map_err(ej)?;
+    let rows = User::del_many(db_pool.clone(), data.ids.clone())
+        .await
+        .map_err(ej)?;
+    for pk in data.ids {
+        serv::auth::revoke_sessions(&redis_pool, Audience::User, pk)
+            .await
+            .map_err(ej)?;
+    }
+
+    let actor = serv::audit::Actor::new(&admin, &req);
+    let logs = before
+        .iter()
+        .map(|x| {
+            let mut log = actor.entry(ActionFlag::DEL, "user.del_many", "user", Some(x.id));
+            log.desc = format!("删除用户 {}", display_name(x));
+            log.changes = serv::audit::diff(Some(x), None);
+            log
+        })
+        .collect();
+    serv::audit::record(db_pool, logs).await;
     res_ok!(rows)
 }
+
+/// 管理员使用id获取用户
+#[http_get("{pk}", wrap = "perm::USER_VIEW")]
+pub async fn obj(db_pool: web::Data<DbPool>, pk: web::Path<PK>) -> HttpResult {
+    res_ok!(User::obj(db_pool, pk.into_inner()).await.map_err(ej)?)
+}
+
+/// 管理员删除用户
+#[http_post("{pk}/del", wrap = "perm::USER_DELETE")]
+pub async fn del(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+) -> HttpResult {
+    let pk = pk.into_inner();
+    let before = User::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = User::del(db_pool.clone(), pk).await.map_err(ej)?;
+    serv::auth::revoke_sessions(&redis_pool, Audience::User, pk)
+        .await
+        .map_err(ej)?;
+
+    let mut log =
+        serv::audit::Actor::new(&admin, &req).entry(ActionFlag::DEL, "user.del", "user", Some(pk));
+    log.desc = format!("删除用户 {}", display_name(&before));
+    log.changes = serv::audit::diff(Some(&before), None);
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 管理员修改用户，禁用后该用户所有的 token 失效
+#[http_post("{pk}/update", wrap = "perm::USER_UPDATE")]
+pub async fn update(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    admin: AuthAdmin,
+    pk: web::Path<PK>,
+    web::Json(data): web::Json<ManageUser>,
+) -> HttpResult {
+    data.validate().map_err(OkErr::ev)?;
+    let pk = pk.into_inner();
+    let deactivated = data.is_active == Some(false);
+    let before = User::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let rows = serv::user::manage_user(db_pool.clone(), pk, data)
+        .await
+        .map_err(ej)?;
+    if deactivated {
+        serv::auth::revoke_sessions(&redis_pool, Audience::User, pk)
+            .await
+            .map_err(ej)?;
+    }
+
+    let after = User::obj(db_pool.clone(), pk).await.map_err(ej)?;
+    let mut log = serv::audit::Actor::new(&admin, &req).entry(
+        ActionFlag::UPDATE,
+        "user.update",
+        "user",
+        Some(pk),
+    );
+    log.desc = format!("修改用户 {}", display_name(&after));
+    log.changes = serv::audit::diff(Some(&before), Some(&after));
+    serv::audit::record(db_pool, vec![log]).await;
+    res_ok!(rows)
+}
+
+/// 日志中显示的用户名，手机号注册的用户没有用户名
+fn display_name(user: &User) -> &str {
+    user.username
+        .as_deref()
+        .or(user.phone.as_deref())
+        .unwrap_or_default()
+}
@@ -0,0 +1,82 @@
+use crate::{prelude::*, utils::worker::Workers};
+use std::time::Instant;
+
+/// 负载均衡使用的健康检查，挂载在根路径
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(healthz).service(readyz);
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    ok: bool,
+
+    /// 耗时毫秒
+    ms: u128,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result(r: R<()>, begin: Instant) -> Self {
+        Self {
+            ok: r.is_ok(),
+            ms: begin.elapsed().as_millis(),
+            error: r.err().map(es),
+        }
+    }
+}
+
+/// 进程存活
+#[http_get("/healthz")]
+pub async fn healthz() -> HttpResult {
+    Ok(HttpResponse::Ok().json(json!({ "status": "ok" })))
+}
+
+/// 依赖的 mysql、redis 和后台任务都正常时才返回 200，否则 503
+#[http_get("/readyz")]
+pub async fn readyz(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    workers: web::Data<Workers>,
+) -> HttpResult {
+    let begin = Instant::now();
+    let mysql = web::block(move || -> R<()> {
+        let mut conn = db_pool.get()?;
+        diesel::sql_query("SELECT 1").execute(&mut conn)?;
+        Ok(())
+    })
+    .await
+    .map_err(eany)
+    .and_then(|r| r);
+    let mysql = Check::from_result(mysql, begin);
+
+    let begin = Instant::now();
+    let redis = async {
+        let mut con = redis_pool.get().await?;
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut con)
+            .await?;
+        Ok(())
+    }
+    .await;
+    let redis = Check::from_result(redis, begin);
+
+    let workers = workers.status();
+    let ready = mysql.ok && redis.ok && workers.iter().all(|x| x.running);
+
+    let body = json!({
+        "status": if ready { "ok" } else { "unavailable" },
+        "checks": {
+            "mysql": mysql,
+            "redis": redis,
+            "workers": workers,
+        },
+    });
+
+    if ready {
+        Ok(HttpResponse::Ok().json(body))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(body))
+    }
+}
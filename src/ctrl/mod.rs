@@ -1,4 +1,6 @@
 pub mod api;
+pub mod health;
+pub mod well_known;
 
 #[cfg(feature = "template")]
 pub mod chat;
@@ -0,0 +1,14 @@
+use crate::{prelude::*, utils::jwt};
+
+/// 给其他服务使用的公开信息，挂载在根路径
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(jwks);
+}
+
+/// 验证 token 的公钥，HS256 时为空
+#[http_get("/.well-known/jwks.json")]
+pub async fn jwks() -> HttpResult {
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(jwt::jwks()))
+}
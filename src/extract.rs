@@ -0,0 +1,107 @@
+/**
+ * 登陆用户提取器
+ *
+ * 需要在 [crate::middleware::JWTAuth] 之后使用，从 jwt 的 Claims 加载管理员或用户
+ *
+ * ```
+ * #[http_get("current")]
+ * pub async fn current(admin: AuthAdmin) -> HttpResult {
+ *     res_ok!(admin.admin)
+ * }
+ * ```
+ */
+use crate::models::{admin::Admin, user::User};
+use crate::prelude::*;
+use actix_web::{dev::Payload, FromRequest, HttpMessage};
+use futures_util::future::LocalBoxFuture;
+use std::ops::Deref;
+
+/// 当前登陆的管理员
+#[derive(Debug)]
+pub struct AuthAdmin {
+    pub admin: Admin,
+    pub claims: Claims,
+}
+
+impl Deref for AuthAdmin {
+    type Target = Admin;
+
+    fn deref(&self) -> &Self::Target {
+        &self.admin
+    }
+}
+
+impl FromRequest for AuthAdmin {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let (claims, db_pool) = principal(&req, Audience::Admin)?;
+            let admin = Admin::obj(db_pool, claims.id).await.map_err(deleted)?;
+            if !admin.is_active {
+                return Err(ErrKind::Forbidden.err("auth.inactive").into());
+            }
+            Ok(Self { admin, claims })
+        })
+    }
+}
+
+/// 当前登陆的用户
+#[derive(Debug)]
+pub struct AuthUser {
+    pub user: User,
+    pub claims: Claims,
+}
+
+impl Deref for AuthUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let (claims, db_pool) = principal(&req, Audience::User)?;
+            let user = User::obj(db_pool, claims.id).await.map_err(deleted)?;
+            if !user.is_active {
+                return Err(ErrKind::Forbidden.err("auth.inactive").into());
+            }
+            Ok(Self { user, claims })
+        })
+    }
+}
+
+/// 取出中间件验证过的 Claims，aud 必须一致
+fn principal(req: &HttpRequest, aud: Audience) -> Result<(Claims, web::Data<DbPool>), OkErr> {
+    let claims = req
+        .extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| ErrKind::Auth.err("auth.required"))?;
+    if claims.aud != aud {
+        return Err(ErrKind::Forbidden.err("auth.forbidden"));
+    }
+    let db_pool = req
+        .app_data::<web::Data<DbPool>>()
+        .cloned()
+        .ok_or_else(|| ErrKind::Internal.err("DbPool 未注册"))?;
+    Ok((claims, db_pool))
+}
+
+/// token 有效但账号已被删除
+fn deleted(e: anyhow::Error) -> OkErr {
+    let e = ej(e);
+    match e.kind {
+        ErrKind::NotFound => ErrKind::Auth.err("auth.invalid_token"),
+        _ => e,
+    }
+}
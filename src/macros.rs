@@ -18,14 +18,12 @@ macro_rules! res_ok {
         })))
     };
 
-    // 分页查询
-    ( $data:expr, $total:expr/*,  $page:expr, $limit:expr*/ ) => {
+    // 带总数的列表，分页查询使用 res_page!
+    ( $data:expr, $total:expr ) => {
         Ok::<actix_web::HttpResponse, actix_web::Error>(actix_web::HttpResponse::Ok().json(serde_json::json!({
             "success": true,
             "data": $data,
             "total": $total,
-            // "current": $page,
-            // "pageSize": $limit,
         })))
     };
 
@@ -42,17 +40,112 @@ macro_rules! res_err {
     ( $err:expr ) => {
         Err(ej($err).actix())
     };
+
+    // res_err!(NotFound, "未找到")
+    ( $kind:ident, $err:expr ) => {
+        Err($crate::utils::error::ErrKind::$kind.err($err).actix())
+    };
 }
 
-/// 从$x中计算出分页数据
-/// 查询参数page从1开始，但是数据库从0开始
+/// 分页查询的结果 [crate::utils::page::Paginated]
+///
+/// keyset 分页时没有 total、current，有下一页时返回 cursor
 #[macro_export]
-macro_rules! get_limit_offset {
-    ( $x:expr ) => {{
-        let page = $x.page.unwrap();
-        let limit = $x.limit.unwrap();
-        let offset = (page - 1) * limit;
-        (limit, offset)
+macro_rules! res_page {
+    ( $page:expr ) => {{
+        let page: $crate::utils::page::Paginated<_> = $page;
+        Ok::<actix_web::HttpResponse, actix_web::Error>(actix_web::HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "data": page.data,
+            "total": page.total,
+            "current": page.current,
+            "pageSize": page.page_size,
+            "cursor": page.cursor,
+        })))
+    }};
+}
+
+/// 按白名单应用 [crate::utils::page::PageQuery] 的过滤条件，返回 R 的函数中使用
+///
+/// 字段没有声明或者不支持该操作符时返回 400
+///
+/// ```
+/// let q = page_filter!(admin::table.into_boxed(), page, {
+///     "username" => admin::username: String [Eq, Like],
+///     "is_active" => admin::is_active: bool [Eq],
+///     "last_login" => admin::last_login: NaiveDateTime [Range],
+/// });
+/// ```
+#[macro_export]
+macro_rules! page_filter {
+    ( $q:expr, $page:expr, { $( $name:literal => $col:path : $ty:ty [ $( $op:ident ),+ ] ),* $(,)? } ) => {{
+        let mut q = $q;
+        for f in $page.filters.iter() {
+            match f.field.as_str() {
+                $(
+                    $name => {
+                        #[allow(unreachable_patterns)]
+                        match f.op {
+                            $( $crate::utils::page::FilterOp::$op => {
+                                q = $crate::page_filter!(@$op q, $col, $ty, f);
+                            } )+
+                            _ => return Err(f.unsupported()),
+                        }
+                    }
+                )*
+                _ => return Err(f.unsupported()),
+            }
+        }
+        q
+    }};
+
+    ( @Eq $q:ident, $col:path, $ty:ty, $f:ident ) => {
+        $q.filter($col.eq($f.value::<$ty>()?))
+    };
+
+    ( @Like $q:ident, $col:path, $ty:ty, $f:ident ) => {
+        $q.filter($col.like($f.like()))
+    };
+
+    ( @In $q:ident, $col:path, $ty:ty, $f:ident ) => {
+        $q.filter($col.eq_any($f.values::<$ty>()?))
+    };
+
+    ( @Range $q:ident, $col:path, $ty:ty, $f:ident ) => {{
+        let (from, to) = $f.range::<$ty>()?;
+        let mut q = $q;
+        if let Some(v) = from {
+            q = q.filter($col.ge(v));
+        }
+        if let Some(v) = to {
+            q = q.filter($col.le(v));
+        }
+        q
+    }};
+}
+
+/// 按白名单应用 [crate::utils::page::PageQuery] 的排序，返回 R 的函数中使用
+///
+/// ```
+/// let q = page_sort!(q, page, {
+///     "id" => admin::id,
+///     "last_login" => admin::last_login,
+/// });
+/// ```
+#[macro_export]
+macro_rules! page_sort {
+    ( $q:expr, $page:expr, { $( $name:literal => $col:path ),* $(,)? } ) => {{
+        let mut q = $q;
+        for s in $page.sort.iter() {
+            q = match (s.field.as_str(), s.order) {
+                $(
+                    ($name, $crate::utils::page::Order::Asc) => q.then_order_by($col.asc()),
+                    ($name, $crate::utils::page::Order::Desc) => q.then_order_by($col.desc()),
+                )*
+                _ => return Err(s.unsupported()),
+            };
+        }
+        q
     }};
 }
 
@@ -64,14 +157,11 @@ macro_rules! dbg_sql {
     }};
 }
 
-/// 获取环境变量并解析类型，开发人员需要保证类型的正确性
+/// 获取运行时配置 [crate::config::AppConfig]
 #[macro_export]
-macro_rules! get_env {
-    ( $name:expr ) => {
-        dotenv!($name)
-    };
-    ( $name:expr, $t:tt ) => {
-        dotenv!($name).parse::<$t>().expect($name)
+macro_rules! conf {
+    () => {
+        $crate::config::conf()
     };
 }
 
@@ -97,6 +187,16 @@ macro_rules! user {
     }};
 }
 
+/// 生成 $len 字节的随机字符串，十六进制编码
+#[macro_export]
+macro_rules! random_token {
+    ( $len:expr ) => {{
+        let mut bytes = [0u8; $len];
+        thread_rng().fill(&mut bytes[..]);
+        hex::encode(bytes)
+    }};
+}
+
 /// 手机 4位数字 验证码
 #[macro_export]
 macro_rules! make_phone_code {
@@ -123,13 +223,11 @@ macro_rules! make_random_string {
     }};
 }
 
-/// 加密密码，返回加密后的密码
+/// 加密密码，返回加密后的密码，见 [crate::utils::password]
 #[macro_export]
 macro_rules! pwd_encode {
     ( $password:expr ) => {{
-        let secret = get_env!("PWD_SECRET");
-        let config = argon2::Config::default();
-        argon2::hash_encoded($password.as_ref(), secret.as_ref(), &config).unwrap()
+        $crate::utils::password::encode($password.as_ref())
     }};
 }
 
@@ -137,11 +235,7 @@ macro_rules! pwd_encode {
 #[macro_export]
 macro_rules! pwd_decode {
     ( $encode_pwd:expr, $password:expr ) => {{
-        let r = argon2::verify_encoded($encode_pwd, $password);
-        match r {
-            Ok(r) => r,
-            _ => false,
-        }
+        $crate::utils::password::verify($encode_pwd, $password)
     }};
 }
 
@@ -174,7 +268,7 @@ macro_rules! get_order_id {
         // 2. 生成序列号
         let key_prefix = $key_prefix; // 每个订单业务对应单独的key
         let date = &now.format("%Y:%m:%d").to_string(); // 每天都生成不同的key
-        let key = &format!("{}:order_no:{}:{}", get_env!("APP_NAME"), key_prefix, date);
+        let key = &format!("{}:order_no:{}:{}", conf!().app_name, key_prefix, date);
 
         let count: u64 = $con.incr(key, 1).await.map_err(ej)?;
         if count > 0xFF_FFFF {
@@ -291,13 +385,194 @@ macro_rules! sha1 {
     };
 }
 
-/// 手机验证码 key
+/// 手机验证码 key，不同用途的验证码不能混用
 ///
-/// ab:vc:手机号
+/// ab:vc:用途:手机号
 #[macro_export]
 macro_rules! rk_phone_vc {
-    ($phone:expr) => {
-        format!("{}:vc:{}", get_env!("APP_NAME"), $phone)
+    ($purpose:expr, $phone:expr) => {
+        format!("{}:vc:{}:{}", conf!().app_name, $purpose, $phone)
+    };
+}
+
+/// 同一个IP发送手机验证码的次数
+#[macro_export]
+macro_rules! rk_phone_vc_ip {
+    ($ip:expr) => {
+        format!("{}:vc_ip:{}", conf!().app_name, $ip)
+    };
+}
+
+/// 用户的 token 版本，见 [crate::utils::jwt::Claims::ver]
+#[macro_export]
+macro_rules! rk_jwt_ver {
+    ($aud:expr, $pk:expr) => {
+        format!("{}:jwt:ver:{}:{}", conf!().app_name, $aud.as_str(), $pk)
+    };
+}
+
+/// 已退出登陆的 access token
+#[macro_export]
+macro_rules! rk_jwt_deny {
+    ($jti:expr) => {
+        format!("{}:jwt:deny:{}", conf!().app_name, $jti)
+    };
+}
+
+/// refresh token
+#[macro_export]
+macro_rules! rk_jwt_refresh {
+    ($token:expr) => {
+        format!("{}:jwt:refresh:{}", conf!().app_name, $token)
+    };
+}
+
+/// 重置密码的 token，值为用户id
+#[macro_export]
+macro_rules! rk_pwd_reset {
+    ($token:expr) => {
+        format!("{}:pwd_reset:{}", conf!().app_name, $token)
+    };
+}
+
+/// 用户最新的重置密码 token，重新申请时旧的作废
+#[macro_export]
+macro_rules! rk_pwd_reset_user {
+    ($pk:expr) => {
+        format!("{}:pwd_reset:user:{}", conf!().app_name, $pk)
+    };
+}
+
+/// 重置邮件的发送间隔，邮箱不存在时也设置
+#[macro_export]
+macro_rules! rk_pwd_reset_cooldown {
+    ($email:expr) => {
+        format!("{}:pwd_reset:cooldown:{}", conf!().app_name, $email)
+    };
+}
+
+/// 同一个IP申请重置邮件的次数
+#[macro_export]
+macro_rules! rk_pwd_reset_ip {
+    ($ip:expr) => {
+        format!("{}:pwd_reset:ip:{}", conf!().app_name, $ip)
+    };
+}
+
+/// 密码正确、等待两步验证的管理员，值为管理员id
+#[macro_export]
+macro_rules! rk_mfa_pending {
+    ($token:expr) => {
+        format!("{}:mfa:pending:{}", conf!().app_name, $token)
+    };
+}
+
+/// mfa_token 已经绑定过的标记，同一次登陆只生成一次密钥
+#[macro_export]
+macro_rules! rk_mfa_enrolled {
+    ($token:expr) => {
+        format!("{}:mfa:enrolled:{}", conf!().app_name, $token)
+    };
+}
+
+/// 管理员最后使用的 TOTP 周期，同一个验证码不能重复使用
+#[macro_export]
+macro_rules! rk_totp_step {
+    ($pk:expr) => {
+        format!("{}:mfa:step:{}", conf!().app_name, $pk)
+    };
+}
+
+/// 权限缓存的版本，权限、组变化时加1，所有管理员的缓存失效
+#[macro_export]
+macro_rules! rk_perm_ver {
+    () => {
+        format!("{}:perm:ver", conf!().app_name)
+    };
+}
+
+/// 管理员的有效权限缓存，见 [crate::serv::rbac]
+#[macro_export]
+macro_rules! rk_admin_perms {
+    ($ver:expr, $pk:expr) => {
+        format!("{}:perm:{}:{}", conf!().app_name, $ver, $pk)
+    };
+}
+
+/// 登陆失败次数，见 [crate::serv::guard]
+#[macro_export]
+macro_rules! rk_login_fail {
+    ($scope:expr, $id:expr) => {
+        format!("{}:login:fail:{}:{}", conf!().app_name, $scope.as_str(), $id)
+    };
+}
+
+/// 账号锁定，过期后自动解锁
+#[macro_export]
+macro_rules! rk_login_lock {
+    ($scope:expr, $id:expr) => {
+        format!("{}:login:lock:{}:{}", conf!().app_name, $scope.as_str(), $id)
+    };
+}
+
+/// 账号被锁定的次数，每次锁定的时间翻倍
+#[macro_export]
+macro_rules! rk_login_level {
+    ($scope:expr, $id:expr) => {
+        format!("{}:login:level:{}:{}", conf!().app_name, $scope.as_str(), $id)
+    };
+}
+
+/// 同一个IP的登陆失败次数
+#[macro_export]
+macro_rules! rk_login_ip {
+    ($ip:expr) => {
+        format!("{}:login:ip:{}", conf!().app_name, $ip)
+    };
+}
+
+/// 模型缓存，见 [crate::utils::cache]
+#[macro_export]
+macro_rules! rk_cache {
+    ($name:expr, $pk:expr) => {
+        format!("{}:cache:{}:{}", conf!().app_name, $name, $pk)
+    };
+}
+
+/// 模型缓存的加载锁，同一时间只有一个请求查数据库
+#[macro_export]
+macro_rules! rk_cache_lock {
+    ($name:expr, $pk:expr) => {
+        format!("{}:cache:lock:{}:{}", conf!().app_name, $name, $pk)
+    };
+}
+
+/// 秒杀活动的 hash: stock begin end limit，见 [crate::serv::spike]
+#[macro_export]
+macro_rules! rk_spike {
+    ($pk:expr) => {
+        format!("{}:spike:{}", conf!().app_name, $pk)
+    };
+}
+
+/// 秒杀活动中每个用户已抢到的数量，hash 用户id => 数量
+#[macro_export]
+macro_rules! rk_spike_users {
+    ($pk:expr) => {
+        format!("{}:spike:{}:users", conf!().app_name, $pk)
+    };
+}
+
+/// 秒杀请求的结果，同时用作幂等 key。排队中为空，成功后为订单号，失败为 failed
+#[macro_export]
+macro_rules! rk_spike_ticket {
+    ($user_id:expr, $request_id:expr) => {
+        format!(
+            "{}:spike:ticket:{}:{}",
+            conf!().app_name,
+            $user_id,
+            $request_id
+        )
     };
 }
 
@@ -305,7 +580,7 @@ macro_rules! rk_phone_vc {
 #[macro_export]
 macro_rules! rk_wx_access_token {
     () => {
-        concat!(env!("APP_NAME"), ":wx:access_token")
+        &format!("{}:wx:access_token", conf!().app_name)
     };
 }
 
@@ -313,7 +588,7 @@ macro_rules! rk_wx_access_token {
 #[macro_export]
 macro_rules! rk_wx_jsapi_ticket {
     () => {
-        concat!(env!("APP_NAME"), ":wx:jsapi_ticket")
+        &format!("{}:wx:jsapi_ticket", conf!().app_name)
     };
 }
 
@@ -321,7 +596,7 @@ macro_rules! rk_wx_jsapi_ticket {
 #[macro_export]
 macro_rules! rk_wx_userinfo {
     ($pk:expr) => {
-        format!("{}:wx:userinfo:{}", env!("APP_NAME"), $pk)
+        format!("{}:wx:userinfo:{}", conf!().app_name, $pk)
     };
 }
 
@@ -329,7 +604,7 @@ macro_rules! rk_wx_userinfo {
 #[macro_export]
 macro_rules! rk_wxmp_access_token {
     () => {
-        concat!(env!("APP_NAME"), ":wxmp:access_token")
+        &format!("{}:wxmp:access_token", conf!().app_name)
     };
 }
 
@@ -337,7 +612,7 @@ macro_rules! rk_wxmp_access_token {
 #[macro_export]
 macro_rules! rk_media_md5 {
     ($md5_hash: expr) => {
-        format!("{}:media_md5:{}", env!("APP_NAME"), $md5_hash)
+        format!("{}:media_md5:{}", conf!().app_name, $md5_hash)
     };
 }
 
@@ -345,6 +620,6 @@ macro_rules! rk_media_md5 {
 #[macro_export]
 macro_rules! rk_qn_etag {
     ($etag: expr) => {
-        format!("{}:qn_etag:{}", env!("APP_NAME"), $etag)
+        format!("{}:qn_etag:{}", conf!().app_name, $etag)
     };
 }
@@ -4,18 +4,21 @@
 #[macro_use]
 extern crate diesel;
 
-#[macro_use]
-extern crate dotenv_codegen;
-
 #[macro_use]
 pub mod macros;
 
 use actix_web::{web, App, HttpServer};
+use clap::Parser;
+use config::ListenMode;
 
+mod cli;
+pub mod config;
 mod ctrl;
+mod extract;
 mod middleware;
 pub mod models;
 mod modules;
+mod perm;
 mod prelude;
 mod queues;
 pub mod schema;
@@ -26,44 +29,94 @@ mod utils;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    utils::init::env();
-    env_logger::init_from_env(env_logger::Env::new());
+    let cli = cli::Cli::parse();
+    if let Some(cli::Command::Permissions) = cli.command {
+        perm::print_catalog();
+        return Ok(());
+    }
+
+    let app_config = match config::init(&cli) {
+        Ok(c) => c,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or(&app_config.rust_log));
+
+    // 依赖在重试后仍不可用时正常退出，不要 panic
+    let to_io_err =
+        |e: anyhow::Error| std::io::Error::new(std::io::ErrorKind::Other, e.to_string());
+
+    if let Some(cli::Command::Admin(ref cmd)) = cli.command {
+        return cli::admin(cmd, app_config).await.map_err(to_io_err);
+    }
 
     #[cfg(feature = "dev")]
     log::info!(
         "starting HTTP server at http://{}:{}",
         local_ipaddress::get().unwrap(),
-        env!("ACTIX_PORT")
+        app_config.actix_port
     );
-    let redis_pool = utils::init::redis_pool();
-    let mysql_pool = utils::init::mysql_pool();
+    utils::jwt::init(&app_config.jwt).map_err(to_io_err)?;
+    let redis_pool = utils::init::redis_pool(app_config).await.map_err(to_io_err)?;
+    let mysql_pool = utils::init::mysql_pool(app_config).await.map_err(to_io_err)?;
+    utils::cache::init(redis_pool.clone());
+    serv::rbac::sync_catalog(web::Data::new(mysql_pool.clone()))
+        .await
+        .map_err(to_io_err)?;
+
+    let workers = utils::worker::Workers::default();
 
     #[cfg(feature = "mail")]
-    queues::mail::init(redis_pool.clone());
+    queues::mail::init(redis_pool.clone(), &workers);
 
-    tasks::delay_task::init(redis_pool.clone());
-    tasks::timer_task::init(redis_pool.clone());
-    tasks::persistent_task::init(redis_pool.clone());
+    queues::sms::init(redis_pool.clone(), &workers);
+    queues::spike::init(
+        redis_pool.clone(),
+        web::Data::new(mysql_pool.clone()),
+        &workers,
+    );
+    serv::spike::preload_active(web::Data::new(mysql_pool.clone()), &redis_pool)
+        .await
+        .map_err(to_io_err)?;
+
+    tasks::delay_task::init(
+        redis_pool.clone(),
+        web::Data::new(mysql_pool.clone()),
+        &workers,
+    );
+    tasks::timer_task::init(
+        redis_pool.clone(),
+        web::Data::new(mysql_pool.clone()),
+        &workers,
+    );
+    tasks::persistent_task::init(redis_pool.clone(), &workers);
 
+    let app_workers = workers.clone();
     let serve = HttpServer::new(move || {
         let app = App::new();
 
         #[cfg(feature = "dev")]
         let app = app
-            .service(actix_files::Files::new(env!("STATIC_URL"), "public/static"))
-            .service(actix_files::Files::new(env!("MEDIA_URL"), "public/media"))
+            .service(actix_files::Files::new(&app_config.static_url, "public/static"))
+            .service(actix_files::Files::new(&app_config.media_url, "public/media"))
             .service(actix_files::Files::new("/admin", "public/admin").index_file("index.html"));
 
+        let app = app.app_data(web::Data::new(app_config.clone()));
         let app = app.app_data(web::Data::new(awc::Client::default()));
         let app = app.app_data(web::Data::new(redis_pool.clone()));
         let app = app.app_data(web::Data::new(mysql_pool.clone()));
+        let app = app.app_data(web::Data::new(app_workers.clone()));
 
         #[cfg(feature = "cors")]
         let app = app.wrap(actix_cors::Cors::default().allowed_origin_fn(|_, _| true));
 
         let app = app
             // .wrap(actix_web::middleware::Logger::default())
-            .wrap(actix_web::middleware::Compress::default());
+            .wrap(actix_web::middleware::Compress::default())
+            // 放在最外层，其他中间件返回的错误也能翻译
+            .wrap(middleware::I18n);
 
         #[cfg(feature = "template")]
         let app = app.service(web::scope("/chat").configure(ctrl::chat::config));
@@ -71,6 +124,8 @@ async fn main() -> std::io::Result<()> {
         #[cfg(feature = "ws")]
         let app = app.service(web::scope("/ws").configure(ctrl::ws::config));
 
+        let app = app.configure(ctrl::health::config);
+        let app = app.configure(ctrl::well_known::config);
         let app = app.service(web::scope("/api").configure(ctrl::api::config));
 
         // 根路径应始终定义为最后一项
@@ -79,26 +134,38 @@ async fn main() -> std::io::Result<()> {
         // let app = app.default_service(web::to(utils::all_try_files));
 
         app
-    });
-
-    if cfg!(feature = "dev") {
-        let port = get_env!("ACTIX_PORT", u16);
-        serve.bind(("0.0.0.0", port))?
-    } else {
-        let port = get_env!("ACTIX_PORT", u16);
-        serve.bind(("0.0.0.0", port))?
-
-        // // 断掉服务文件会被自动删除，若文件存在则会启动失败
-        // let p = format!("/tmp/{}.socket", env!("APP_NAME"));
-        // let r = serve.bind_uds(&p)?;
-
-        // // 设置权限，不然nginx没有权限访问
-        // let mut perms = std::fs::metadata(&p)?.permissions();
-        // perms.set_readonly(false);
-        // std::fs::set_permissions(&p, perms)?;
-
-        // r
+    })
+    .shutdown_timeout(app_config.shutdown_timeout);
+
+    let listen = &app_config.listen;
+    let addr = (listen.host.as_str(), app_config.actix_port);
+    let serve = match listen.mode {
+        ListenMode::Tcp => {
+            log::info!("listening on http://{}:{}", addr.0, addr.1);
+            serve.bind(addr)?
+        }
+        ListenMode::Uds => {
+            utils::init::clean_stale_socket(&listen.uds_path).map_err(to_io_err)?;
+            let serve = serve.bind_uds(&listen.uds_path)?;
+            utils::init::set_socket_mode(&listen.uds_path, listen.uds_mode).map_err(to_io_err)?;
+            log::info!("listening on unix:{}", listen.uds_path);
+            serve
+        }
+        ListenMode::Tls => {
+            let acceptor = utils::init::ssl_acceptor(listen).map_err(to_io_err)?;
+            log::info!("listening on https://{}:{}", addr.0, addr.1);
+            serve.bind_openssl(addr, acceptor)?
+        }
+    };
+    serve.run().await?;
+
+    if listen.mode == ListenMode::Uds {
+        let _ = std::fs::remove_file(&listen.uds_path);
     }
-    .run()
-    .await
+
+    // HttpServer 收到 SIGINT/SIGTERM 并处理完请求后，再停止后台任务
+    workers
+        .shutdown(std::time::Duration::from_secs(app_config.shutdown_timeout))
+        .await;
+    Ok(())
 }
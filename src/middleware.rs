@@ -1,16 +1,47 @@
 use crate::prelude::*;
+use crate::utils::i18n::{self, Locale};
+use actix_web::body::EitherBody;
 use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderValue, ACCEPT_LANGUAGE, CONTENT_LANGUAGE};
 use actix_web::{Error, HttpMessage};
 use futures_util::future::LocalBoxFuture;
 
 use std::future::{ready, Ready};
+use std::rc::Rc;
 
 /// jwt 验证
 ///
-pub struct JWTAuth;
+/// token 的 aud 必须是 audiences 之一，管理员的 token 不能访问用户接口，反之亦然
+pub struct JWTAuth {
+    audiences: &'static [Audience],
+}
+
+impl JWTAuth {
+    /// 只允许管理员
+    pub fn admin() -> Self {
+        Self {
+            audiences: &[Audience::Admin],
+        }
+    }
+
+    /// 只允许用户
+    pub fn user() -> Self {
+        Self {
+            audiences: &[Audience::User],
+        }
+    }
+
+    /// 管理员和用户都可以
+    pub fn any() -> Self {
+        Self {
+            audiences: &[Audience::Admin, Audience::User],
+        }
+    }
+}
+
 impl<S, B> Transform<S, ServiceRequest> for JWTAuth
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -21,17 +52,21 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(JWTAuthMiddleware { service }))
+        ready(Ok(JWTAuthMiddleware {
+            service: Rc::new(service),
+            audiences: self.audiences,
+        }))
     }
 }
 
 pub struct JWTAuthMiddleware<S> {
-    service: S,
+    service: Rc<S>,
+    audiences: &'static [Audience],
 }
 
 impl<S, B> JWTAuthMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -47,7 +82,7 @@ where
 
 impl<S, B> Service<ServiceRequest> for JWTAuthMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -58,23 +93,113 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        if let Ok(authorization) = self.get_token(&req) {
+        let claims = if let Ok(authorization) = self.get_token(&req) {
             // 7.. => 'Bearer ' + token
-            match Claims::decode(&authorization[7..]) {
-                Ok(token_data) => {
-                    req.extensions_mut().insert(token_data.claims);
-                }
+            match Claims::decode(authorization.get(7..).unwrap_or_default(), self.audiences) {
+                Ok(token_data) => token_data.claims,
                 Err(err) => {
-                    return Box::pin(async move { Err(ej(&err).auth().actix()) });
+                    log::debug!("jwt 验证失败: {}", err);
+                    let err = ej(err);
+                    if err.kind == ErrKind::Forbidden {
+                        return Box::pin(async move { Err(err.actix()) });
+                    }
+                    return Box::pin(async { res_err!(Auth, "auth.invalid_token") });
                 }
             }
         } else {
-            return Box::pin(async { Err(ej("需要身份验证").auth().actix()) });
-        }
+            return Box::pin(async { res_err!(Auth, "auth.required") });
+        };
 
-        let fut = self.service.call(req);
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            // 已退出登陆、修改过密码的 token
+            let redis_pool = req
+                .app_data::<web::Data<RedisPool>>()
+                .ok_or_else(|| ErrKind::Internal.err("RedisPool 未注册").actix())?;
+            serv::auth::verify_session(redis_pool, &claims)
+                .await
+                .map_err(|e| ej(e).actix())?;
 
-        Box::pin(async move { Ok(fut.await?) })
+            req.extensions_mut().insert(claims);
+            service.call(req).await
+        })
+    }
+}
+
+/// 检查管理员的权限，必须在 [JWTAuth::admin] 之后使用，超级管理员不检查
+///
+/// 权限在 [crate::perm] 中声明，路由使用 wrap 指定:
+///
+/// ```
+/// #[http_post("del", wrap = "perm::ADMIN_DELETE")]
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RequirePermission(pub &'static str);
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequirePermissionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePermissionMiddleware {
+            service: Rc::new(service),
+            name: self.0,
+        }))
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: Rc<S>,
+    name: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let name = self.name;
+
+        Box::pin(async move {
+            let claims = req
+                .extensions()
+                .get::<Claims>()
+                .cloned()
+                .ok_or_else(|| ErrKind::Auth.err("auth.required").actix())?;
+            if claims.aud != Audience::Admin {
+                return res_err!(Forbidden, "auth.forbidden");
+            }
+
+            let db_pool = req
+                .app_data::<web::Data<DbPool>>()
+                .cloned()
+                .ok_or_else(|| ErrKind::Internal.err("DbPool 未注册").actix())?;
+            let redis_pool = req
+                .app_data::<web::Data<RedisPool>>()
+                .ok_or_else(|| ErrKind::Internal.err("RedisPool 未注册").actix())?;
+            serv::rbac::check(db_pool, redis_pool, claims.id, name)
+                .await
+                .map_err(|e| ej(e).actix())?;
+
+            service.call(req).await
+        })
     }
 }
 
@@ -149,7 +274,7 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         if let Ok(r) = self.is_big(&req) {
             if r {
-                return Box::pin(async { res_err!("请求实体太大") });
+                return Box::pin(async { res_err!(PayloadTooLarge, "http.payload_too_large") });
             }
         }
 
@@ -222,3 +347,117 @@ where
         })
     }
 }
+
+/// 根据请求选择语言，错误消息按这个语言翻译
+///
+/// 优先级: 登陆用户保存的语言 > 用户设置的 lang cookie > Accept-Language > 配置 DEFAULT_LOCALE
+///
+/// ```
+/// App::new().wrap(middleware::I18n)
+/// ```
+pub struct I18n;
+impl<S, B> Transform<S, ServiceRequest> for I18n
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = I18nMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(I18nMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct I18nMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S> I18nMiddleware<S> {
+    /// 登陆用户保存的语言，从缓存读取
+    ///
+    /// 这里只用来选择语言，token 是否有效由 [JWTAuth] 检查，无效时返回 None
+    async fn saved_locale(req: &ServiceRequest) -> Option<Locale> {
+        let token = req
+            .headers()
+            .get("Authorization")?
+            .to_str()
+            .ok()?
+            .get(7..)?;
+        let claims = Claims::decode(token, &[Audience::Admin, Audience::User])
+            .ok()?
+            .claims;
+        let db_pool = req.app_data::<web::Data<DbPool>>()?.clone();
+        let saved = match claims.aud {
+            Audience::Admin => {
+                models::admin::Admin::obj(db_pool, claims.id)
+                    .await
+                    .ok()?
+                    .locale
+            }
+            Audience::User => {
+                models::user::User::obj(db_pool, claims.id)
+                    .await
+                    .ok()?
+                    .locale
+            }
+        };
+        saved?.parse().ok()
+    }
+
+    fn locale(req: &ServiceRequest) -> Locale {
+        if let Some(locale) = req.cookie("lang").and_then(|c| c.value().parse().ok()) {
+            return locale;
+        }
+
+        req.headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(Locale::from_accept_language)
+            .unwrap_or_default()
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for I18nMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let locale = match Self::saved_locale(&req).await {
+                Some(locale) => locale,
+                None => Self::locale(&req),
+            };
+            let (http_req, payload) = req.into_parts();
+
+            i18n::scope(locale, async move {
+                let fut = service.call(ServiceRequest::from_parts(http_req.clone(), payload));
+                // 其他中间件返回的错误也要在这里生成响应，离开 scope 后就无法获取语言
+                let mut res = match fut.await {
+                    Ok(res) => res.map_into_left_body(),
+                    Err(err) => ServiceResponse::from_err(err, http_req).map_into_right_body(),
+                };
+                res.headers_mut()
+                    .insert(CONTENT_LANGUAGE, HeaderValue::from_static(locale.tag()));
+                Ok(res)
+            })
+            .await
+        })
+    }
+}
@@ -1,4 +1,7 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    utils::cache::{Cacheable, Cached},
+};
 use schema::*;
 
 #[derive(Identifiable, Queryable, Serialize, Deserialize, Validate, Debug)]
@@ -7,13 +10,13 @@ pub struct Admin {
     pub id: PK,
     pub username: String,
 
-    #[serde(skip_serializing)]
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
+    #[serde(skip_serializing, default)]
+    #[validate(length(min = 3, max = 20, message = "validation.password_len"))]
     pub password: String,
 
     pub realname: Option<String>,
 
-    #[validate(custom(function = "vf::email", message = "邮箱格式错误"))]
+    #[validate(custom(function = "vf::email", message = "validation.email"))]
     pub email: Option<String>,
 
     pub remark: Option<String>,
@@ -22,6 +25,25 @@ pub struct Admin {
     pub last_login: Option<NaiveDateTime>,
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
+
+    /// 加密后的 TOTP 密钥，见 [crate::utils::totp]
+    #[serde(skip_serializing, default)]
+    pub totp_secret: Option<String>,
+
+    /// 是否开启两步验证
+    pub totp_enabled: bool,
+
+    /// 恢复码的 sha256，json 数组，使用后删除
+    #[serde(skip_serializing, default)]
+    pub recovery_codes: Option<String>,
+
+    /// 保存的语言，见 [crate::middleware::I18n]
+    pub locale: Option<String>,
+}
+
+/// 密码、TOTP 密钥和恢复码不缓存，需要时使用 [Admin::obj_with_secrets]
+impl Cacheable for Admin {
+    const NAME: &'static str = "admin";
 }
 
 impl Admin {
@@ -31,7 +53,7 @@ impl Admin {
             diesel::insert_into(admin::table)
                 .values(data)
                 .execute(&mut conn)
-                .map_err(|e| db_unique_err(e, "注册失败"))?;
+                .map_err(|e| db_unique_err(e, "user.register_failed"))?;
             let id: PK = diesel::dsl::select(last_insert_id()).get_result::<PK>(&mut conn)?;
             Ok(id)
         })
@@ -39,13 +61,32 @@ impl Admin {
         Ok(new_id)
     }
 
+    /// 优先读取缓存，见 [crate::utils::cache]
+    ///
+    /// 不包括密码、TOTP 密钥和恢复码，是否读取缓存都一样
     pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<Admin> {
+        let load = async {
+            let mut x = Self::load(db_pool, pk).await?;
+            x.password.clear();
+            x.totp_secret = None;
+            x.recovery_codes = None;
+            Ok(x)
+        };
+        Cached::<Admin>::get_or_load(pk, load).await
+    }
+
+    /// 从数据库读取，包括密码、TOTP 密钥和恢复码，只在验证时使用
+    pub async fn obj_with_secrets(db_pool: web::Data<DbPool>, pk: PK) -> R<Admin> {
+        Self::load(db_pool, pk).await
+    }
+
+    async fn load(db_pool: web::Data<DbPool>, pk: PK) -> R<Admin> {
         let user: Admin = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
             Ok(admin::table
                 .find(pk)
                 .first(&mut conn)
-                .map_err(|e| db_not_found_err(e, "未找到"))?)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
         })
         .await??;
         Ok(user)
@@ -62,63 +103,115 @@ impl Admin {
         Ok(data)
     }
 
-    /// 批量删除
-    pub async fn del(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<usize> {
-        let rows: usize = web::block(move || -> R<_> {
+    /// 分页查询非超级管理员
+    pub async fn page(db_pool: web::Data<DbPool>, page: PageQuery) -> R<Paginated<Admin>> {
+        let res = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
-            Ok(diesel::delete(admin::table)
-                .filter(admin::id.eq_any(pks))
-                .execute(&mut conn)?)
+            let build = || -> R<_> {
+                let q = admin::table
+                    .filter(admin::is_superadmin.eq(false))
+                    .into_boxed();
+                Ok(page_filter!(q, page, {
+                    "username" => admin::username: String [Eq, Like],
+                    "email" => admin::email: String [Eq, Like],
+                    "realname" => admin::realname: String [Eq, Like],
+                    "remark" => admin::remark: String [Like],
+                    "is_active" => admin::is_active: bool [Eq],
+                    "totp_enabled" => admin::totp_enabled: bool [Eq],
+                    "last_login" => admin::last_login: NaiveDateTime [Range],
+                    "create_at" => admin::create_at: NaiveDateTime [Range],
+                }))
+            };
+
+            let total: i64 = build()?.count().get_result(&mut conn)?;
+            let data = page_sort!(build()?, page, {
+                "id" => admin::id,
+                "username" => admin::username,
+                "last_login" => admin::last_login,
+                "create_at" => admin::create_at,
+            })
+            .then_order_by(admin::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<Admin>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page))
         })
         .await??;
-        Ok(rows)
+        Ok(res)
     }
 
-    pub async fn update(db_pool: web::Data<DbPool>, data: ser::admin::UpdateAdmin) -> R<usize> {
-        let rows = web::block(move || -> R<usize> {
+    /// 包括超级管理员
+    pub async fn list_all(db_pool: web::Data<DbPool>) -> R<Vec<Admin>> {
+        let data = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
-            let target = admin::table.filter(admin::id.eq(data.id));
-            let rows = diesel::update(target).set(&data).execute(&mut conn)?;
+            Ok(admin::table
+                .order(admin::id.asc())
+                .load::<Admin>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
 
-            if rows == 0 {
-                bail!("更新失败!");
-            }
+    /// 使用多个id获取
+    pub async fn objs(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<Vec<Admin>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(admin::table
+                .filter(admin::id.eq_any(pks))
+                .load::<Admin>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
 
-            Ok(rows)
+    /// 批量删除，operator_superadmin 为 false 时跳过超级管理员
+    pub async fn del(
+        db_pool: web::Data<DbPool>,
+        pks: Vec<PK>,
+        operator_superadmin: bool,
+    ) -> R<usize> {
+        let ids = pks.clone();
+        let rows: usize = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            let mut q = diesel::delete(admin::table)
+                .filter(admin::id.eq_any(pks))
+                .into_boxed();
+            if !operator_superadmin {
+                q = q.filter(admin::is_superadmin.eq(false));
+            }
+            Ok(q.execute(&mut conn)?)
         })
         .await??;
+        Cached::<Admin>::invalidate_many(&ids).await;
         Ok(rows)
     }
 
-    /// 修改密码
-    pub async fn change_pwd(
+    /// 修改，operator_superadmin 为 false 时不能修改超级管理员
+    pub async fn update(
         db_pool: web::Data<DbPool>,
-        pk: PK,
-        pwd: String,
-        new_pwd: String,
-        new_pwd2: String,
+        data: ser::admin::UpdateAdmin,
+        operator_superadmin: bool,
     ) -> R<usize> {
-        if new_pwd != new_pwd2 {
-            bail!("两次密码不一样");
-        }
-
-        // 验证旧密码
-        let user = Admin::obj(db_pool.clone(), pk).await?;
-        if !pwd_decode!(&user.password, pwd.as_ref()) {
-            bail!("密码错误");
-        }
-
-        // 设置新密码
+        let pk = data.id;
         let rows = web::block(move || -> R<usize> {
             let mut conn = db_pool.get()?;
-            let new_password = pwd_encode!(new_pwd);
-            let target = admin::table.filter(admin::id.eq(pk));
-            Ok(diesel::update(target)
-                .set(admin::password.eq(new_password))
-                .execute(&mut conn)?)
+            let target = admin::table.filter(admin::id.eq(data.id));
+            let rows = if operator_superadmin {
+                diesel::update(target).set(&data).execute(&mut conn)?
+            } else {
+                diesel::update(target.filter(admin::is_superadmin.eq(false)))
+                    .set(&data)
+                    .execute(&mut conn)?
+            };
+
+            if rows == 0 {
+                bail!("user.update_failed");
+            }
+
+            Ok(rows)
         })
         .await??;
-
+        Cached::<Admin>::invalidate(pk).await;
         Ok(rows)
     }
 
@@ -130,11 +223,12 @@ impl Admin {
                 .set(admin::last_login.eq(current_timestamp().nullable()))
                 .execute(&mut conn)?;
             if rows == 0 {
-                bail!("更新登陆时间失败");
+                bail!("user.update_login_failed");
             }
             Ok(())
         })
         .await??;
+        Cached::<Admin>::invalidate(pk).await;
         Ok(())
     }
 
@@ -145,28 +239,135 @@ impl Admin {
             Ok(admin::table
                 .filter(admin::username.eq(&username))
                 .first(&mut conn)
-                .map_err(|e| db_not_found_err(e, "账号或密码错误！"))?)
+                .map_err(|e| db_not_found_as(e, ErrKind::Auth, "auth.bad_credentials"))?)
         })
         .await??;
         Ok(user)
     }
 
-    pub async fn login(
+    /// 保存新的 TOTP 密钥(已加密)，验证通过前不开启
+    pub async fn set_totp_secret(db_pool: web::Data<DbPool>, pk: PK, secret: String) -> R<()> {
+        web::block(move || -> R<()> {
+            let mut conn = conn!(db_pool);
+            diesel::update(admin::table.find(pk))
+                .set((
+                    admin::totp_secret.eq(secret),
+                    admin::totp_enabled.eq(false),
+                    admin::recovery_codes.eq(None::<String>),
+                ))
+                .execute(&mut conn)?;
+            Ok(())
+        })
+        .await??;
+        Cached::<Admin>::invalidate(pk).await;
+        Ok(())
+    }
+
+    /// 保存语言，None 时使用请求的语言
+    pub async fn set_locale(
         db_pool: web::Data<DbPool>,
-        username: String,
-        password: String,
-    ) -> R<String> {
-        let user: Self = Self::obj_with_username(db_pool.clone(), username).await?;
+        pk: PK,
+        locale: Option<String>,
+    ) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(db_pool);
+            Ok(diesel::update(admin::table.find(pk))
+                .set(admin::locale.eq(locale))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Cached::<Admin>::invalidate(pk).await;
+        Ok(rows)
+    }
+
+    /// 开启两步验证，同时保存恢复码
+    pub async fn enable_totp(db_pool: web::Data<DbPool>, pk: PK, recovery_codes: String) -> R<()> {
+        web::block(move || -> R<()> {
+            let mut conn = conn!(db_pool);
+            diesel::update(admin::table.find(pk))
+                .set((
+                    admin::totp_enabled.eq(true),
+                    admin::recovery_codes.eq(recovery_codes),
+                ))
+                .execute(&mut conn)?;
+            Ok(())
+        })
+        .await??;
+        Cached::<Admin>::invalidate(pk).await;
+        Ok(())
+    }
 
-        if !pwd_decode!(&user.password, password.as_ref()) {
-            bail!("账号或密码错误");
+    /// 更新剩余的恢复码
+    pub async fn set_recovery_codes(
+        db_pool: web::Data<DbPool>,
+        pk: PK,
+        recovery_codes: String,
+    ) -> R<()> {
+        web::block(move || -> R<()> {
+            let mut conn = conn!(db_pool);
+            diesel::update(admin::table.find(pk))
+                .set(admin::recovery_codes.eq(recovery_codes))
+                .execute(&mut conn)?;
+            Ok(())
+        })
+        .await??;
+        Cached::<Admin>::invalidate(pk).await;
+        Ok(())
+    }
+
+    /// 使用一个恢复码，保存剩余的恢复码
+    ///
+    /// 只有恢复码仍然是 old 时才修改，并发使用同一个恢复码时只有一个成功，失败返回 false
+    pub async fn use_recovery_code(
+        db_pool: web::Data<DbPool>,
+        pk: PK,
+        old: String,
+        rest: String,
+    ) -> R<bool> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(db_pool);
+            Ok(
+                diesel::update(admin::table.find(pk).filter(admin::recovery_codes.eq(old)))
+                    .set(admin::recovery_codes.eq(rest))
+                    .execute(&mut conn)?,
+            )
+        })
+        .await??;
+        Cached::<Admin>::invalidate(pk).await;
+        Ok(rows > 0)
+    }
+
+    /// 关闭两步验证，清除密钥和恢复码
+    pub async fn disable_totp(db_pool: web::Data<DbPool>, pk: PK) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(db_pool);
+            Ok(diesel::update(admin::table.find(pk))
+                .set((
+                    admin::totp_secret.eq(None::<String>),
+                    admin::totp_enabled.eq(false),
+                    admin::recovery_codes.eq(None::<String>),
+                ))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Cached::<Admin>::invalidate(pk).await;
+        Ok(rows)
+    }
+
+    /// 验证账号密码，成功后返回管理员
+    ///
+    /// 开启两步验证时还需要验证 TOTP，登陆时间在签发 token 时更新，见 [crate::serv::auth::admin_login]
+    pub async fn login(db_pool: web::Data<DbPool>, username: String, password: String) -> R<Self> {
+        let user: Self = Self::obj_with_username(db_pool, username).await?;
+
+        if !serv::password::verify(user.password.clone(), password).await? {
+            bail!(ErrKind::Auth.err("auth.bad_credentials"));
         }
 
         if !user.is_active {
-            bail!("账号未激活");
+            bail!(ErrKind::Forbidden.err("auth.inactive"));
         }
 
-        Self::update_last_login(db_pool, user.id).await?;
-        Ok(Claims::new(user.id, 0).jwt()?)
+        Ok(user)
     }
 }
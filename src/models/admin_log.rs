@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use diesel::mysql::Mysql;
 use schema::*;
 
 pub enum ActionFlag {
@@ -11,10 +12,100 @@ pub enum ActionFlag {
 #[diesel(table_name = admin_log)]
 pub struct AdminLog {
     pub id: PK,
-    pub sys_user_id: PK,
     pub action_flag: u8, // (*&ActionFlag::ADD as u8) == action_flag
     pub action_msg: String,
     pub desc: String,
+    pub admin_id: PK,
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
+    pub object_type: String,
+    pub object_id: Option<PK>,
+
+    /// 修改前后的值，json {"字段": [修改前, 修改后]}
+    pub changes: Option<String>,
+    pub ip: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = admin_log)]
+pub struct NewAdminLog {
+    pub action_flag: u8,
+    pub action_msg: String,
+    pub desc: String,
+    pub admin_id: PK,
+    pub object_type: String,
+    pub object_id: Option<PK>,
+    pub changes: Option<String>,
+    pub ip: String,
+}
+
+impl AdminLog {
+    pub async fn create_many(db_pool: web::Data<DbPool>, data: Vec<NewAdminLog>) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(&db_pool);
+            Ok(diesel::insert_into(admin_log::table)
+                .values(data)
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 分页查询，新的在前，请求中有 cursor 时使用 keyset 分页
+    pub async fn page(db_pool: web::Data<DbPool>, page: PageQuery) -> R<Paginated<AdminLog>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+
+            if page.keyset {
+                let mut q = filtered(&page)?;
+                if let Some(after) = page.after()? {
+                    q = q.filter(admin_log::id.lt(after));
+                }
+                let data = q
+                    .order(admin_log::id.desc())
+                    .limit(page.limit + 1)
+                    .load::<AdminLog>(&mut conn)?;
+                return Ok(Paginated::keyset(data, &page, |x| x.id));
+            }
+
+            let total: i64 = filtered(&page)?.count().get_result(&mut conn)?;
+            let data = page_sort!(filtered(&page)?, page, {
+                "id" => admin_log::id,
+                "admin_id" => admin_log::admin_id,
+                "create_at" => admin_log::create_at,
+            })
+            .then_order_by(admin_log::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<AdminLog>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page))
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 导出，忽略分页，最多 max 条，新的在前
+    pub async fn export(db_pool: web::Data<DbPool>, page: PageQuery, max: i64) -> R<Vec<AdminLog>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(filtered(&page)?
+                .order(admin_log::id.desc())
+                .limit(max)
+                .load::<AdminLog>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+}
+
+fn filtered(page: &PageQuery) -> R<admin_log::BoxedQuery<'static, Mysql>> {
+    Ok(page_filter!(admin_log::table.into_boxed(), page, {
+        "admin_id" => admin_log::admin_id: PK [Eq, In],
+        "action_flag" => admin_log::action_flag: u8 [Eq, In],
+        "action_msg" => admin_log::action_msg: String [Eq, Like],
+        "object_type" => admin_log::object_type: String [Eq, In],
+        "object_id" => admin_log::object_id: PK [Eq, In],
+        "ip" => admin_log::ip: String [Eq],
+        "create_at" => admin_log::create_at: NaiveDateTime [Range],
+    }))
 }
@@ -14,7 +14,9 @@ pub struct Group {
 #[derive(Insertable, Validate, Deserialize, PartialEq, Debug)]
 #[diesel(table_name = auth_group)]
 pub struct GroupNew {
+    #[validate(length(min = 1, max = 100))]
     pub name: String,
+    #[validate(length(max = 150))]
     pub desc: String,
 }
 
@@ -22,8 +24,90 @@ pub struct GroupNew {
 #[diesel(table_name = auth_group)]
 pub struct GroupUpdate {
     pub id: Option<PK>,
+    #[validate(length(min = 1, max = 100))]
     pub name: Option<String>,
+    #[validate(length(max = 150))]
     pub desc: Option<String>,
     pub create_at: Option<NaiveDateTime>,
     pub update_at: Option<NaiveDateTime>,
 }
+
+impl Group {
+    pub async fn list(db_pool: web::Data<DbPool>) -> R<Vec<Group>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(auth_group::table
+                .order(auth_group::id.asc())
+                .load::<Group>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<Group> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(auth_group::table
+                .find(pk)
+                .first(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    pub async fn create(db_pool: web::Data<DbPool>, data: GroupNew) -> R<PK> {
+        let new_id = web::block(move || -> R<PK> {
+            let mut conn = conn!(&db_pool);
+            diesel::insert_into(auth_group::table)
+                .values(data)
+                .execute(&mut conn)
+                .map_err(|e| db_unique_err(e, "rbac.name_exists"))?;
+            let id: PK = diesel::dsl::select(last_insert_id()).get_result::<PK>(&mut conn)?;
+            Ok(id)
+        })
+        .await??;
+        Ok(new_id)
+    }
+
+    /// id 和创建时间不能修改
+    pub async fn update(db_pool: web::Data<DbPool>, pk: PK, mut data: GroupUpdate) -> R<usize> {
+        data.id = None;
+        data.create_at = None;
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::update(auth_group::table.find(pk))
+                .set(&data)
+                .execute(&mut conn)
+                .map_err(|e| db_unique_err(e, "rbac.name_exists"))?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 批量删除，组的分配通过外键级联删除
+    pub async fn del(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(auth_group::table)
+                .filter(auth_group::id.eq_any(pks))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 管理员所在的组
+    pub async fn of_admin(db_pool: web::Data<DbPool>, admin_id: PK) -> R<Vec<Group>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(m2m_admin_group::table
+                .inner_join(auth_group::table)
+                .filter(m2m_admin_group::admin_id.eq(admin_id))
+                .select(auth_group::all_columns)
+                .load::<Group>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+}
@@ -0,0 +1,52 @@
+use crate::prelude::*;
+use schema::*;
+
+/// 登陆安全事件
+pub enum AuthEvent {
+    /// 密码或验证码错误次数过多，账号被锁定
+    Locked = 1,
+
+    /// 验证码错误次数过多，验证码作废
+    CaptchaRevoked = 2,
+
+    /// 同一个IP失败次数过多
+    IpLimited = 3,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = auth_log)]
+pub struct AuthLog {
+    pub id: PK,
+
+    /// [AuthEvent]
+    pub event: u8,
+    pub scope: String,
+    pub principal: String,
+    pub ip: String,
+    pub desc: String,
+    pub create_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = auth_log)]
+pub struct NewAuthLog {
+    pub event: u8,
+    pub scope: String,
+    pub principal: String,
+    pub ip: String,
+    pub desc: String,
+}
+
+impl AuthLog {
+    pub async fn create(db_pool: web::Data<DbPool>, data: NewAuthLog) -> R<()> {
+        web::block(move || -> R<()> {
+            let mut conn = conn!(&db_pool);
+            diesel::insert_into(auth_log::table)
+                .values(data)
+                .execute(&mut conn)?;
+            Ok(())
+        })
+        .await??;
+        Ok(())
+    }
+}
@@ -9,12 +9,17 @@ pub struct Permission {
     pub desc: String,
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
+
+    /// 代码中已没有声明，见 [crate::perm::CATALOG]
+    pub orphaned: bool,
 }
 
 #[derive(Insertable, Validate, Deserialize, PartialEq, Debug)]
 #[diesel(table_name = auth_permission)]
 pub struct PermissionNew {
+    #[validate(length(min = 1, max = 100))]
     pub name: String,
+    #[validate(length(max = 150))]
     pub desc: String,
 }
 
@@ -22,8 +27,229 @@ pub struct PermissionNew {
 #[diesel(table_name = auth_permission)]
 pub struct PermissionUpdate {
     pub id: Option<PK>,
+    #[validate(length(min = 1, max = 100))]
     pub name: Option<String>,
+    #[validate(length(max = 150))]
     pub desc: Option<String>,
     pub create_at: Option<NaiveDateTime>,
     pub update_at: Option<NaiveDateTime>,
-}
\ No newline at end of file
+}
+
+/// 同步权限目录的结果
+#[derive(Debug, Default)]
+pub struct CatalogSync {
+    pub created: usize,
+    pub updated: usize,
+    pub orphaned: usize,
+}
+
+impl Permission {
+    pub async fn list(db_pool: web::Data<DbPool>) -> R<Vec<Permission>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(auth_permission::table
+                .order(auth_permission::name.asc())
+                .load::<Permission>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<Permission> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(auth_permission::table
+                .find(pk)
+                .first(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    pub async fn create(db_pool: web::Data<DbPool>, data: PermissionNew) -> R<PK> {
+        let new_id = web::block(move || -> R<PK> {
+            let mut conn = conn!(&db_pool);
+            diesel::insert_into(auth_permission::table)
+                .values(data)
+                .execute(&mut conn)
+                .map_err(|e| db_unique_err(e, "rbac.name_exists"))?;
+            let id: PK = diesel::dsl::select(last_insert_id()).get_result::<PK>(&mut conn)?;
+            Ok(id)
+        })
+        .await??;
+        Ok(new_id)
+    }
+
+    /// id 和创建时间不能修改
+    pub async fn update(
+        db_pool: web::Data<DbPool>,
+        pk: PK,
+        mut data: PermissionUpdate,
+    ) -> R<usize> {
+        data.id = None;
+        data.create_at = None;
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::update(auth_permission::table.find(pk))
+                .set(&data)
+                .execute(&mut conn)
+                .map_err(|e| db_unique_err(e, "rbac.name_exists"))?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 批量删除，权限的分配通过外键级联删除
+    pub async fn del(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(auth_permission::table)
+                .filter(auth_permission::id.eq_any(pks))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 直接分配给管理员的权限
+    pub async fn of_admin(db_pool: web::Data<DbPool>, admin_id: PK) -> R<Vec<Permission>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(m2m_admin_permission::table
+                .inner_join(auth_permission::table)
+                .filter(m2m_admin_permission::admin_id.eq(admin_id))
+                .select(auth_permission::all_columns)
+                .load::<Permission>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    /// 组的权限
+    pub async fn of_group(db_pool: web::Data<DbPool>, group_id: PK) -> R<Vec<Permission>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(m2m_group_permission::table
+                .inner_join(auth_permission::table)
+                .filter(m2m_group_permission::group_id.eq(group_id))
+                .select(auth_permission::all_columns)
+                .load::<Permission>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    /// 按 id 查询权限名
+    pub async fn names_by_ids(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<Vec<String>> {
+        let names = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(auth_permission::table
+                .filter(auth_permission::id.eq_any(pks))
+                .select(auth_permission::name)
+                .load::<String>(&mut conn)?)
+        })
+        .await??;
+        Ok(names)
+    }
+
+    /// 多个组的所有权限名
+    pub async fn names_of_groups(db_pool: web::Data<DbPool>, group_ids: Vec<PK>) -> R<Vec<String>> {
+        let names = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(m2m_group_permission::table
+                .inner_join(auth_permission::table)
+                .filter(m2m_group_permission::group_id.eq_any(group_ids))
+                .select(auth_permission::name)
+                .distinct()
+                .load::<String>(&mut conn)?)
+        })
+        .await??;
+        Ok(names)
+    }
+
+    /// 管理员的有效权限，直接分配的加上所在组的
+    pub async fn names_of_admin(db_pool: web::Data<DbPool>, pk: PK) -> R<Vec<String>> {
+        let names = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+
+            let mut names: Vec<String> = m2m_admin_permission::table
+                .inner_join(auth_permission::table)
+                .filter(m2m_admin_permission::admin_id.eq(pk))
+                .select(auth_permission::name)
+                .load(&mut conn)?;
+
+            let group_ids = m2m_admin_group::table
+                .filter(m2m_admin_group::admin_id.eq(pk))
+                .select(m2m_admin_group::group_id);
+            let via_groups: Vec<String> = m2m_group_permission::table
+                .inner_join(auth_permission::table)
+                .filter(m2m_group_permission::group_id.eq_any(group_ids))
+                .select(auth_permission::name)
+                .load(&mut conn)?;
+
+            names.extend(via_groups);
+            names.sort();
+            names.dedup();
+            Ok(names)
+        })
+        .await??;
+        Ok(names)
+    }
+
+    /// 同步代码中声明的权限，(权限名, 说明)
+    ///
+    /// 新增没有的权限，更新说明，数据库中有但代码中没有的标记为 orphaned，不删除已有的分配
+    pub async fn sync_catalog(
+        db_pool: web::Data<DbPool>,
+        catalog: Vec<(String, String)>,
+    ) -> R<CatalogSync> {
+        let res = web::block(move || -> R<CatalogSync> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let existing: HashMap<String, String> = auth_permission::table
+                    .select((auth_permission::name, auth_permission::desc))
+                    .load::<(String, String)>(conn)?
+                    .into_iter()
+                    .collect();
+
+                let mut res = CatalogSync::default();
+                for (name, desc) in catalog.iter() {
+                    match existing.get(name) {
+                        // 多个实例同时启动时可能已经被其他实例插入，忽略唯一索引冲突
+                        None => {
+                            res.created += diesel::insert_or_ignore_into(auth_permission::table)
+                                .values(PermissionNew {
+                                    name: name.clone(),
+                                    desc: desc.clone(),
+                                })
+                                .execute(conn)?;
+                        }
+                        Some(old) if old != desc => {
+                            res.updated += diesel::update(auth_permission::table)
+                                .filter(auth_permission::name.eq(name))
+                                .set(auth_permission::desc.eq(desc))
+                                .execute(conn)?;
+                        }
+                        _ => (),
+                    }
+                }
+
+                let names: Vec<String> = catalog.into_iter().map(|(name, _)| name).collect();
+                res.orphaned = diesel::update(auth_permission::table)
+                    .filter(auth_permission::name.ne_all(names.clone()))
+                    .filter(auth_permission::orphaned.eq(false))
+                    .set(auth_permission::orphaned.eq(true))
+                    .execute(conn)?;
+                diesel::update(auth_permission::table)
+                    .filter(auth_permission::name.eq_any(names))
+                    .filter(auth_permission::orphaned.eq(true))
+                    .set(auth_permission::orphaned.eq(false))
+                    .execute(conn)?;
+                Ok(res)
+            })
+        })
+        .await??;
+        Ok(res)
+    }
+}
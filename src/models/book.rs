@@ -1,4 +1,7 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    utils::cache::{Cacheable, Cached},
+};
 use actix_web::web;
 use schema::*;
 
@@ -28,13 +31,17 @@ pub struct Book {
     pub name: String,
     pub price: BigDecimal,
     pub user_id: PK,
-    #[serde(rename(serialize = "type"))]
+    #[serde(rename = "type")]
     pub type_: u8,
     pub stock: u32,
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
 }
 
+impl Cacheable for Book {
+    const NAME: &'static str = "book";
+}
+
 impl Book {
     pub async fn create(db_pool: web::Data<DbPool>, data: BookNew) -> R<usize> {
         let new_rows: usize = web::block(move || -> R<_> {
@@ -48,7 +55,12 @@ impl Book {
         Ok(new_rows)
     }
 
+    /// 优先读取缓存，见 [crate::utils::cache]
     pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<Book> {
+        Cached::<Book>::get_or_load(pk, Self::load(db_pool, pk)).await
+    }
+
+    async fn load(db_pool: web::Data<DbPool>, pk: PK) -> R<Book> {
         let res: Book = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
             Ok(books::table.find(pk).first(&mut conn)?)
@@ -58,28 +70,87 @@ impl Book {
         Ok(res)
     }
 
-    pub async fn del(db_pool: web::Data<DbPool>, pk: PK) -> R<usize> {
+    /// 分页查询，默认按价格倒序
+    pub async fn page(db_pool: web::Data<DbPool>, page: PageQuery) -> R<Paginated<Book>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            let build = || -> R<_> {
+                Ok(page_filter!(books::table.into_boxed(), page, {
+                    "name" => books::name: String [Eq, Like],
+                    "user_id" => books::user_id: PK [Eq, In],
+                    "type" => books::type_: u8 [Eq, In],
+                    "price" => books::price: BigDecimal [Range],
+                    "create_at" => books::create_at: NaiveDateTime [Range],
+                }))
+            };
+
+            let total: i64 = build()?.count().get_result(&mut conn)?;
+            let mut q = build()?;
+            if page.sort.is_empty() {
+                q = q.order(books::price.desc());
+            }
+            let data = page_sort!(q, page, {
+                "id" => books::id,
+                "price" => books::price,
+                "stock" => books::stock,
+                "create_at" => books::create_at,
+            })
+            .then_order_by(books::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<Book>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page))
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 删除，owner 不为 None 时只能删除自己的书，见 [crate::serv::rbac::owner_scope]
+    pub async fn del(db_pool: web::Data<DbPool>, pk: PK, owner: Option<PK>) -> R<usize> {
         let rows: usize = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
+            Self::ensure_owner(&mut conn, pk, owner)?;
             Ok(diesel::delete(books::table)
                 .filter(books::id.eq(pk))
                 .execute(&mut conn)?)
         })
         .await??;
+        Cached::<Book>::invalidate(pk).await;
 
         Ok(rows)
     }
 
-    pub async fn update(db_pool: web::Data<DbPool>, pk: PK, data: BookUpdate) -> R<usize> {
+    /// 修改，owner 不为 None 时只能修改自己的书
+    pub async fn update(
+        db_pool: web::Data<DbPool>,
+        pk: PK,
+        owner: Option<PK>,
+        data: BookUpdate,
+    ) -> R<usize> {
         let rows: usize = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
+            Self::ensure_owner(&mut conn, pk, owner)?;
             let target = books::table.find(pk);
             Ok(diesel::update(target).set(&data).execute(&mut conn)?)
         })
         .await??;
+        Cached::<Book>::invalidate(pk).await;
 
         Ok(rows)
     }
+
+    /// 书不存在返回 NotFound，不是自己的返回 Forbidden
+    fn ensure_owner(conn: &mut MysqlConnection, pk: PK, owner: Option<PK>) -> R<()> {
+        let user_id: PK = books::table
+            .find(pk)
+            .select(books::user_id)
+            .first(conn)
+            .map_err(|e| db_not_found_err(e, "common.not_found"))?;
+        if owner.map_or(false, |x| x != user_id) {
+            bail!(ErrKind::Forbidden.err("auth.forbidden"));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Insertable, Validate, Deserialize, Debug)]
@@ -87,18 +158,21 @@ impl Book {
 pub struct BookNew {
     pub name: String,
     pub price: BigDecimal,
+
+    /// 当前登陆的用户
+    #[serde(skip_deserializing)]
     pub user_id: PK,
     #[serde(rename(deserialize = "type"))]
     pub type_: u8,
     pub stock: u32,
 }
 
-#[derive(AsChangeset, Identifiable, Validate, Deserialize, Debug)]
+/// 不能修改所属用户，id 使用路径中的 pk
+#[derive(AsChangeset, Validate, Deserialize, Debug)]
 #[diesel(table_name = books)]
 pub struct BookUpdate {
-    pub id: PK,
     pub name: Option<String>,
-    pub user_id: Option<PK>,
+    #[serde(rename(deserialize = "type"))]
     pub type_: Option<u8>,
     pub price: Option<BigDecimal>,
     pub stock: Option<u32>,
@@ -12,3 +12,54 @@ pub struct AdminGroup {
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
 }
+
+impl AdminGroup {
+    /// 给管理员分配组，已分配的忽略，id 不存在时返回 NotFound
+    pub async fn grant(db_pool: web::Data<DbPool>, admin_id: PK, group_ids: Vec<PK>) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let mut ids = group_ids;
+                ids.sort_unstable();
+                ids.dedup();
+
+                let owners: i64 = admin::table.find(admin_id).count().get_result(conn)?;
+                let items: i64 = auth_group::table
+                    .filter(auth_group::id.eq_any(&ids))
+                    .count()
+                    .get_result(conn)?;
+                if owners == 0 || items as usize != ids.len() {
+                    bail!(ErrKind::NotFound.err("common.not_found"));
+                }
+
+                let values = ids
+                    .into_iter()
+                    .map(|id| {
+                        (
+                            m2m_admin_group::admin_id.eq(admin_id),
+                            m2m_admin_group::group_id.eq(id),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Ok(diesel::insert_or_ignore_into(m2m_admin_group::table)
+                    .values(values)
+                    .execute(conn)?)
+            })
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 取消管理员的组
+    pub async fn revoke(db_pool: web::Data<DbPool>, admin_id: PK, group_ids: Vec<PK>) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(m2m_admin_group::table)
+                .filter(m2m_admin_group::admin_id.eq(admin_id))
+                .filter(m2m_admin_group::group_id.eq_any(group_ids))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+}
@@ -12,3 +12,62 @@ pub struct AdminPerm {
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
 }
+
+impl AdminPerm {
+    /// 给管理员分配权限，已分配的忽略，id 不存在时返回 NotFound
+    pub async fn grant(
+        db_pool: web::Data<DbPool>,
+        admin_id: PK,
+        permission_ids: Vec<PK>,
+    ) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let mut ids = permission_ids;
+                ids.sort_unstable();
+                ids.dedup();
+
+                let owners: i64 = admin::table.find(admin_id).count().get_result(conn)?;
+                let items: i64 = auth_permission::table
+                    .filter(auth_permission::id.eq_any(&ids))
+                    .count()
+                    .get_result(conn)?;
+                if owners == 0 || items as usize != ids.len() {
+                    bail!(ErrKind::NotFound.err("common.not_found"));
+                }
+
+                let values = ids
+                    .into_iter()
+                    .map(|id| {
+                        (
+                            m2m_admin_permission::admin_id.eq(admin_id),
+                            m2m_admin_permission::permission_id.eq(id),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Ok(diesel::insert_or_ignore_into(m2m_admin_permission::table)
+                    .values(values)
+                    .execute(conn)?)
+            })
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 取消管理员的权限
+    pub async fn revoke(
+        db_pool: web::Data<DbPool>,
+        admin_id: PK,
+        permission_ids: Vec<PK>,
+    ) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(m2m_admin_permission::table)
+                .filter(m2m_admin_permission::admin_id.eq(admin_id))
+                .filter(m2m_admin_permission::permission_id.eq_any(permission_ids))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+}
@@ -13,6 +13,65 @@ pub struct GroupPermission {
     pub update_at: NaiveDateTime,
 }
 
+impl GroupPermission {
+    /// 给组分配权限，已分配的忽略，id 不存在时返回 NotFound
+    pub async fn grant(
+        db_pool: web::Data<DbPool>,
+        group_id: PK,
+        permission_ids: Vec<PK>,
+    ) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let mut ids = permission_ids;
+                ids.sort_unstable();
+                ids.dedup();
+
+                let owners: i64 = auth_group::table.find(group_id).count().get_result(conn)?;
+                let items: i64 = auth_permission::table
+                    .filter(auth_permission::id.eq_any(&ids))
+                    .count()
+                    .get_result(conn)?;
+                if owners == 0 || items as usize != ids.len() {
+                    bail!(ErrKind::NotFound.err("common.not_found"));
+                }
+
+                let values = ids
+                    .into_iter()
+                    .map(|id| {
+                        (
+                            m2m_group_permission::group_id.eq(group_id),
+                            m2m_group_permission::permission_id.eq(id),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Ok(diesel::insert_or_ignore_into(m2m_group_permission::table)
+                    .values(values)
+                    .execute(conn)?)
+            })
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 取消组的权限
+    pub async fn revoke(
+        db_pool: web::Data<DbPool>,
+        group_id: PK,
+        permission_ids: Vec<PK>,
+    ) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(m2m_group_permission::table)
+                .filter(m2m_group_permission::group_id.eq(group_id))
+                .filter(m2m_group_permission::permission_id.eq_any(permission_ids))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+}
+
 #[derive(Insertable, Validate, Deserialize, PartialEq, Debug)]
 #[diesel(table_name = m2m_group_permission)]
 pub struct GroupPermissionNew {
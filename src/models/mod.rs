@@ -1,9 +1,14 @@
 pub mod admin;
 pub mod admin_log;
 pub mod auth_group;
+pub mod auth_log;
 pub mod auth_permission;
 pub mod book;
+pub mod order;
+pub mod password_history;
 pub mod post;
+pub mod spike_event;
+pub mod sys_setting;
 pub mod user;
 
 pub mod m2m_admin_group;
@@ -0,0 +1,448 @@
+use crate::{models::book::Book, prelude::*, utils::cache::Cached};
+use schema::*;
+
+/// 订单状态
+///
+/// 待支付 -> 已支付 -> 已退款
+///
+/// 待支付 -> 已取消(用户取消或超时未支付) -> 取消后支付 -> 已退款
+///
+/// 取消后才收到的支付通知记录为取消后支付，库存已经归还，需要在支付平台退款
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Created = 1,
+    Paid = 2,
+    Cancelled = 3,
+    Refunded = 4,
+    PaidAfterCancel = 5,
+}
+
+#[derive(Identifiable, Associations, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(belongs_to(models::user::User, foreign_key = user_id))]
+#[diesel(table_name = orders)]
+pub struct Order {
+    pub id: PK,
+    pub order_no: String,
+    pub user_id: PK,
+    pub amount: BigDecimal,
+
+    /// [OrderStatus]
+    pub status: u8,
+    pub pay_channel: String,
+    pub trade_no: String,
+    pub expire_at: NaiveDateTime,
+    pub paid_at: Option<NaiveDateTime>,
+    pub closed_at: Option<NaiveDateTime>,
+    pub create_at: NaiveDateTime,
+    pub update_at: NaiveDateTime,
+
+    /// 见 [OrderSource::request_id]
+    pub request_id: Option<String>,
+
+    /// 见 [OrderSource::spike_event_id]
+    pub spike_event_id: Option<PK>,
+}
+
+#[derive(Identifiable, Associations, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(belongs_to(Order, foreign_key = order_id))]
+#[diesel(table_name = order_items)]
+pub struct OrderItem {
+    pub id: PK,
+    pub order_id: PK,
+    pub book_id: PK,
+    pub name: String,
+    pub price: BigDecimal,
+    pub num: u32,
+    pub create_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrderDetail {
+    #[serde(flatten)]
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = orders)]
+struct NewOrder {
+    order_no: String,
+    user_id: PK,
+    amount: BigDecimal,
+    expire_at: NaiveDateTime,
+    request_id: Option<String>,
+    spike_event_id: Option<PK>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = order_items)]
+struct NewOrderItem {
+    order_id: PK,
+    book_id: PK,
+    name: String,
+    price: BigDecimal,
+    num: u32,
+}
+
+/// 下单的一项
+#[derive(Validate, Deserialize, Debug)]
+pub struct OrderItemNew {
+    pub book_id: PK,
+
+    #[validate(range(min = 1, max = 999))]
+    pub num: u32,
+
+    /// 秒杀等活动的价格，为 None 时使用书的价格
+    #[serde(skip_deserializing)]
+    pub price: Option<BigDecimal>,
+}
+
+/// 订单的来源，普通下单使用 default
+#[derive(Debug, Default)]
+pub struct OrderSource {
+    /// 客户端的请求id，同一个用户相同的 request_id 只创建一个订单，重复下单返回已有的订单
+    pub request_id: Option<String>,
+
+    /// 秒杀订单的活动，取消或退款时归还活动的库存和限购名额，见 [crate::serv::spike::release]
+    pub spike_event_id: Option<PK>,
+}
+
+/// 支付平台回调的支付结果
+#[derive(Debug)]
+pub struct Payment {
+    /// alipay wxpay
+    pub channel: &'static str,
+    pub order_no: String,
+    pub trade_no: String,
+    pub amount: BigDecimal,
+}
+
+impl Order {
+    /// 下单，在同一个事务中扣减库存，库存不足时全部回滚
+    ///
+    /// source 带有 request_id 时，已经存在的订单直接返回，不再扣减库存
+    pub async fn place(
+        db_pool: web::Data<DbPool>,
+        order_no: String,
+        user_id: PK,
+        items: Vec<OrderItemNew>,
+        expire_at: NaiveDateTime,
+        source: OrderSource,
+    ) -> R<OrderDetail> {
+        let book_ids = items.iter().map(|x| x.book_id).collect::<Vec<_>>();
+        let res = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                if let Some(request_id) = &source.request_id {
+                    let placed: Option<PK> = orders::table
+                        .filter(orders::user_id.eq(user_id))
+                        .filter(orders::request_id.eq(request_id))
+                        .select(orders::id)
+                        .for_update()
+                        .first(conn)
+                        .optional()?;
+                    if let Some(order_id) = placed {
+                        return Self::load_detail(conn, order_id);
+                    }
+                }
+
+                let mut amount = BigDecimal::from(0);
+                let mut new_items = Vec::with_capacity(items.len());
+                for item in items {
+                    let rows = diesel::update(
+                        books::table
+                            .find(item.book_id)
+                            .filter(books::stock.ge(item.num)),
+                    )
+                    .set(books::stock.eq(books::stock - item.num))
+                    .execute(conn)?;
+                    if rows == 0 {
+                        bail!(ErrKind::BadRequest
+                            .err("order.out_of_stock")
+                            .arg("book", item.book_id)
+                            .build());
+                    }
+
+                    let (name, book_price) = books::table
+                        .find(item.book_id)
+                        .select((books::name, books::price))
+                        .first::<(String, BigDecimal)>(conn)?;
+                    let price = item.price.unwrap_or(book_price);
+                    amount += &price * BigDecimal::from(item.num);
+                    new_items.push(NewOrderItem {
+                        order_id: 0,
+                        book_id: item.book_id,
+                        name,
+                        price,
+                        num: item.num,
+                    });
+                }
+
+                diesel::insert_into(orders::table)
+                    .values(NewOrder {
+                        order_no,
+                        user_id,
+                        amount,
+                        expire_at,
+                        request_id: source.request_id,
+                        spike_event_id: source.spike_event_id,
+                    })
+                    .execute(conn)?;
+                let order_id: PK = diesel::dsl::select(last_insert_id()).get_result(conn)?;
+
+                for item in new_items.iter_mut() {
+                    item.order_id = order_id;
+                }
+                diesel::insert_into(order_items::table)
+                    .values(&new_items)
+                    .execute(conn)?;
+
+                Self::load_detail(conn, order_id)
+            })
+        })
+        .await??;
+        Cached::<Book>::invalidate_many(&book_ids).await;
+        Ok(res)
+    }
+
+    /// 订单和明细
+    pub async fn detail(db_pool: web::Data<DbPool>, pk: PK) -> R<OrderDetail> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Self::load_detail(&mut conn, pk)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    pub async fn obj_by_no(db_pool: web::Data<DbPool>, order_no: String) -> R<Order> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(orders::table
+                .filter(orders::order_no.eq(order_no))
+                .first(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 分页查询，user_id 不为 None 时只查询这个用户的订单
+    pub async fn page(
+        db_pool: web::Data<DbPool>,
+        page: PageQuery,
+        user_id: Option<PK>,
+    ) -> R<Paginated<Order>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            let build = || -> R<_> {
+                let mut q = orders::table.into_boxed();
+                if let Some(user_id) = user_id {
+                    q = q.filter(orders::user_id.eq(user_id));
+                }
+                Ok(page_filter!(q, page, {
+                    "order_no" => orders::order_no: String [Eq],
+                    "user_id" => orders::user_id: PK [Eq, In],
+                    "status" => orders::status: u8 [Eq, In],
+                    "pay_channel" => orders::pay_channel: String [Eq],
+                    "create_at" => orders::create_at: NaiveDateTime [Range],
+                }))
+            };
+
+            let total: i64 = build()?.count().get_result(&mut conn)?;
+            let data = page_sort!(build()?, page, {
+                "id" => orders::id,
+                "amount" => orders::amount,
+                "create_at" => orders::create_at,
+                "paid_at" => orders::paid_at,
+            })
+            .then_order_by(orders::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<Order>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page))
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 取消待支付的订单并归还库存，user_id 不为 None 时只能取消这个用户的订单
+    ///
+    /// 订单已经不是待支付时返回 None，超时取消的任务可以重复执行
+    pub async fn cancel(
+        db_pool: web::Data<DbPool>,
+        order_no: String,
+        user_id: Option<PK>,
+    ) -> R<Option<OrderDetail>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let order = Self::lock(conn, &order_no)?;
+                if user_id.map_or(false, |x| x != order.user_id) {
+                    bail!(ErrKind::NotFound.err("common.not_found"));
+                }
+                if order.status != OrderStatus::Created as u8 {
+                    return Ok(None);
+                }
+                Self::close(conn, &order, OrderStatus::Cancelled)?;
+                Ok(Some(Self::load_detail(conn, order.id)?))
+            })
+        })
+        .await??;
+        if let Some(detail) = &res {
+            Self::closed(detail).await;
+        }
+        Ok(res)
+    }
+
+    /// 超时未支付的订单号，按过期时间排序
+    pub async fn overdue(db_pool: web::Data<DbPool>, limit: i64) -> R<Vec<String>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(orders::table
+                .filter(orders::status.eq(OrderStatus::Created as u8))
+                .filter(orders::expire_at.lt(current_timestamp()))
+                .order(orders::expire_at.asc())
+                .select(orders::order_no)
+                .limit(limit)
+                .load::<String>(&mut conn)?)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 退款，只修改订单状态并归还库存，需要先在支付平台退款
+    ///
+    /// 取消后支付的订单库存已经归还，返回的 bool 为这次是否归还了库存
+    pub async fn refund(db_pool: web::Data<DbPool>, pk: PK) -> R<(OrderDetail, bool)> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let order_no: String = orders::table
+                    .find(pk)
+                    .select(orders::order_no)
+                    .first(conn)
+                    .map_err(|e| db_not_found_err(e, "common.not_found"))?;
+                let order = Self::lock(conn, &order_no)?;
+                let restocked = match order.status {
+                    x if x == OrderStatus::Paid as u8 => {
+                        Self::close(conn, &order, OrderStatus::Refunded)?;
+                        true
+                    }
+                    x if x == OrderStatus::PaidAfterCancel as u8 => {
+                        diesel::update(orders::table.find(order.id))
+                            .set((
+                                orders::status.eq(OrderStatus::Refunded as u8),
+                                orders::closed_at.eq(current_timestamp().nullable()),
+                            ))
+                            .execute(conn)?;
+                        false
+                    }
+                    _ => bail!(Self::invalid_status(&order)),
+                };
+                Ok((Self::load_detail(conn, pk)?, restocked))
+            })
+        })
+        .await??;
+        if res.1 {
+            Self::closed(&res.0).await;
+        }
+        Ok(res)
+    }
+
+    /// 支付成功，金额必须和订单一致
+    ///
+    /// 返回修改后的状态，已取消的订单记录支付并改为 [OrderStatus::PaidAfterCancel]，等待退款。
+    /// 同一笔交易重复回调时返回 None
+    pub async fn mark_paid(db_pool: web::Data<DbPool>, payment: Payment) -> R<Option<OrderStatus>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                let order = Self::lock(conn, &payment.order_no)?;
+                let recorded = order.status == OrderStatus::Paid as u8
+                    || order.status == OrderStatus::PaidAfterCancel as u8;
+                if recorded
+                    && order.pay_channel == payment.channel
+                    && order.trade_no == payment.trade_no
+                {
+                    return Ok(None);
+                }
+                let status = match order.status {
+                    x if x == OrderStatus::Created as u8 => OrderStatus::Paid,
+                    x if x == OrderStatus::Cancelled as u8 => OrderStatus::PaidAfterCancel,
+                    _ => bail!(Self::invalid_status(&order)),
+                };
+                if order.amount != payment.amount {
+                    bail!(ErrKind::BadRequest
+                        .err("order.amount_mismatch")
+                        .arg("order_no", &order.order_no)
+                        .build());
+                }
+
+                diesel::update(orders::table.find(order.id))
+                    .set((
+                        orders::status.eq(status as u8),
+                        orders::pay_channel.eq(payment.channel),
+                        orders::trade_no.eq(&payment.trade_no),
+                        orders::paid_at.eq(current_timestamp().nullable()),
+                    ))
+                    .execute(conn)?;
+                Ok(Some(status))
+            })
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 加行锁读取订单，状态修改都在锁内进行
+    fn lock(conn: &mut MysqlConnection, order_no: &str) -> R<Order> {
+        Ok(orders::table
+            .filter(orders::order_no.eq(order_no))
+            .for_update()
+            .first(conn)
+            .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+    }
+
+    /// 取消或退款，归还库存，书已经删除的跳过
+    fn close(conn: &mut MysqlConnection, order: &Order, status: OrderStatus) -> R<()> {
+        diesel::update(orders::table.find(order.id))
+            .set((
+                orders::status.eq(status as u8),
+                orders::closed_at.eq(current_timestamp().nullable()),
+            ))
+            .execute(conn)?;
+
+        let items = order_items::table
+            .filter(order_items::order_id.eq(order.id))
+            .select((order_items::book_id, order_items::num))
+            .load::<(PK, u32)>(conn)?;
+        for (book_id, num) in items {
+            diesel::update(books::table.find(book_id))
+                .set(books::stock.eq(books::stock + num))
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /// 事务提交后，删除库存变化的书的缓存
+    async fn closed(detail: &OrderDetail) {
+        let book_ids = detail.items.iter().map(|x| x.book_id).collect::<Vec<_>>();
+        Cached::<Book>::invalidate_many(&book_ids).await;
+    }
+
+    fn load_detail(conn: &mut MysqlConnection, pk: PK) -> R<OrderDetail> {
+        let order: Order = orders::table
+            .find(pk)
+            .first(conn)
+            .map_err(|e| db_not_found_err(e, "common.not_found"))?;
+        let items = OrderItem::belonging_to(&order).load::<OrderItem>(conn)?;
+        Ok(OrderDetail { order, items })
+    }
+
+    fn invalid_status(order: &Order) -> OkErr {
+        ErrKind::BadRequest
+            .err("order.invalid_status")
+            .arg("order_no", &order.order_no)
+            .build()
+    }
+}
@@ -0,0 +1,77 @@
+use crate::prelude::*;
+use schema::*;
+
+/// 用过的密码，不能重复使用最近 PWD_HISTORY 次的密码
+#[derive(Identifiable, Queryable, Serialize, Debug)]
+#[diesel(table_name = password_history)]
+pub struct PasswordHistory {
+    pub id: PK,
+
+    /// [Audience]
+    pub scope: String,
+    pub principal_id: PK,
+
+    #[serde(skip_serializing)]
+    pub password: String,
+    pub create_at: NaiveDateTime,
+}
+
+impl PasswordHistory {
+    /// 最近用过的密码，最新的在前
+    pub async fn recent(
+        db_pool: web::Data<DbPool>,
+        aud: Audience,
+        pk: PK,
+        limit: i64,
+    ) -> R<Vec<String>> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(password_history::table
+                .filter(password_history::scope.eq(aud.as_str()))
+                .filter(password_history::principal_id.eq(pk))
+                .order(password_history::id.desc())
+                .limit(limit)
+                .select(password_history::password)
+                .load::<String>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    /// 记录新密码，只保留最近 keep 条
+    pub async fn add(
+        db_pool: web::Data<DbPool>,
+        aud: Audience,
+        pk: PK,
+        password: String,
+        keep: i64,
+    ) -> R<()> {
+        web::block(move || -> R<()> {
+            let mut conn = conn!(&db_pool);
+            diesel::insert_into(password_history::table)
+                .values((
+                    password_history::scope.eq(aud.as_str()),
+                    password_history::principal_id.eq(pk),
+                    password_history::password.eq(password),
+                ))
+                .execute(&mut conn)?;
+
+            let expired: Vec<PK> = password_history::table
+                .filter(password_history::scope.eq(aud.as_str()))
+                .filter(password_history::principal_id.eq(pk))
+                .order(password_history::id.desc())
+                .offset(keep)
+                .limit(100)
+                .select(password_history::id)
+                .load(&mut conn)?;
+            if !expired.is_empty() {
+                diesel::delete(password_history::table)
+                    .filter(password_history::id.eq_any(expired))
+                    .execute(&mut conn)?;
+            }
+            Ok(())
+        })
+        .await??;
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
 use crate::prelude::*;
+use diesel::{
+    dsl::sql,
+    sql_types::{Bool, Double, Text},
+};
 use schema::*;
 
 #[derive(Identifiable, Associations, Queryable, Serialize, Deserialize, Debug)]
@@ -12,3 +16,172 @@ pub struct Post {
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
 }
+
+/// 作者的公开信息
+#[derive(Queryable, Serialize, Deserialize, Debug)]
+pub struct Author {
+    pub id: PK,
+    pub username: Option<String>,
+    pub avatar: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PostWithAuthor {
+    #[serde(flatten)]
+    pub post: Post,
+    pub author: Author,
+}
+
+impl From<(Post, Author)> for PostWithAuthor {
+    fn from((post, author): (Post, Author)) -> Self {
+        Self { post, author }
+    }
+}
+
+#[derive(Insertable, Validate, Deserialize, Debug)]
+#[diesel(table_name = posts)]
+pub struct PostNew {
+    #[validate(length(min = 1, max = 200))]
+    pub title: String,
+
+    #[validate(length(min = 1, max = 20000))]
+    pub content: String,
+
+    /// 当前登陆的用户
+    #[serde(skip_deserializing)]
+    pub user_id: PK,
+}
+
+#[derive(AsChangeset, Validate, Deserialize, Debug)]
+#[diesel(table_name = posts)]
+pub struct PostUpdate {
+    #[validate(length(min = 1, max = 200))]
+    pub title: Option<String>,
+
+    #[validate(length(min = 1, max = 20000))]
+    pub content: Option<String>,
+}
+
+/// 作者的字段
+type AuthorColumns = (users::id, users::username, users::avatar);
+const AUTHOR_COLUMNS: AuthorColumns = (users::id, users::username, users::avatar);
+
+/// 全文搜索 title 和 content，使用 ft_title_content 索引
+const MATCH_AGAINST: &str = "MATCH (`posts`.`title`, `posts`.`content`) AGAINST (";
+
+impl Post {
+    pub async fn create(db_pool: web::Data<DbPool>, data: PostNew) -> R<PK> {
+        let new_id = web::block(move || -> R<PK> {
+            let mut conn = conn!(&db_pool);
+            diesel::insert_into(posts::table)
+                .values(data)
+                .execute(&mut conn)?;
+            let id: PK = diesel::dsl::select(last_insert_id()).get_result::<PK>(&mut conn)?;
+            Ok(id)
+        })
+        .await??;
+        Ok(new_id)
+    }
+
+    pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<Post> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(posts::table
+                .find(pk)
+                .first(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+        })
+        .await??;
+        Ok(data)
+    }
+
+    /// 帖子和作者
+    pub async fn obj_with_author(db_pool: web::Data<DbPool>, pk: PK) -> R<PostWithAuthor> {
+        let data = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(posts::table
+                .inner_join(users::table)
+                .filter(posts::id.eq(pk))
+                .select((posts::all_columns, AUTHOR_COLUMNS))
+                .first::<(Post, Author)>(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+        })
+        .await??;
+        Ok(data.into())
+    }
+
+    pub async fn update(db_pool: web::Data<DbPool>, pk: PK, data: PostUpdate) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::update(posts::table.find(pk))
+                .set(&data)
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    pub async fn del(db_pool: web::Data<DbPool>, pk: PK) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(posts::table.find(pk)).execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+
+    /// 分页查询，包含作者
+    ///
+    /// search 不为空时全文搜索 title 和 content，没有指定排序时按相关度排序
+    pub async fn page(
+        db_pool: web::Data<DbPool>,
+        page: PageQuery,
+        search: Option<String>,
+    ) -> R<Paginated<PostWithAuthor>> {
+        let search = search.filter(|x| !x.trim().is_empty());
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            let build = || -> R<_> {
+                let mut q = posts::table
+                    .inner_join(users::table)
+                    .select((posts::all_columns, AUTHOR_COLUMNS))
+                    .into_boxed();
+                if let Some(ref search) = search {
+                    q = q.filter(
+                        sql::<Bool>(MATCH_AGAINST)
+                            .bind::<Text, _>(search.clone())
+                            .sql(" IN NATURAL LANGUAGE MODE)"),
+                    );
+                }
+                Ok(page_filter!(q, page, {
+                    "user_id" => posts::user_id: PK [Eq, In],
+                    "title" => posts::title: String [Like],
+                    "create_at" => posts::create_at: NaiveDateTime [Range],
+                }))
+            };
+
+            let total: i64 = build()?.count().get_result(&mut conn)?;
+            let mut q = build()?;
+            if let (Some(ref search), true) = (&search, page.sort.is_empty()) {
+                q = q.order(
+                    sql::<Double>(MATCH_AGAINST)
+                        .bind::<Text, _>(search.clone())
+                        .sql(" IN NATURAL LANGUAGE MODE)")
+                        .desc(),
+                );
+            }
+            let data = page_sort!(q, page, {
+                "id" => posts::id,
+                "create_at" => posts::create_at,
+                "update_at" => posts::update_at,
+            })
+            .then_order_by(posts::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<(Post, Author)>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page).map(PostWithAuthor::from))
+        })
+        .await??;
+        Ok(res)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::prelude::*;
+use schema::*;
+
+#[derive(Identifiable, Associations, Queryable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(belongs_to(models::book::Book, foreign_key = book_id))]
+#[diesel(table_name = spike_events)]
+pub struct SpikeEvent {
+    pub id: PK,
+    pub title: String,
+    pub book_id: PK,
+    pub price: BigDecimal,
+    pub stock: u32,
+    pub per_user_limit: u32,
+    pub begin_at: NaiveDateTime,
+    pub end_at: NaiveDateTime,
+    pub create_at: NaiveDateTime,
+    pub update_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Validate, Deserialize, Debug)]
+#[diesel(table_name = spike_events)]
+pub struct SpikeEventNew {
+    #[validate(length(min = 1, max = 50))]
+    pub title: String,
+    pub book_id: PK,
+    pub price: BigDecimal,
+
+    #[validate(range(min = 1))]
+    pub stock: u32,
+
+    #[validate(range(min = 1, max = 999))]
+    pub per_user_limit: u32,
+    pub begin_at: NaiveDateTime,
+    pub end_at: NaiveDateTime,
+}
+
+impl SpikeEvent {
+    pub async fn create(db_pool: web::Data<DbPool>, data: SpikeEventNew) -> R<SpikeEvent> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                diesel::insert_into(spike_events::table)
+                    .values(data)
+                    .execute(conn)?;
+                let id: PK = diesel::dsl::select(last_insert_id()).get_result(conn)?;
+                Ok(spike_events::table.find(id).first(conn)?)
+            })
+        })
+        .await??;
+        Ok(res)
+    }
+
+    pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<SpikeEvent> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(spike_events::table
+                .find(pk)
+                .first(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 未结束的活动，按开始时间排序
+    pub async fn active(db_pool: web::Data<DbPool>) -> R<Vec<SpikeEvent>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            Ok(spike_events::table
+                .filter(spike_events::end_at.gt(current_timestamp()))
+                .order(spike_events::begin_at.asc())
+                .load::<SpikeEvent>(&mut conn)?)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 分页查询，见 [crate::utils::page]
+    pub async fn page(db_pool: web::Data<DbPool>, page: PageQuery) -> R<Paginated<SpikeEvent>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            let build = || -> R<_> {
+                Ok(page_filter!(spike_events::table.into_boxed(), page, {
+                    "title" => spike_events::title: String [Eq, Like],
+                    "book_id" => spike_events::book_id: PK [Eq, In],
+                    "begin_at" => spike_events::begin_at: NaiveDateTime [Range],
+                    "end_at" => spike_events::end_at: NaiveDateTime [Range],
+                }))
+            };
+
+            let total: i64 = build()?.count().get_result(&mut conn)?;
+            let data = page_sort!(build()?, page, {
+                "id" => spike_events::id,
+                "begin_at" => spike_events::begin_at,
+                "end_at" => spike_events::end_at,
+            })
+            .then_order_by(spike_events::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<SpikeEvent>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page))
+        })
+        .await??;
+        Ok(res)
+    }
+
+    pub async fn del(db_pool: web::Data<DbPool>, pk: PK) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = db_pool.get()?;
+            Ok(diesel::delete(spike_events::table.find(pk)).execute(&mut conn)?)
+        })
+        .await??;
+        Ok(rows)
+    }
+}
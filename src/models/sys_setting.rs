@@ -0,0 +1,48 @@
+use crate::prelude::*;
+use schema::*;
+
+/// 超级管理员设置，所有管理员必须开启两步验证，值为 "1" 或 "0"
+pub const ADMIN_FORCE_2FA: &str = "admin_force_2fa";
+
+/// 系统设置，运行时可以修改的开关，不需要重启服务
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = sys_setting, primary_key(name))]
+pub struct SysSetting {
+    pub name: String,
+    pub value: String,
+    pub update_at: NaiveDateTime,
+}
+
+impl SysSetting {
+    /// 获取设置，不存在时返回 None
+    pub async fn get(db_pool: web::Data<DbPool>, name: &'static str) -> R<Option<String>> {
+        let value = web::block(move || -> R<_> {
+            let mut conn = conn!(&db_pool);
+            Ok(sys_setting::table
+                .find(name)
+                .select(sys_setting::value)
+                .first::<String>(&mut conn)
+                .optional()?)
+        })
+        .await??;
+        Ok(value)
+    }
+
+    /// 开关类的设置，"1" 为开启
+    pub async fn flag(db_pool: web::Data<DbPool>, name: &'static str) -> R<bool> {
+        Ok(Self::get(db_pool, name).await?.as_deref() == Some("1"))
+    }
+
+    /// 保存设置，不存在时创建
+    pub async fn set(db_pool: web::Data<DbPool>, name: &'static str, value: String) -> R<()> {
+        web::block(move || -> R<()> {
+            let mut conn = conn!(&db_pool);
+            diesel::replace_into(sys_setting::table)
+                .values((sys_setting::name.eq(name), sys_setting::value.eq(value)))
+                .execute(&mut conn)?;
+            Ok(())
+        })
+        .await??;
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    utils::cache::{Cacheable, Cached},
+};
 use actix_web::web;
 use schema::*;
 
@@ -26,17 +29,25 @@ pub struct User {
     pub email: Option<String>,
     pub username: Option<String>,
 
-    #[serde(skip_serializing)]
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
+    #[serde(skip_serializing, default)]
+    #[validate(length(min = 3, max = 20, message = "validation.password_len"))]
     pub password: Option<String>,
 
-    #[validate(custom(function = "vf::phone", message = "手机号格式错误"))]
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
     pub phone: Option<String>,
     pub avatar: Option<String>,
     pub is_active: bool,
     pub last_login: Option<NaiveDateTime>,
     pub create_at: NaiveDateTime,
     pub update_at: NaiveDateTime,
+
+    /// 保存的语言，见 [crate::middleware::I18n]
+    pub locale: Option<String>,
+}
+
+/// 密码不缓存，需要时使用 [User::obj_with_secrets]
+impl Cacheable for User {
+    const NAME: &'static str = "user";
 }
 
 impl User {
@@ -62,19 +73,80 @@ impl User {
         Ok(data)
     }
 
-    /// 使用pk获取用户
+    /// 分页查询
+    pub async fn page(db_pool: web::Data<DbPool>, page: PageQuery) -> R<Paginated<User>> {
+        let res = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            let build = || -> R<_> {
+                Ok(page_filter!(users::table.into_boxed(), page, {
+                    "user_type" => users::user_type: u8 [Eq, In],
+                    "email" => users::email: String [Eq, Like],
+                    "username" => users::username: String [Eq, Like],
+                    "phone" => users::phone: String [Eq, Like],
+                    "is_active" => users::is_active: bool [Eq],
+                    "last_login" => users::last_login: NaiveDateTime [Range],
+                    "create_at" => users::create_at: NaiveDateTime [Range],
+                }))
+            };
+
+            let total: i64 = build()?.count().get_result(&mut conn)?;
+            let data = page_sort!(build()?, page, {
+                "id" => users::id,
+                "username" => users::username,
+                "last_login" => users::last_login,
+                "create_at" => users::create_at,
+            })
+            .then_order_by(users::id.desc())
+            .limit(page.limit)
+            .offset(page.offset())
+            .load::<User>(&mut conn)?;
+            Ok(Paginated::new(data, total, &page))
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// 使用pk获取用户，优先读取缓存，见 [crate::utils::cache]
+    ///
+    /// 不包括密码，是否读取缓存都一样
     pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<User> {
+        let load = async {
+            let mut x = Self::load(db_pool, pk).await?;
+            x.password = None;
+            Ok(x)
+        };
+        Cached::<User>::get_or_load(pk, load).await
+    }
+
+    /// 从数据库读取，包括密码，只在验证时使用
+    pub async fn obj_with_secrets(db_pool: web::Data<DbPool>, pk: PK) -> R<User> {
+        Self::load(db_pool, pk).await
+    }
+
+    async fn load(db_pool: web::Data<DbPool>, pk: PK) -> R<User> {
         let user: User = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
             Ok(users::table
                 .find(pk)
                 .first(&mut conn)
-                .map_err(|e| db_not_found_err(e, "未找到"))?)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
         })
         .await??;
         Ok(user)
     }
 
+    /// 使用多个pk获取用户
+    pub async fn objs(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<Vec<User>> {
+        let data: Vec<User> = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(users::table
+                .filter(users::id.eq_any(pks))
+                .load::<User>(&mut conn)?)
+        })
+        .await??;
+        Ok(data)
+    }
+
     /// 使用pk删除用户
     pub async fn del(db_pool: web::Data<DbPool>, pk: PK) -> R<usize> {
         let rows: usize = web::block(move || -> R<_> {
@@ -84,11 +156,13 @@ impl User {
                 .execute(&mut conn)?)
         })
         .await??;
+        Cached::<User>::invalidate(pk).await;
         Ok(rows)
     }
 
     /// 使用pk删除多个用户
     pub async fn del_many(db_pool: web::Data<DbPool>, pks: Vec<PK>) -> R<usize> {
+        let ids = pks.clone();
         let rows: usize = web::block(move || -> R<_> {
             let mut conn = db_pool.get()?;
             Ok(diesel::delete(users::table)
@@ -96,6 +170,7 @@ impl User {
                 .execute(&mut conn)?)
         })
         .await??;
+        Cached::<User>::invalidate_many(&ids).await;
         Ok(rows)
     }
 
@@ -106,7 +181,20 @@ impl User {
             Ok(users::table
                 .filter(users::username.eq(&username))
                 .first(&mut conn)
-                .map_err(|e| db_not_found_err(e, "账号或密码错误！"))?)
+                .map_err(|e| db_not_found_as(e, ErrKind::Auth, "auth.bad_credentials"))?)
+        })
+        .await??;
+        Ok(user)
+    }
+
+    /// 使用邮箱查找
+    pub async fn obj_with_email(db_pool: web::Data<DbPool>, email: String) -> R<User> {
+        let user: User = web::block(move || -> R<_> {
+            let mut conn = db_pool.get()?;
+            Ok(users::table
+                .filter(users::email.eq(&email))
+                .first(&mut conn)
+                .map_err(|e| db_not_found_err(e, "common.not_found"))?)
         })
         .await??;
         Ok(user)
@@ -119,13 +207,30 @@ impl User {
             Ok(users::table
                 .filter(users::phone.eq(&phone))
                 .first(&mut conn)
-                .map_err(|e| db_not_found_err(e, "未找到手机号！"))?)
+                .map_err(|e| db_not_found_err(e, "auth.phone_not_found"))?)
         })
         .await??;
         Ok(user)
     }
 
     /// 更新最后登陆时间
+    /// 保存语言，None 时使用请求的语言
+    pub async fn set_locale(
+        db_pool: web::Data<DbPool>,
+        pk: PK,
+        locale: Option<String>,
+    ) -> R<usize> {
+        let rows = web::block(move || -> R<usize> {
+            let mut conn = conn!(db_pool);
+            Ok(diesel::update(users::table.find(pk))
+                .set(users::locale.eq(locale))
+                .execute(&mut conn)?)
+        })
+        .await??;
+        Cached::<User>::invalidate(pk).await;
+        Ok(rows)
+    }
+
     pub async fn update_last_login(db_pool: web::Data<DbPool>, pk: PK) -> R<()> {
         web::block(move || -> R<()> {
             let mut conn = conn!(db_pool);
@@ -134,12 +239,13 @@ impl User {
                 .execute(&mut conn)?;
 
             if rows == 0 {
-                bail!("更新登陆时间失败");
+                bail!("user.update_login_failed");
             }
 
             Ok(())
         })
         .await??;
+        Cached::<User>::invalidate(pk).await;
         Ok(())
     }
 
@@ -154,7 +260,7 @@ impl User {
             diesel::insert_into(users::table)
                 .values(user_form)
                 .execute(&mut conn)
-                .map_err(|e| db_unique_err(e, "注册失败"))?;
+                .map_err(|e| db_unique_err(e, "user.register_failed"))?;
             let id: PK = diesel::dsl::select(last_insert_id()).get_result::<PK>(&mut conn)?;
             Ok(id)
         })
@@ -170,7 +276,7 @@ impl User {
             diesel::insert_into(users::table)
                 .values(user_form)
                 .execute(&mut conn)
-                .map_err(|e| db_unique_err(e, "手机号已注册"))?;
+                .map_err(|e| db_unique_err(e, "user.phone_registered"))?;
             let id: PK = diesel::dsl::select(last_insert_id()).get_result::<PK>(&mut conn)?;
             Ok(id)
         })
@@ -182,12 +288,27 @@ impl User {
 #[derive(AsChangeset, Serialize, Deserialize, Validate, Debug)]
 #[diesel(table_name = users)]
 pub struct UpdateUser {
-    #[validate(custom(function = "vf::email", message = "邮箱格式错误"))]
+    #[validate(custom(function = "vf::email", message = "validation.email"))]
+    pub email: Option<String>,
+
+    pub username: Option<String>,
+
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
+    pub phone: Option<String>,
+
+    pub avatar: Option<String>,
+}
+
+/// 管理员修改用户，可以禁用账号
+#[derive(AsChangeset, Serialize, Deserialize, Validate, Debug)]
+#[diesel(table_name = users)]
+pub struct ManageUser {
+    #[validate(custom(function = "vf::email", message = "validation.email"))]
     pub email: Option<String>,
 
     pub username: Option<String>,
 
-    #[validate(custom(function = "vf::phone", message = "手机号格式错误"))]
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
     pub phone: Option<String>,
 
     pub avatar: Option<String>,
@@ -201,7 +322,7 @@ pub struct NewPhoneUser {
     #[serde(default = "UserType::phone")]
     pub user_type: u8,
 
-    #[validate(custom(function = "vf::phone", message = "手机号格式错误"))]
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
     pub phone: String,
 
     pub avatar: Option<String>,
@@ -216,7 +337,7 @@ pub struct NewAccountUser {
 
     pub username: String,
 
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
+    #[validate(custom = "vf::password")]
     pub password: String,
 
     pub avatar: Option<String>,
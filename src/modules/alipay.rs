@@ -1,45 +1,21 @@
 extern crate openssl;
 use crate::prelude::*;
 
+use models::order::Payment;
 use openssl::{
-    base64, hash::MessageDigest, nid::Nid, pkey::PKey, rsa::Rsa, sign::Signer, x509::X509,
+    base64,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Public},
+    rsa::Rsa,
+    sign::{Signer, Verifier},
+    x509::X509,
 };
 
-/// 支付宝商户id
-const MERCHANT_PID: &str = env!("ALI_MERCHANT_PID");
-
-/// 应用id
-const APPID: &str = env!("ALI_APPID");
-
-/// 支付宝网关地址
-const GATEWAY_ADDR: &str = env!("ALI_GATEWAY_ADDR");
-
-/// 接口内容加密方式
-const CONTENT_ENCRYPT: &str = env!("ALI_CONTENT_ENCRYPT");
-
-/// 应用公钥证书
-const PUBLIC_KEY_CERT: &str = include_str!(concat!(
-    env!("CARGO_MANIFEST_DIR"),
-    env!("ALI_PUBLIC_KEY_CERT"),
-));
-
-/// 应用私钥
-const PRIVATE_KEY: &str = env!("ALI_PRIVATE_KEY");
-
-/// 支付宝公钥（注：在公钥模式获取这个，证书模式不需要）
-const ALIPAY_PUBLIC_KEY: &str = env!("ALI_ALIPAY_PUBLIC_KEY");
-
-/// 支付宝公钥证书
-const ALIPAY_PUBLIC_KEY_CERT: &str = include_str!(concat!(
-    env!("CARGO_MANIFEST_DIR"),
-    env!("ALI_ALIPAY_PUBLIC_KEY_CERT"),
-));
-
-/// 支付宝根证书
-const ALIPAY_ROOT_CERT: &str = include_str!(concat!(
-    env!("CARGO_MANIFEST_DIR"),
-    env!("ALI_ALIPAY_ROOT_CERT"),
-));
+/// 读取证书文件，路径来自配置
+fn read_cert(path: &str) -> R<String> {
+    std::fs::read_to_string(path).map_err(|e| anyhow!("读取证书 {} 失败: {}", path, e))
+}
 
 /// 从 X.509 公钥证书格式中，获取此证书的颁发者名称
 ///
@@ -89,7 +65,7 @@ impl<'a> Default for AlipayConfig<'a> {
         let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
         Self {
             public_params: HashMap::from([
-                ("app_id", APPID.into()),
+                ("app_id", conf!().ali.appid.clone()),
                 ("method", "alipay.trade.page.pay".into()),
                 ("charset", "UTF-8".into()),
                 ("sign_type", "RSA2".into()),
@@ -143,11 +119,12 @@ impl<'a> AlipayConfig<'a> {
     /// 使用 证书模式
     pub fn cert(&mut self) -> R<&mut AlipayConfig<'a>> {
         // 获取证书序列号
-        let app_cert_sn = get_cert_md5(PUBLIC_KEY_CERT.as_ref())?;
+        let ali_config = &conf!().ali;
+        let app_cert_sn = get_cert_md5(read_cert(&ali_config.public_key_cert)?.as_ref())?;
         self.public_params.insert("app_cert_sn", app_cert_sn);
 
         // 获取根证书序列号
-        let alipay_root_cert_sn: String = ALIPAY_ROOT_CERT
+        let alipay_root_cert_sn: String = read_cert(&ali_config.alipay_root_cert)?
             .split_inclusive("-----END CERTIFICATE-----")
             .filter(|cert| {
                 let ssl = X509::from_pem(cert.as_ref()).unwrap();
@@ -187,7 +164,7 @@ impl<'a> AlipayConfig<'a> {
     /// rsa256签名
     fn rsa2_sign(&self, content: &[u8]) -> R<String> {
         // 解码 DER 编码的 PKCS#1 RSAPrivateKey 结构
-        let cert_content = base64::decode_block(PRIVATE_KEY)?;
+        let cert_content = base64::decode_block(&conf!().ali.private_key)?;
         let rsa = Rsa::private_key_from_der(cert_content.as_ref())?;
 
         // 创建一个包含 RSA 密钥的新 PKey
@@ -220,7 +197,7 @@ impl<'a> AlipayConfig<'a> {
         // 返回html表单，让前端渲染执行表单中的post发起请求
         let form_html_str = format!(
             r#"<form name="submit_form" method="post" action="{action}"><input type="submit" value="提交" style="display:none" ></form><script>document.forms[0].submit();</script>"#,
-            action = format!("{}?{}", GATEWAY_ADDR, qs_data_encode)
+            action = format!("{}?{}", conf!().ali.gateway_addr, qs_data_encode)
         );
         Ok(form_html_str)
 
@@ -245,11 +222,14 @@ pub async fn trade_page_pay(
         .cert()?
         .biz_content(biz_content)
         .return_url("https://baidu.com");
+    if !conf!().ali.notify_url.is_empty() {
+        alipay_config.notify_url(&conf!().ali.notify_url);
+    }
 
     let form_html_str = alipay_config.build()?;
 
     // let body: web::Bytes = clint
-    //     .post(GATEWAY_ADDR)
+    //     .post(&conf!().ali.gateway_addr)
     //     .content_type("application/x-www-form-urlencoded;charset=utf-8")
     //     .send_body(form_html_str)
     //     .await
@@ -260,3 +240,69 @@ pub async fn trade_page_pay(
 
     Ok(form_html_str)
 }
+
+/// 支付宝公钥，公钥模式使用 ALI_ALIPAY_PUBLIC_KEY，证书模式从 ALI_ALIPAY_PUBLIC_KEY_CERT 读取
+fn alipay_public_key() -> R<PKey<Public>> {
+    let ali_config = &conf!().ali;
+    if !ali_config.alipay_public_key.is_empty() {
+        let der = base64::decode_block(&ali_config.alipay_public_key)?;
+        return Ok(PKey::public_key_from_der(&der)?);
+    }
+    let cert = read_cert(&ali_config.alipay_public_key_cert)?;
+    Ok(X509::from_pem(cert.as_ref())?.public_key()?)
+}
+
+/// 支付结果异步通知
+///
+/// https://opendocs.alipay.com/open/203/105286
+pub struct Notify;
+
+impl serv::order::PayNotify for Notify {
+    fn verify(&self, _req: &HttpRequest, body: &[u8]) -> R<Option<Payment>> {
+        let params: HashMap<String, String> =
+            url::form_urlencoded::parse(body).into_owned().collect();
+        let param = |key: &str| {
+            params
+                .get(key)
+                .cloned()
+                .ok_or_else(|| anyhow!("支付宝通知缺少参数: {}", key))
+        };
+
+        // 除去 sign、sign_type，按 key 排序后拼接，使用支付宝公钥验证签名
+        let mut keys = params
+            .keys()
+            .filter(|k| *k != "sign" && *k != "sign_type")
+            .collect::<Vec<_>>();
+        keys.sort();
+        let content = keys
+            .iter()
+            .map(|k| format!("{}={}", k, params[*k]))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let public_key = alipay_public_key()?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+        verifier.update(content.as_bytes())?;
+        if !verifier.verify(&base64::decode_block(&param("sign")?)?)? {
+            bail!("支付宝通知签名错误");
+        }
+        if param("app_id")? != conf!().ali.appid {
+            bail!("支付宝通知的 app_id 不一致");
+        }
+
+        match param("trade_status")?.as_str() {
+            "TRADE_SUCCESS" | "TRADE_FINISHED" => (),
+            _ => return Ok(None),
+        }
+        Ok(Some(Payment {
+            channel: "alipay",
+            order_no: param("out_trade_no")?,
+            trade_no: param("trade_no")?,
+            amount: param("total_amount")?.parse()?,
+        }))
+    }
+
+    fn reply(&self, ok: bool) -> HttpResponse {
+        HttpResponse::Ok().body(if ok { "success" } else { "failure" })
+    }
+}
@@ -1,3 +1,5 @@
+pub mod sms;
+
 #[cfg(feature = "wx")]
 pub mod wx;
 
@@ -0,0 +1,165 @@
+/**
+ * 短信发送
+ *
+ * SMS_PROVIDER 选择实现:
+ *
+ * - `console` 只打印到日志，开发环境使用
+ * - `http` POST json 到 SMS_URL，可以对接短信平台或本地 mock
+ *
+ * 不要在请求中直接发送，使用 [crate::queues::sms::push] 放入队列
+ */
+use crate::{
+    config::{SmsConfig, SmsProvider},
+    prelude::*,
+    utils::i18n::{self, Locale},
+};
+use futures_util::future::LocalBoxFuture;
+use std::{fmt::Display, str::FromStr};
+
+/// 短信用途，每种用途使用不同的模板，验证码也不能混用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsPurpose {
+    Register,
+    Login,
+    ResetPassword,
+}
+
+impl Default for SmsPurpose {
+    fn default() -> Self {
+        Self::Login
+    }
+}
+
+impl SmsPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Register => "register",
+            Self::Login => "login",
+            Self::ResetPassword => "reset_password",
+        }
+    }
+
+    /// 短信平台的模板id
+    fn template_id<'a>(&self, config: &'a SmsConfig) -> &'a str {
+        match self {
+            Self::Register => &config.template_register,
+            Self::Login => &config.template_login,
+            Self::ResetPassword => &config.template_reset_password,
+        }
+    }
+}
+
+impl FromStr for SmsPurpose {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "register" => Ok(Self::Register),
+            "login" => Ok(Self::Login),
+            "reset_password" => Ok(Self::ResetPassword),
+            _ => Err(format!("{}，可选值 register login reset_password", s)),
+        }
+    }
+}
+
+impl Display for SmsPurpose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// 一条验证码短信
+#[derive(Debug, Clone)]
+pub struct SmsMessage {
+    pub phone: String,
+    pub purpose: SmsPurpose,
+    pub code: String,
+    pub locale: Locale,
+}
+
+impl SmsMessage {
+    /// 短信内容，消息目录中的 sms.{purpose}
+    pub fn content(&self) -> String {
+        i18n::tf(
+            self.locale,
+            &format!("sms.{}", self.purpose),
+            &[("code", &self.code)],
+        )
+    }
+}
+
+/// 短信发送，awc 不是 Send，所以返回 LocalBoxFuture
+pub trait SmsSender {
+    fn send<'a>(&'a self, msg: &'a SmsMessage) -> LocalBoxFuture<'a, R<()>>;
+}
+
+/// 按配置创建
+pub fn sender(config: &SmsConfig) -> Box<dyn SmsSender> {
+    match config.provider {
+        SmsProvider::Console => Box::new(ConsoleSms),
+        SmsProvider::Http => Box::new(HttpSms {
+            client: awc::Client::default(),
+            config: config.clone(),
+        }),
+    }
+}
+
+/// 打印到日志
+pub struct ConsoleSms;
+
+impl SmsSender for ConsoleSms {
+    fn send<'a>(&'a self, msg: &'a SmsMessage) -> LocalBoxFuture<'a, R<()>> {
+        Box::pin(async move {
+            log::info!("[sms] {} {}: {}", msg.phone, msg.purpose, msg.content());
+            Ok(())
+        })
+    }
+}
+
+/// POST json 到 SMS_URL，返回 2xx 即为成功
+///
+/// ```json
+/// {
+///   "phone": "15281414664",
+///   "sign_name": "ab",
+///   "template_id": "SMS_001",
+///   "params": { "code": "1234" },
+///   "content": "您的登陆验证码是 1234，请勿泄露给他人"
+/// }
+/// ```
+pub struct HttpSms {
+    client: awc::Client,
+    config: SmsConfig,
+}
+
+impl SmsSender for HttpSms {
+    fn send<'a>(&'a self, msg: &'a SmsMessage) -> LocalBoxFuture<'a, R<()>> {
+        Box::pin(async move {
+            let mut res = self
+                .client
+                .post(&self.config.url)
+                .insert_header(("Authorization", format!("Bearer {}", self.config.api_key)))
+                .timeout(std::time::Duration::from_secs(10))
+                .send_json(&json!({
+                    "phone": msg.phone,
+                    "sign_name": self.config.sign_name,
+                    "template_id": msg.purpose.template_id(&self.config),
+                    "params": { "code": msg.code },
+                    "content": msg.content(),
+                }))
+                .await
+                .map_err(eany)?;
+
+            if !res.status().is_success() {
+                let body = res.body().await.unwrap_or_default();
+                bail!(
+                    "短信发送失败: {} {}",
+                    res.status(),
+                    String::from_utf8_lossy(&body)
+                );
+            }
+            Ok(())
+        })
+    }
+}
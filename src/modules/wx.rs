@@ -1,17 +1,12 @@
 use crate::prelude::*;
 use ser::wx::*;
 
-pub static APP_ID: &str = env!("WX_APP_ID");
-pub static APPSECRET: &str = env!("WX_APPSECRET");
-pub static TOKEN: &str = env!("WX_TOKEN");
-pub static ENCODING_AES_KEY: &str = env!("WX_ENCODING_AES_KEY");
-
 /// 验证消息的确来自微信服务器
 ///
 /// https://developers.weixin.qq.com/doc/offiaccount/Basic_Information/Access_Overview.html
 pub fn validation_message(query: &ValidationMessage) -> bool {
     // 排序
-    let mut tmp_arr: Vec<&str> = vec![TOKEN, &query.timestamp, &query.nonce];
+    let mut tmp_arr: Vec<&str> = vec![&conf!().wx.token, &query.timestamp, &query.nonce];
     tmp_arr.sort();
 
     // 拼接字符串
@@ -34,8 +29,10 @@ pub async fn get_access_token(redis_pool: &RedisPool, client: &awc::Client) -> R
     Ok(match access_token {
         Some(access_token) => access_token,
         _ => {
+            let app_id = &conf!().wx.app_id;
+            let appsecret = &conf!().wx.appsecret;
             let body : web::Bytes = client.get(format!(
-                "https://api.weixin.qq.com/cgi-bin/token?grant_type=client_credential&appid={APP_ID}&secret={APPSECRET}"
+                "https://api.weixin.qq.com/cgi-bin/token?grant_type=client_credential&appid={app_id}&secret={appsecret}"
             ))
             .send()
             .await.map_err(eany)?
@@ -113,16 +110,18 @@ pub async fn get_oauth_access_token(
 
     // 通过redis的递增来获取id
     // TODO: 从数据库中创建新用户，然后获取id
-    let user_id: PK = con.incr(concat!(env!("APP_NAME"), ":wx:userid"), 1).await?;
+    let user_id: PK = con.incr(format!("{}:wx:userid", conf!().app_name), 1).await?;
 
     // 每个用户的key都不同
-    let key = &format!("{}:wx:oauth:{}", env!("APP_NAME"), user_id);
+    let key = &format!("{}:wx:oauth:{}", conf!().app_name, user_id);
 
     let access_token: Option<String> = con.hget(key, "access_token").await?;
 
     if access_token.is_none() {
+        let app_id = &conf!().wx.app_id;
+        let appsecret = &conf!().wx.appsecret;
         let body : web::Bytes = client.get(&format!(
-                  "https://api.weixin.qq.com/sns/oauth2/access_token?appid={APP_ID}&secret={APPSECRET}&code={code}&grant_type=authorization_code"))
+                  "https://api.weixin.qq.com/sns/oauth2/access_token?appid={app_id}&secret={appsecret}&code={code}&grant_type=authorization_code"))
                   .send()
                   .await.map_err(eany)?
                   .body()
@@ -212,7 +211,7 @@ pub async fn get_jssdk_config(
     let sign: String = sha1!(tmp_str.as_ref());
 
     return Ok(JsSdkConfigResult {
-        app_id: APP_ID.into(),
+        app_id: conf!().wx.app_id.clone(),
         timestamp,
         nonce_str,
         signature: sign,
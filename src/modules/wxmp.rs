@@ -1,9 +1,6 @@
 use crate::prelude::*;
 use actix_web::web::Bytes;
 
-pub static APP_ID: &str = env!("WXMP_APP_ID");
-pub static APPSECRET: &str = env!("WXMP_APPSECRET");
-
 #[derive(Debug, Deserialize)]
 pub struct MpLogin {
     pub code: String,
@@ -82,8 +79,8 @@ pub struct Watermark {
 pub async fn login(redis_pool: &RedisPool, client: &awc::Client, code: &str) -> R<PK> {
     let body : Bytes = client.get(format!(
         "https://api.weixin.qq.com/sns/jscodesession?appid={appid}&secret={secret}&js_code={js_code}&grant_type=authorization_code",
-        appid = APP_ID,
-        secret = APPSECRET,
+        appid = conf!().wxmp.app_id,
+        secret = conf!().wxmp.appsecret,
         js_code = code,
 
     ))
@@ -96,7 +93,7 @@ pub async fn login(redis_pool: &RedisPool, client: &awc::Client, code: &str) ->
 
     // TODO: 模拟的userid
     let user_id: u64 = con.incr("wxmp:userid", 1).await?;
-    let key = &format!("{}:wxmp:session:{}", env!("APP_NAME"), user_id);
+    let key = &format!("{}:wxmp:session:{}", conf!().app_name, user_id);
 
     let res: Code2SessionResult = serde_json::from_slice(&body)?;
 
@@ -128,8 +125,10 @@ pub async fn get_access_token(redis_pool: &RedisPool, client: &awc::Client) -> R
     Ok(match access_token {
         Some(access_token) => access_token,
         _ => {
+            let app_id = &conf!().wxmp.app_id;
+            let appsecret = &conf!().wxmp.appsecret;
             let body : Bytes = client.get(format!(
-                "https://api.weixin.qq.com/cgi-bin/token?grant_type=client_credential&appid={APP_ID}&secret={APPSECRET}"
+                "https://api.weixin.qq.com/cgi-bin/token?grant_type=client_credential&appid={app_id}&secret={appsecret}"
             ))
             .send()
             .await.map_err(eany)?
@@ -0,0 +1,92 @@
+/**
+ * 管理员接口需要的权限
+ *
+ * 权限在这里声明，启动时同步到 auth_permission，分配给管理员或组后才能访问，见 [crate::serv::rbac]。
+ * 新增接口只需要在这里加一行，路由使用 `wrap` 指定:
+ *
+ * ```
+ * #[http_post("del", wrap = "perm::ADMIN_DELETE")]
+ * pub async fn del(...) -> HttpResult {}
+ * ```
+ *
+ * 打印权限目录: `server permissions`
+ */
+use crate::middleware::RequirePermission;
+
+/// 权限目录中的一项
+#[derive(Debug, Clone, Copy)]
+pub struct PermissionDef {
+    pub name: &'static str,
+    pub desc: &'static str,
+}
+
+/// 声明权限，同时生成 [CATALOG]
+macro_rules! permissions {
+    ( $( $ident:ident = $name:literal, $desc:literal; )* ) => {
+        $(
+            #[doc = $desc]
+            pub const $ident: RequirePermission = RequirePermission($name);
+        )*
+
+        /// 代码中声明的所有权限
+        pub const CATALOG: &[PermissionDef] = &[
+            $( PermissionDef { name: $name, desc: $desc }, )*
+        ];
+    };
+}
+
+permissions! {
+    ADMIN_LIST = "admin.list", "管理员列表";
+    ADMIN_VIEW = "admin.view", "查看管理员";
+    ADMIN_CREATE = "admin.create", "创建管理员";
+    ADMIN_UPDATE = "admin.update", "修改管理员";
+    ADMIN_DELETE = "admin.delete", "删除管理员";
+    ADMIN_GRANT = "admin.grant", "给管理员分配组和权限";
+
+    ADMIN_LOG_LIST = "admin_log.list", "操作日志列表";
+    ADMIN_LOG_EXPORT = "admin_log.export", "导出操作日志";
+
+    USER_LIST = "user.list", "用户列表";
+    USER_VIEW = "user.view", "查看用户";
+    USER_UPDATE = "user.update", "修改、禁用用户";
+    USER_DELETE = "user.delete", "删除用户";
+
+    POST_UPDATE = "post.update", "修改任意用户的帖子";
+    POST_DELETE = "post.delete", "删除任意用户的帖子";
+
+    BOOK_UPDATE = "book.update", "修改任意用户的书";
+    BOOK_DELETE = "book.delete", "删除任意用户的书";
+
+    ORDER_LIST = "order.list", "订单列表";
+    ORDER_VIEW = "order.view", "查看订单";
+    ORDER_REFUND = "order.refund", "订单退款";
+
+    SPIKE_LIST = "spike.list", "秒杀活动列表";
+    SPIKE_CREATE = "spike.create", "创建秒杀活动";
+    SPIKE_DELETE = "spike.delete", "删除秒杀活动";
+
+    GROUP_LIST = "group.list", "组列表";
+    GROUP_VIEW = "group.view", "查看组";
+    GROUP_CREATE = "group.create", "创建组";
+    GROUP_UPDATE = "group.update", "修改组";
+    GROUP_DELETE = "group.delete", "删除组";
+    GROUP_GRANT = "group.grant", "给组分配权限";
+
+    PERMISSION_LIST = "permission.list", "权限列表";
+    PERMISSION_VIEW = "permission.view", "查看权限";
+    PERMISSION_CREATE = "permission.create", "创建权限";
+    PERMISSION_UPDATE = "permission.update", "修改权限";
+    PERMISSION_DELETE = "permission.delete", "删除权限";
+}
+
+/// 打印权限目录，`server permissions`
+pub fn print_catalog() {
+    let width = CATALOG
+        .iter()
+        .map(|x| x.name.len())
+        .max()
+        .unwrap_or_default();
+    for def in CATALOG {
+        println!("{:width$}  {}", def.name, def.desc, width = width);
+    }
+}
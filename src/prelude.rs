@@ -35,8 +35,8 @@ pub type HttpResult = actix_web::Result<HttpResponse>;
 pub type HttpBody<T> = web::Either<web::Json<T>, web::Form<T>>;
 
 pub use crate::utils::{
-    error::{db_not_found_err, db_unique_err, eany, ej, es, OkErr},
-    jwt::Claims,
+    error::{db_not_found_as, db_not_found_err, db_unique_err, eany, ej, es, ErrKind, OkErr},
+    jwt::{Audience, Claims},
     valid as vf,
 };
 
@@ -45,10 +45,12 @@ pub use itertools::izip;
 /// 数据库主键的类型
 pub type PK = u64;
 
+pub use crate::extract::{AuthAdmin, AuthUser};
 pub use crate::models;
 pub use crate::schema;
 pub use crate::ser;
 pub use crate::serv;
+pub use crate::utils::page::{PageQuery, Paginated};
 
 #[cfg(feature = "wx")]
 pub use crate::modules::wx;
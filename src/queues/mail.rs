@@ -1,4 +1,10 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    utils::{
+        i18n::{self, Locale},
+        worker::Workers,
+    },
+};
 use askama::Template;
 use deadpool_redis::Connection;
 use lettre::{
@@ -9,21 +15,17 @@ use lettre::{
 use redis::streams::{StreamId, StreamKey, StreamReadOptions, StreamReadReply};
 use tokio::{
     sync::mpsc::{self, Sender},
-    time::{sleep, Duration},
+    time::Duration,
 };
 
 /// 队列名
-const KEY: &str = concat!(env!("APP_NAME"), ":queue:mail");
+fn key() -> String {
+    format!("{}:queue:mail", conf!().app_name)
+}
 
 /// 消费者组名
 const GROUP_NAME: &str = "g1";
 
-/// 邮箱配置
-const MAIL_FROM: &str = env!("MAIL_FROM");
-const MAIL_USER: &str = env!("MAIL_USER");
-const MAIL_PWD: &str = env!("MAIL_PWD");
-const MAIL_HOST: &str = env!("MAIL_HOST");
-
 #[derive(Debug)]
 enum ErrMsg {
     /// 重试
@@ -36,6 +38,32 @@ enum ErrMsg {
     },
 }
 
+/// 放入发送队列，template 为 hello reset_password
+pub async fn push(
+    redis_pool: &RedisPool,
+    to: &str,
+    subject: &str,
+    link: &str,
+    template: &str,
+    locale: Locale,
+) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let _: String = con
+        .xadd(
+            key(),
+            "*",
+            &[
+                ("to", to),
+                ("subject", subject),
+                ("link", link),
+                ("template", template),
+                ("lang", locale.tag()),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
 /// 不要传递过多的消息体，应该在消费时才进行组装
 ///
 /// ```
@@ -47,6 +75,8 @@ enum ErrMsg {
 ///         ("to", "ajanuw1995@gmail.com"),
 ///         ("subject", "Happy new year"),
 ///         ("link", "http://192.168.17.131:7777"),
+///         ("lang", "en"), // 可选，邮件内容的语言，默认 DEFAULT_LOCALE
+///         ("template", "hello"), // 可选，邮件模板，默认 hello
 ///     ],
 /// )
 /// .await
@@ -56,7 +86,7 @@ enum ErrMsg {
 /// ```
 /// XADD ab:mail * to ajanuw1995@gmail.com subject title link https://baidu.com
 /// ```
-pub fn init(redis_pool: RedisPool) {
+pub fn init(redis_pool: RedisPool, workers: &Workers) {
     let redis_pool = Arc::new(redis_pool);
 
     // 创建一个最大容量为 32 的新通道
@@ -71,43 +101,83 @@ pub fn init(redis_pool: RedisPool) {
             .block(5000);
 
         let c_redis_pool = Arc::clone(&redis_pool);
+        let mut hb = workers.register(&format!("mail:{}", consumer_name));
 
         // 处理最新的消息
         tokio::spawn(async move {
-            let mut con = c_redis_pool.get().await.unwrap();
-            let _: Result<(), _> = con.xgroup_create_mkstream(KEY, GROUP_NAME, "$").await;
+            let key = key();
+            let mut con = match super::connect(&c_redis_pool, &mut hb, &key, GROUP_NAME).await {
+                Some(c) => c,
+                None => return,
+            };
 
-            loop {
-                let read_reply: StreamReadReply =
-                    match con.xread_options(&[KEY], &[">"], &opts).await {
-                        Ok(x) => x,
-                        Err(_) => continue,
-                    };
+            // 收到停机信号后不再读取新消息，当前消息处理完(ack 或交给重试)再退出
+            while !hb.is_shutdown() {
+                hb.beat();
+                let read_reply: StreamReadReply = match con
+                    .xread_options(&[&key], &[">"], &opts)
+                    .await
+                {
+                    Ok(x) => x,
+                    Err(err) => {
+                        // 连接可能已经失效(如 redis 重启)，重新获取
+                        log::error!("邮件队列读取失败，重新连接: {}", err);
+                        hb.sleep(Duration::from_secs(1)).await;
+                        con = match super::connect(&c_redis_pool, &mut hb, &key, GROUP_NAME).await {
+                            Some(c) => c,
+                            None => return,
+                        };
+                        continue;
+                    }
+                };
 
                 if !read_reply.keys.is_empty() {
                     handle_msg(&mut con, read_reply, &consumer_name, &c_tx).await;
                 }
 
-                sleep(Duration::from_millis(100)).await;
+                hb.sleep(Duration::from_millis(100)).await;
             }
         });
     }
 
-    // 处理错误消息
+    // 处理错误消息，停机时未处理的消息留在 pending-list 中，不会丢失
+    let mut hb = workers.register("mail:retry");
     tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
+        let key = key();
+        while !hb.is_shutdown() {
+            hb.beat();
+            let msg = tokio::select! {
+                msg = rx.recv() => msg,
+                _ = hb.sleep(Duration::from_millis(1000)) => continue,
+            };
+            let msg = match msg {
+                Some(m) => m,
+                None => break,
+            };
+
             use ErrMsg::Retry;
             match msg {
                 Retry { id, consumer_name } => {
-                    let mut con = redis_pool.get().await.unwrap();
+                    let mut con = match redis_pool.get().await {
+                        Ok(c) => c,
+                        Err(err) => {
+                            log::error!("邮件重试获取 redis 连接失败: {} {}", id, err);
+                            hb.sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    };
                     log::info!("{} {}", id, consumer_name);
 
                     let pmsg: Vec<Vec<(String, String, i64, i64)>> = match con
-                        .xpending_consumer_count(KEY, GROUP_NAME, &id, "+", 1, &consumer_name)
+                        .xpending_consumer_count(&key, GROUP_NAME, &id, "+", 1, &consumer_name)
                         .await
                     {
                         Ok(m) => m,
-                        Err(_) => continue,
+                        Err(err) => {
+                            log::error!("邮件重试读取 pending-list 失败: {} {}", id, err);
+                            hb.sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
                     };
 
                     // pending-list 为空
@@ -122,7 +192,7 @@ pub fn init(redis_pool: RedisPool) {
                     if *retry_count >= 3 {
                         // TODO: 将多次不成功的消息发送到其他队列或存数据库手动处理或直接删除
                         log::error!("{}消费了{}次，强制ack", &id, retry_count);
-                        let _: usize = con.xack(KEY, GROUP_NAME, &[id]).await.unwrap();
+                        let _: usize = con.xack(&key, GROUP_NAME, &[id]).await.unwrap();
                         continue;
                     }
 
@@ -130,7 +200,7 @@ pub fn init(redis_pool: RedisPool) {
 
                     if create_ms == 0 {
                         log::error!("错误的消息id: {}", &id);
-                        let _: usize = con.xdel(KEY, &[id]).await.unwrap();
+                        let _: usize = con.xdel(&key, &[id]).await.unwrap();
                         continue;
                     }
 
@@ -139,11 +209,19 @@ pub fn init(redis_pool: RedisPool) {
                         .count(1)
                         .block(5000);
                     let read_reply: StreamReadReply = match con
-                        .xread_options(&[KEY], &[format!("{}-{}", create_ms - 1, create_ms)], &opts)
+                        .xread_options(
+                            &[&key],
+                            &[format!("{}-{}", create_ms - 1, create_ms)],
+                            &opts,
+                        )
                         .await
                     {
                         Ok(x) => x,
-                        Err(_) => continue,
+                        Err(err) => {
+                            log::error!("邮件重试读取消息失败: {} {}", id, err);
+                            hb.sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
                     };
 
                     if !read_reply.keys.is_empty() {
@@ -168,6 +246,44 @@ fn get_msg_args(msg: &StreamId) -> Result<(String, String, String), ()> {
 
 struct HelloTemplate {
     link: String,
+    title: String,
+}
+
+/// 重置密码
+#[derive(Template)]
+#[template(path = "mail/reset_password.html")]
+struct ResetPasswordTemplate {
+    link: String,
+    title: String,
+    tip: String,
+}
+
+/// 按消息中的 template 渲染邮件
+fn render(template: &str, link: String, locale: Locale) -> askama::Result<String> {
+    match template {
+        "reset_password" => ResetPasswordTemplate {
+            link,
+            title: i18n::t(locale, "mail.reset_password.title"),
+            tip: i18n::t(locale, "mail.reset_password.tip"),
+        }
+        .render(),
+        _ => HelloTemplate {
+            link,
+            title: i18n::t(locale, "mail.hello.title"),
+        }
+        .render(),
+    }
+}
+
+/// 交给重试处理，停机后重试通道已关闭，消息留在 pending-list 中
+async fn send_retry(tx: &Sender<ErrMsg>, id: &str, consumer_name: &str) {
+    let msg = ErrMsg::Retry {
+        id: id.to_owned(),
+        consumer_name: consumer_name.into(),
+    };
+    if tx.send(msg).await.is_err() {
+        log::warn!("重试通道已关闭: {}", id);
+    }
 }
 
 async fn handle_msg(
@@ -186,12 +302,24 @@ async fn handle_msg(
                     continue;
                 }
                 Ok((to, subject, link)) => {
-                    let hello = HelloTemplate { link };
-                    let html = hello.render().unwrap();
+                    let mail_config = &conf!().mail;
+                    let locale: Locale = msg
+                        .get::<String>("lang")
+                        .and_then(|x| x.parse().ok())
+                        .unwrap_or_default();
+                    let template = msg.get::<String>("template").unwrap_or_default();
+                    let html = match render(&template, link, locale) {
+                        Ok(html) => html,
+                        Err(err) => {
+                            log::error!("渲染邮件模板错误，删除消息: {} {}", &id, err);
+                            let _: usize = con.xdel(&key, &[id]).await.unwrap();
+                            continue;
+                        }
+                    };
 
                     let email = match Message::builder()
-                        .from(MAIL_FROM.parse().unwrap())
-                        .reply_to(MAIL_FROM.parse().unwrap())
+                        .from(mail_config.from.parse().unwrap())
+                        .reply_to(mail_config.from.parse().unwrap())
                         .to(to.parse().unwrap())
                         .subject(subject)
                         // .body(body) // 发送纯文本
@@ -211,32 +339,26 @@ async fn handle_msg(
                         Ok(m) => m,
                         Err(err) => {
                             log::error!("Message::builder 错误: {} {}", &id, err);
-                            tx.send(ErrMsg::Retry {
-                                id: id.clone(),
-                                consumer_name: consumer_name.into(),
-                            })
-                            .await
-                            .unwrap();
+                            send_retry(tx, id, consumer_name).await;
                             continue;
                         }
                     };
 
                     // 打开到 mail 的远程连接
-                    let mailer = match AsyncSmtpTransport::<Tokio1Executor>::relay(MAIL_HOST) {
-                        Ok(m) => m,
-                        Err(err) => {
-                            log::error!("AsyncSmtpTransport::relay 错误: {} {}", &id, err);
-                            tx.send(ErrMsg::Retry {
-                                id: id.clone(),
-                                consumer_name: consumer_name.into(),
-                            })
-                            .await
-                            .unwrap();
-                            continue;
+                    let mailer =
+                        match AsyncSmtpTransport::<Tokio1Executor>::relay(&mail_config.host) {
+                            Ok(m) => m,
+                            Err(err) => {
+                                log::error!("AsyncSmtpTransport::relay 错误: {} {}", &id, err);
+                                send_retry(tx, id, consumer_name).await;
+                                continue;
+                            }
                         }
-                    }
-                    .credentials(Credentials::from((MAIL_USER, MAIL_PWD)))
-                    .build();
+                        .credentials(Credentials::from((
+                            mail_config.user.as_str(),
+                            mail_config.pwd.as_str(),
+                        )))
+                        .build();
 
                     // 发送邮件
                     match mailer.send(email).await {
@@ -245,12 +367,7 @@ async fn handle_msg(
                             let _: usize = con.xack(&key, GROUP_NAME, &[id]).await.unwrap();
                         }
                         Err(err) => {
-                            tx.send(ErrMsg::Retry {
-                                id: id.clone(),
-                                consumer_name: consumer_name.into(),
-                            })
-                            .await
-                            .unwrap();
+                            send_retry(tx, id, consumer_name).await;
                             log::error!("发送邮件失败: {:?}", err);
                         }
                     }
@@ -1,2 +1,44 @@
+use crate::{prelude::*, utils::worker::WorkerHandle};
+use tokio::time::Duration;
+
 #[cfg(feature = "mail")]
 pub mod mail;
+
+pub mod sms;
+pub mod spike;
+
+/// 重新连接的最长间隔，单位秒
+const RECONNECT_MAX_SECS: u64 = 30;
+
+/// 消费者获取 redis 连接并创建消费者组，失败时等待后重试(间隔翻倍)，收到停机信号时返回 None
+///
+/// redis 重启后连接失效，读取失败时丢弃旧连接重新调用
+pub async fn connect(
+    redis_pool: &RedisPool,
+    hb: &mut WorkerHandle,
+    key: &str,
+    group: &str,
+) -> Option<deadpool_redis::Connection> {
+    let mut wait = 1;
+    while !hb.is_shutdown() {
+        hb.beat();
+        match redis_pool.get().await {
+            Ok(mut con) => {
+                // 消费者组已存在时返回错误，忽略
+                let _: Result<(), _> = con.xgroup_create_mkstream(key, group, "$").await;
+                return Some(con);
+            }
+            Err(err) => {
+                log::error!(
+                    "队列 {} 获取 redis 连接失败，{}秒后重试: {}",
+                    key,
+                    wait,
+                    err
+                );
+                hb.sleep(Duration::from_secs(wait)).await;
+                wait = (wait * 2).min(RECONNECT_MAX_SECS);
+            }
+        }
+    }
+    None
+}
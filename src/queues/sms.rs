@@ -0,0 +1,148 @@
+use crate::{
+    modules::sms::{self, SmsMessage, SmsPurpose},
+    prelude::*,
+    utils::{i18n::Locale, worker::Workers},
+};
+use deadpool_redis::Connection;
+use redis::streams::{StreamId, StreamReadOptions, StreamReadReply};
+use tokio::time::Duration;
+
+/// 队列名
+fn key() -> String {
+    format!("{}:queue:sms", conf!().app_name)
+}
+
+/// 消费者组名
+const GROUP_NAME: &str = "g1";
+
+/// 消费者名，只有一个消费者
+const CONSUMER_NAME: &str = "c1";
+
+/// 每条消息最多发送的次数
+const MAX_ATTEMPTS: u64 = 3;
+
+/// 放入发送队列
+///
+/// ```
+/// XADD ab:queue:sms * phone 15281414664 purpose login code 1234 lang zh-CN
+/// ```
+pub async fn push(redis_pool: &RedisPool, msg: &SmsMessage) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let _: String = con
+        .xadd(
+            key(),
+            "*",
+            &[
+                ("phone", msg.phone.as_str()),
+                ("purpose", msg.purpose.as_str()),
+                ("code", msg.code.as_str()),
+                ("lang", msg.locale.tag()),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// 启动消费者
+///
+/// 发送失败时间隔 1s、2s 重试，验证码很快过期，超过有效期的消息直接丢弃
+///
+/// 重启后先处理上次未 ack 的消息(pending-list)，再读取新消息
+///
+/// awc 不是 Send，使用 actix 的 spawn 在当前线程运行
+pub fn init(redis_pool: RedisPool, workers: &Workers) {
+    let mut hb = workers.register("sms");
+
+    actix_web::rt::spawn(async move {
+        let key = key();
+        let sender = sms::sender(&conf!().sms);
+        let opts = StreamReadOptions::default()
+            .group(GROUP_NAME, CONSUMER_NAME)
+            .count(10)
+            .block(5000);
+
+        let mut con = match redis_pool.get().await {
+            Ok(c) => c,
+            Err(err) => {
+                log::error!("sms 队列获取 redis 连接失败: {}", err);
+                return;
+            }
+        };
+        let _: Result<(), _> = con.xgroup_create_mkstream(&key, GROUP_NAME, "$").await;
+
+        // "0" 读取 pending-list，读完后切换到 ">" 读取新消息
+        let mut cursor = "0";
+        while !hb.is_shutdown() {
+            hb.beat();
+            let read_reply: StreamReadReply =
+                match con.xread_options(&[&key], &[cursor], &opts).await {
+                    Ok(x) => x,
+                    Err(err) => {
+                        log::error!("sms 队列读取失败: {}", err);
+                        hb.sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+            let ids = read_reply
+                .keys
+                .into_iter()
+                .flat_map(|x| x.ids)
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                cursor = ">";
+                continue;
+            }
+
+            for msg in &ids {
+                handle_msg(&mut con, sender.as_ref(), &key, msg).await;
+            }
+        }
+    });
+}
+
+fn get_msg(msg: &StreamId) -> Option<SmsMessage> {
+    Some(SmsMessage {
+        phone: msg.get("phone")?,
+        purpose: msg.get::<String>("purpose")?.parse::<SmsPurpose>().ok()?,
+        code: msg.get("code")?,
+        locale: msg
+            .get::<String>("lang")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or_else(Locale::default),
+    })
+}
+
+async fn handle_msg(con: &mut Connection, sender: &dyn sms::SmsSender, key: &str, msg: &StreamId) {
+    let id = &msg.id;
+    let create_ms: i64 = redis_extract_create_ms!(id);
+    let age = Utc::now().timestamp_millis() - create_ms;
+
+    match get_msg(msg) {
+        None => log::error!("短信消息参数错误，删除消息: {}", id),
+        Some(_) if age > (serv::auth::PHONE_CODE_TTL * 1000) as i64 => {
+            log::warn!("短信验证码已过期，不再发送: {}", id)
+        }
+        Some(sms) => {
+            for attempt in 1..=MAX_ATTEMPTS {
+                match sender.send(&sms).await {
+                    Ok(_) => {
+                        log::info!("短信发送成功: {} {}", id, sms.purpose);
+                        break;
+                    }
+                    Err(err) if attempt < MAX_ATTEMPTS => {
+                        log::warn!("短信发送失败，第{}次: {} {}", attempt, id, err);
+                        tokio::time::sleep(Duration::from_secs(attempt)).await;
+                    }
+                    Err(err) => {
+                        // TODO: 多次不成功的消息发送到其他队列或存数据库手动处理
+                        log::error!("短信发送{}次都失败，放弃: {} {}", attempt, id, err);
+                    }
+                }
+            }
+        }
+    }
+
+    let _: Result<usize, _> = con.xack(key, GROUP_NAME, &[id]).await;
+    let _: Result<usize, _> = con.xdel(key, &[id]).await;
+}
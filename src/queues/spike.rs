@@ -0,0 +1,107 @@
+use crate::{
+    prelude::*,
+    serv::spike::{self, Entry},
+    utils::worker::Workers,
+};
+use redis::streams::{StreamId, StreamReadOptions, StreamReadReply};
+use tokio::time::Duration;
+
+/// 队列名，抢购成功的记录由 [crate::serv::spike] 的 lua 脚本写入
+pub fn key() -> String {
+    format!("{}:queue:spike", conf!().app_name)
+}
+
+/// 消费者组名
+const GROUP_NAME: &str = "g1";
+
+/// 消费者名，只有一个消费者
+const CONSUMER_NAME: &str = "c1";
+
+/// 启动消费者，把抢购记录转成订单
+///
+/// 处理失败(redis、数据库不可用)的消息不 ack，留在 pending-list 中，下一轮重新读取
+pub fn init(redis_pool: RedisPool, db_pool: web::Data<DbPool>, workers: &Workers) {
+    let mut hb = workers.register("spike");
+
+    tokio::spawn(async move {
+        let key = key();
+        let opts = StreamReadOptions::default()
+            .group(GROUP_NAME, CONSUMER_NAME)
+            .count(100)
+            .block(5000);
+
+        let mut con = match super::connect(&redis_pool, &mut hb, &key, GROUP_NAME).await {
+            Some(c) => c,
+            None => return,
+        };
+
+        // "0" 读取 pending-list，读完后切换到 ">" 读取新消息
+        let mut cursor = "0";
+        while !hb.is_shutdown() {
+            hb.beat();
+            let read_reply: StreamReadReply =
+                match con.xread_options(&[&key], &[cursor], &opts).await {
+                    Ok(x) => x,
+                    Err(err) => {
+                        // 连接可能已经失效(如 redis 重启)，重新获取
+                        log::error!("秒杀队列读取失败，重新连接: {}", err);
+                        hb.sleep(Duration::from_secs(1)).await;
+                        con = match super::connect(&redis_pool, &mut hb, &key, GROUP_NAME).await {
+                            Some(c) => c,
+                            None => return,
+                        };
+                        cursor = "0";
+                        continue;
+                    }
+                };
+
+            let ids = read_reply
+                .keys
+                .into_iter()
+                .flat_map(|x| x.ids)
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                cursor = ">";
+                continue;
+            }
+
+            let mut failed = false;
+            for msg in &ids {
+                let entry = match get_entry(msg) {
+                    Some(x) => x,
+                    None => {
+                        log::error!("秒杀消息参数错误，删除消息: {}", msg.id);
+                        ack(&mut con, &key, &msg.id).await;
+                        continue;
+                    }
+                };
+                match spike::create_order(db_pool.clone(), &redis_pool, entry).await {
+                    Ok(_) => ack(&mut con, &key, &msg.id).await,
+                    Err(err) => {
+                        log::error!("秒杀消息处理失败，稍后重试: {} {:?}", msg.id, err);
+                        failed = true;
+                    }
+                }
+            }
+
+            if failed {
+                cursor = "0";
+                hb.sleep(Duration::from_secs(1)).await;
+            }
+        }
+    });
+}
+
+fn get_entry(msg: &StreamId) -> Option<Entry> {
+    Some(Entry {
+        event_id: msg.get("event_id")?,
+        user_id: msg.get("user_id")?,
+        num: msg.get("num")?,
+        request_id: msg.get("request_id")?,
+    })
+}
+
+async fn ack(con: &mut deadpool_redis::Connection, key: &str, id: &str) {
+    let _: Result<usize, _> = con.xack(key, GROUP_NAME, &[id]).await;
+    let _: Result<usize, _> = con.xdel(key, &[id]).await;
+}
@@ -11,6 +11,10 @@ table! {
         last_login -> Nullable<Datetime>,
         create_at -> Datetime,
         update_at -> Datetime,
+        totp_secret -> Nullable<Varchar>,
+        totp_enabled -> Bool,
+        recovery_codes -> Nullable<Varchar>,
+        locale -> Nullable<Varchar>,
     }
 }
 
@@ -23,6 +27,10 @@ table! {
         admin_id -> Unsigned<Bigint>,
         create_at -> Datetime,
         update_at -> Datetime,
+        object_type -> Varchar,
+        object_id -> Nullable<Unsigned<Bigint>>,
+        changes -> Nullable<Text>,
+        ip -> Varchar,
     }
 }
 
@@ -36,6 +44,18 @@ table! {
     }
 }
 
+table! {
+    auth_log (id) {
+        id -> Unsigned<Bigint>,
+        event -> Unsigned<Tinyint>,
+        scope -> Varchar,
+        principal -> Varchar,
+        ip -> Varchar,
+        desc -> Varchar,
+        create_at -> Datetime,
+    }
+}
+
 table! {
     auth_permission (id) {
         id -> Unsigned<Bigint>,
@@ -43,6 +63,7 @@ table! {
         desc -> Varchar,
         create_at -> Datetime,
         update_at -> Datetime,
+        orphaned -> Bool,
     }
 }
 
@@ -90,17 +111,81 @@ table! {
     }
 }
 
+table! {
+    order_items (id) {
+        id -> Unsigned<Bigint>,
+        order_id -> Unsigned<Bigint>,
+        book_id -> Unsigned<Bigint>,
+        name -> Varchar,
+        price -> Decimal,
+        num -> Unsigned<Integer>,
+        create_at -> Datetime,
+    }
+}
+
+table! {
+    orders (id) {
+        id -> Unsigned<Bigint>,
+        order_no -> Varchar,
+        user_id -> Unsigned<Bigint>,
+        amount -> Decimal,
+        status -> Unsigned<Tinyint>,
+        pay_channel -> Varchar,
+        trade_no -> Varchar,
+        expire_at -> Datetime,
+        paid_at -> Nullable<Datetime>,
+        closed_at -> Nullable<Datetime>,
+        create_at -> Datetime,
+        update_at -> Datetime,
+        request_id -> Nullable<Varchar>,
+        spike_event_id -> Nullable<Unsigned<Bigint>>,
+    }
+}
+
+table! {
+    password_history (id) {
+        id -> Unsigned<Bigint>,
+        scope -> Varchar,
+        principal_id -> Unsigned<Bigint>,
+        password -> Varchar,
+        create_at -> Datetime,
+    }
+}
+
 table! {
     posts (id) {
         id -> Unsigned<Bigint>,
         title -> Varchar,
-        content -> Varchar,
+        content -> Text,
         user_id -> Unsigned<Bigint>,
         create_at -> Datetime,
         update_at -> Datetime,
     }
 }
 
+table! {
+    spike_events (id) {
+        id -> Unsigned<Bigint>,
+        title -> Varchar,
+        book_id -> Unsigned<Bigint>,
+        price -> Decimal,
+        stock -> Unsigned<Integer>,
+        per_user_limit -> Unsigned<Integer>,
+        begin_at -> Datetime,
+        end_at -> Datetime,
+        create_at -> Datetime,
+        update_at -> Datetime,
+    }
+}
+
+table! {
+    sys_setting (name) {
+        name -> Varchar,
+        value -> Varchar,
+        update_at -> Datetime,
+    }
+}
+
 table! {
     users (id) {
         id -> Unsigned<Bigint>,
@@ -114,6 +199,7 @@ table! {
         last_login -> Nullable<Datetime>,
         create_at -> Datetime,
         update_at -> Datetime,
+        locale -> Nullable<Varchar>,
     }
 }
 
@@ -125,17 +211,26 @@ joinable!(m2m_admin_permission -> admin (admin_id));
 joinable!(m2m_admin_permission -> auth_permission (permission_id));
 joinable!(m2m_group_permission -> auth_group (group_id));
 joinable!(m2m_group_permission -> auth_permission (permission_id));
+joinable!(order_items -> orders (order_id));
+joinable!(orders -> users (user_id));
 joinable!(posts -> users (user_id));
+joinable!(spike_events -> books (book_id));
 
 allow_tables_to_appear_in_same_query!(
     admin,
     admin_log,
     auth_group,
+    auth_log,
     auth_permission,
     books,
     m2m_admin_group,
     m2m_admin_permission,
     m2m_group_permission,
+    order_items,
+    orders,
+    password_history,
     posts,
+    spike_events,
+    sys_setting,
     users,
 );
@@ -1,28 +1,5 @@
 use crate::prelude::*;
 
-#[derive(Deserialize, Debug)]
-pub struct SearchAdmin {
-    pub username: Option<String>,
-    pub realname: Option<String>,
-    pub email: Option<String>,
-    pub is_active: Option<bool>,
-    pub remark: Option<String>,
-    pub last_login: Option<String>,
-    pub create_at: Option<NaiveDateTime>,
-    pub update_at: Option<NaiveDateTime>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct ListQuery {
-    /// page，从1开始
-    pub page: Option<i64>,
-    /// limit 需要多少条数据
-    pub limit: Option<i64>,
-
-    #[serde(flatten)]
-    pub admin: SearchAdmin,
-}
-
 #[derive(Debug, Deserialize)]
 pub struct DelMany {
     pub ids: Vec<PK>,
@@ -40,16 +17,20 @@ pub struct NewAdmin {
     pub username: String,
 
     #[serde(skip_serializing)]
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
+    #[validate(custom = "vf::password")]
     pub password: String,
 
     pub realname: Option<String>,
 
-    #[validate(custom(function = "vf::email", message = "邮箱格式错误"))]
+    #[validate(custom(function = "vf::email", message = "validation.email"))]
     pub email: Option<String>,
 
     pub remark: Option<String>,
     pub is_active: Option<bool>,
+
+    /// 只能通过命令行创建超级管理员，`server admin create --superadmin`
+    #[serde(skip_deserializing)]
+    pub is_superadmin: Option<bool>,
 }
 
 #[derive(AsChangeset, Deserialize, Validate, Debug)]
@@ -61,9 +42,33 @@ pub struct UpdateAdmin {
     pub remark: Option<String>,
     pub realname: Option<String>,
 
-    #[validate(custom(function = "vf::email", message = "邮箱格式错误"))]
+    #[validate(custom(function = "vf::email", message = "validation.email"))]
     pub email: Option<String>,
     pub is_active: Option<bool>,
     pub last_login: Option<NaiveDateTime>,
     pub create_at: Option<NaiveDateTime>,
 }
+
+/// 绑定两步验证，前端使用 otpauth_uri 生成二维码
+#[derive(Debug, Serialize)]
+pub struct TotpEnroll {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+/// 验证码和恢复码二选一
+#[derive(Debug, Deserialize)]
+pub struct TotpDisable {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForceTotp {
+    pub enabled: bool,
+}
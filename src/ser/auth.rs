@@ -1,22 +1,25 @@
-use crate::prelude::*;
+use crate::{modules::sms::SmsPurpose, prelude::*};
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct SendPhoneCaptcha {
-    #[validate(custom(function = "vf::phone", message = "手机号格式错误"))]
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
     pub phone: String,
+
+    /// register login reset_password，默认 login
+    #[serde(default)]
+    pub purpose: SmsPurpose,
 }
 
 #[derive(Deserialize, Validate, Debug)]
 pub struct LoginAccount {
     pub username: Option<String>,
 
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
     pub password: Option<String>,
 
     #[validate(custom = "vf::phone")]
     pub mobile: Option<String>,
 
-    #[validate(length(equal = 4, message = "验证码长度为4位!"))]
+    #[validate(length(equal = 4, message = "validation.captcha_len"))]
     pub captcha: Option<String>,
 
     /// account 账号+密码, mobile 手机号+验证码
@@ -28,6 +31,101 @@ pub struct PhoneUserRegister {
     #[serde(flatten)]
     pub user: models::user::NewPhoneUser,
 
-    #[validate(length(equal = 4, message = "验证码长度为4位!"))]
+    #[validate(length(equal = 4, message = "validation.captcha_len"))]
     pub captcha: String,
 }
+
+/// 登陆、刷新 token 成功后返回
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+
+    /// access_token 过期秒数
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshToken {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Logout {
+    /// 同时作废 refresh token
+    pub refresh_token: Option<String>,
+}
+
+/// 忘记密码，邮箱和手机号二选一
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPassword {
+    #[validate(custom(function = "vf::email", message = "validation.email"))]
+    pub email: Option<String>,
+
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
+    pub phone: Option<String>,
+}
+
+/// 重置密码，邮件中的 token 和 手机号+验证码 二选一
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPassword {
+    pub token: Option<String>,
+
+    #[validate(custom(function = "vf::phone", message = "validation.phone"))]
+    pub phone: Option<String>,
+
+    #[validate(length(equal = 4, message = "validation.captcha_len"))]
+    pub captcha: Option<String>,
+
+    #[validate(custom = "vf::password")]
+    pub password: String,
+
+    #[validate(must_match(other = "password", message = "user.password_mismatch"))]
+    pub password2: String,
+}
+
+/// 管理员登陆的结果，没有开启两步验证时直接返回 token
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AdminLogin {
+    Tokens(TokenPair),
+    MfaRequired(MfaPending),
+}
+
+/// 密码正确，需要两步验证
+#[derive(Debug, Serialize)]
+pub struct MfaPending {
+    pub mfa_required: bool,
+
+    /// 调用 /api/auth/admin_login/2fa 时使用
+    pub mfa_token: String,
+
+    /// 强制开启两步验证但还没有绑定，需要先调用 /api/auth/admin_login/2fa/enroll
+    pub enroll_required: bool,
+
+    /// mfa_token 过期秒数
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaToken {
+    pub mfa_token: String,
+}
+
+/// 两步验证，验证码和恢复码二选一
+#[derive(Debug, Deserialize)]
+pub struct AdminLogin2fa {
+    pub mfa_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+/// 两步验证成功，首次绑定时同时返回恢复码
+#[derive(Debug, Serialize)]
+pub struct MfaLogin {
+    #[serde(flatten)]
+    pub tokens: TokenPair,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
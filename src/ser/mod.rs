@@ -2,23 +2,40 @@ use crate::prelude::*;
 
 pub mod admin;
 pub mod auth;
+pub mod rbac;
 pub mod upload;
 pub mod user;
 
 #[cfg(feature = "wx")]
 pub mod wx;
 
+/// 保存语言，为空时清除，之后使用请求的语言
+#[derive(Debug, Deserialize, Validate)]
+pub struct SetLocale {
+    #[validate(custom(function = "vf::locale", message = "validation.locale"))]
+    pub locale: Option<String>,
+}
+
+impl SetLocale {
+    /// 统一保存为 [crate::utils::i18n::Locale::tag]
+    pub fn tag(&self) -> Option<String> {
+        self.locale
+            .as_deref()
+            .and_then(|x| x.parse::<crate::utils::i18n::Locale>().ok())
+            .map(|x| x.tag().to_owned())
+    }
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct ChangePwd {
     /// 当前密码
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
     pub pwd: String,
 
     /// 新密码
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
+    #[validate(custom = "vf::password")]
     pub new_pwd: String,
 
     /// 确认新密码
-    #[validate(length(min = 3, max = 20, message = "密码3到20位!"))]
+    #[validate(must_match(other = "new_pwd", message = "user.password_mismatch"))]
     pub new_pwd2: String,
 }
@@ -0,0 +1,32 @@
+use crate::prelude::*;
+use models::{auth_group::Group, auth_permission::Permission};
+
+/// 分配或取消的组、权限id
+#[derive(Debug, Deserialize)]
+pub struct Ids {
+    pub ids: Vec<PK>,
+}
+
+/// 组的详情
+#[derive(Debug, Serialize)]
+pub struct GroupDetail {
+    #[serde(flatten)]
+    pub group: Group,
+    pub permissions: Vec<Permission>,
+}
+
+/// 管理员的权限
+#[derive(Debug, Serialize)]
+pub struct AdminPermissions {
+    /// 超级管理员拥有所有权限
+    pub superadmin: bool,
+
+    /// 所在的组
+    pub groups: Vec<Group>,
+
+    /// 直接分配的权限
+    pub direct: Vec<Permission>,
+
+    /// 有效权限，直接分配的加上所在组的
+    pub effective: Vec<String>,
+}
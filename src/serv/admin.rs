@@ -1,5 +1,4 @@
 use crate::prelude::*;
-use schema::*;
 
 /// 修改密码
 pub async fn change_pwd(
@@ -9,21 +8,11 @@ pub async fn change_pwd(
     new_pwd: String,
 ) -> R<usize> {
     // 验证旧密码
-    let user = models::admin::Admin::obj(db_pool.clone(), pk).await?;
-    if !pwd_decode!(&user.password, pwd.as_ref()) {
-        bail!("密码错误");
+    let user = models::admin::Admin::obj_with_secrets(db_pool.clone(), pk).await?;
+    if !serv::password::verify(user.password, pwd).await? {
+        bail!("user.wrong_password");
     }
 
     // 设置新密码
-    let new_password = pwd_encode!(new_pwd);
-    let rows = web::block(move || -> R<usize> {
-        let mut conn = db_pool.get()?;
-        let target = admin::table.filter(admin::id.eq(pk));
-        Ok(diesel::update(target)
-            .set(admin::password.eq(new_password))
-            .execute(&mut conn)?)
-    })
-    .await??;
-
-    Ok(rows)
+    serv::password::set(db_pool, Audience::Admin, pk, new_pwd).await
 }
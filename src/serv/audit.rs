@@ -0,0 +1,154 @@
+/**
+ * 管理员操作日志
+ *
+ * 修改数据的管理员接口在操作成功后记录 谁、对哪个对象、做了什么，修改前后的差异保存在 changes
+ *
+ * ```
+ * let actor = serv::audit::Actor::new(&admin, &req);
+ * let mut log = actor.entry(ActionFlag::UPDATE, "admin.update", "admin", Some(pk));
+ * log.changes = serv::audit::diff(Some(&before), Some(&after));
+ * serv::audit::record(db_pool, vec![log]).await;
+ * ```
+ */
+use crate::prelude::*;
+use models::admin_log::{ActionFlag, AdminLog, NewAdminLog};
+use serde_json::{Map, Value};
+
+/// 不记录的字段
+const IGNORED: &[&str] = &["update_at"];
+
+/// 只记录是否修改，不记录值
+const REDACTED: &[&str] = &["password", "totp_secret", "recovery_codes"];
+
+/// 导出的最大条数
+pub const EXPORT_MAX: i64 = 10000;
+
+/// 操作人
+pub struct Actor {
+    pub admin_id: PK,
+    pub ip: String,
+}
+
+impl Actor {
+    pub fn new(admin: &AuthAdmin, req: &HttpRequest) -> Self {
+        Self {
+            admin_id: admin.id,
+            ip: serv::guard::client_ip(req).unwrap_or_default(),
+        }
+    }
+
+    /// 一条日志，desc 和 changes 按需要设置
+    pub fn entry(
+        &self,
+        flag: ActionFlag,
+        action: &str,
+        object_type: &str,
+        object_id: Option<PK>,
+    ) -> NewAdminLog {
+        NewAdminLog {
+            action_flag: flag as u8,
+            action_msg: action.to_owned(),
+            desc: String::new(),
+            admin_id: self.admin_id,
+            object_type: object_type.to_owned(),
+            object_id,
+            changes: None,
+            ip: self.ip.clone(),
+        }
+    }
+}
+
+/// 比较修改前后的值，返回 json {"字段": [修改前, 修改后]}，没有差异时返回 None
+///
+/// 创建时 before 为 None，删除时 after 为 None
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Option<String> {
+    let before = to_map(before);
+    let after = to_map(after);
+
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        if changes.contains_key(key) || IGNORED.contains(&key.as_str()) {
+            continue;
+        }
+        let (old, new) = (
+            before.get(key).unwrap_or(&Value::Null),
+            after.get(key).unwrap_or(&Value::Null),
+        );
+        if old == new {
+            continue;
+        }
+        let pair = if REDACTED.contains(&key.as_str()) {
+            json!(["***", "***"])
+        } else {
+            json!([old, new])
+        };
+        changes.insert(key.clone(), pair);
+    }
+
+    if changes.is_empty() {
+        None
+    } else {
+        Some(Value::Object(changes).to_string())
+    }
+}
+
+fn to_map<T: Serialize>(value: Option<&T>) -> Map<String, Value> {
+    match value.map(serde_json::to_value) {
+        Some(Ok(Value::Object(map))) => map,
+        _ => Map::new(),
+    }
+}
+
+/// 保存日志，操作已经成功，失败只记录错误日志
+pub async fn record(db_pool: web::Data<DbPool>, mut logs: Vec<NewAdminLog>) {
+    if logs.is_empty() {
+        return;
+    }
+    for log in logs.iter_mut() {
+        if log.desc.chars().count() > 200 {
+            log.desc = log.desc.chars().take(200).collect();
+        }
+    }
+
+    if let Err(err) = AdminLog::create_many(db_pool, logs).await {
+        log::error!("保存管理员操作日志失败: {}", err);
+    }
+}
+
+/// 导出为 csv，第一行是表头
+pub fn to_csv(logs: &[AdminLog]) -> String {
+    let mut out = String::from(
+        "id,admin_id,action_flag,action_msg,object_type,object_id,desc,changes,ip,create_at\n",
+    );
+    for log in logs {
+        let row = [
+            log.id.to_string(),
+            log.admin_id.to_string(),
+            log.action_flag.to_string(),
+            csv_field(&log.action_msg),
+            csv_field(&log.object_type),
+            log.object_id.map(|x| x.to_string()).unwrap_or_default(),
+            csv_field(&log.desc),
+            csv_field(log.changes.as_deref().unwrap_or_default()),
+            csv_field(&log.ip),
+            log.create_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// 包含逗号、引号、换行时加引号，以 = + - @ 开头时加 ' 防止表格软件执行公式
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_owned()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
@@ -1,24 +1,207 @@
 use crate::prelude::*;
+use crate::{
+    modules::sms::{SmsMessage, SmsPurpose},
+    queues,
+    serv::guard::{self, Scope},
+    utils::i18n,
+};
 use actix_web::web;
-use models::user::User;
+use deadpool_redis::Connection;
+use models::{admin::Admin, user::User};
+use ser::auth::{AdminLogin, MfaLogin, MfaPending, TokenPair};
+
+/// 手机验证码有效期，单位秒
+pub const PHONE_CODE_TTL: u64 = 60;
+
+/// 重置密码链接有效期，单位秒
+pub const PWD_RESET_TTL: u64 = 30 * 60;
+
+/// 同一个邮箱两次发送重置邮件的最小间隔，单位秒
+const PWD_RESET_COOLDOWN: usize = 60;
+
+/// 同一个IP在 [PWD_RESET_IP_WINDOW] 内最多申请重置邮件的次数
+const PWD_RESET_IP_MAX: u64 = 10;
+
+/// 统计IP申请次数的时间窗口，单位秒
+const PWD_RESET_IP_WINDOW: usize = 60 * 60;
+
+/// 同一个IP在 [PHONE_CODE_IP_WINDOW] 内最多发送验证码的次数
+const PHONE_CODE_IP_MAX: u64 = 20;
+
+/// 统计IP发送验证码次数的时间窗口，单位秒
+const PHONE_CODE_IP_WINDOW: usize = 60 * 60;
+
+/// refresh token 在 redis 中保存的内容
+#[derive(Debug, Serialize, Deserialize)]
+struct RefreshSession {
+    aud: Audience,
+    id: PK,
+    user_type: u8,
+    ver: u64,
+}
+
+/// 用户当前的 token 版本，没有时为 0
+async fn token_ver(con: &mut Connection, aud: Audience, pk: PK) -> R<u64> {
+    let ver: Option<u64> = con.get(rk_jwt_ver!(aud, pk)).await?;
+    Ok(ver.unwrap_or_default())
+}
+
+async fn issue(
+    con: &mut Connection,
+    aud: Audience,
+    pk: PK,
+    user_type: u8,
+    ver: u64,
+) -> R<TokenPair> {
+    let jwt_config = &conf!().jwt;
+    let access_token = Claims::new(aud, pk, user_type, ver).jwt()?;
+
+    let refresh_token = random_token!(32);
+    let session = serde_json::to_string(&RefreshSession {
+        aud,
+        id: pk,
+        user_type,
+        ver,
+    })?;
+    let _: () = con
+        .set_ex(
+            rk_jwt_refresh!(refresh_token),
+            session,
+            jwt_config.refresh_exp as usize,
+        )
+        .await?;
+
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+        expires_in: jwt_config.exp,
+    })
+}
+
+/// 登陆成功后签发 access token 和 refresh token
+pub async fn issue_tokens(
+    redis_pool: &RedisPool,
+    aud: Audience,
+    pk: PK,
+    user_type: u8,
+) -> R<TokenPair> {
+    let mut con = redis_pool.get().await?;
+    let ver = token_ver(&mut con, aud, pk).await?;
+    issue(&mut con, aud, pk, user_type, ver).await
+}
+
+/// 使用 refresh token 换一对新的 token，旧的 refresh token 立即失效
+pub async fn refresh_tokens(redis_pool: &RedisPool, refresh_token: &str) -> R<TokenPair> {
+    let mut con = redis_pool.get().await?;
+    let key = rk_jwt_refresh!(refresh_token);
+
+    // 读取和删除必须是原子的，同一个 refresh token 只能使用一次
+    let (session, _): (Option<String>, usize) = redis::pipe()
+        .atomic()
+        .get(&key)
+        .del(&key)
+        .query_async(&mut con)
+        .await?;
+
+    let session: RefreshSession = match session {
+        Some(s) => serde_json::from_str(&s)?,
+        None => bail!(ErrKind::Auth.err("auth.invalid_token")),
+    };
+
+    if session.ver != token_ver(&mut con, session.aud, session.id).await? {
+        bail!(ErrKind::Auth.err("auth.invalid_token"));
+    }
+
+    issue(
+        &mut con,
+        session.aud,
+        session.id,
+        session.user_type,
+        session.ver,
+    )
+    .await
+}
+
+/// 退出登陆，access token 加入黑名单直到过期，refresh token 删除
+pub async fn logout(
+    redis_pool: &RedisPool,
+    claims: Option<&Claims>,
+    refresh_token: Option<&str>,
+) -> R<()> {
+    let mut con = redis_pool.get().await?;
+
+    if let Some(claims) = claims {
+        let ttl = claims.ttl();
+        if ttl > 0 {
+            let _: () = con.set_ex(rk_jwt_deny!(claims.jti), 1, ttl).await?;
+        }
+    }
+
+    if let Some(refresh_token) = refresh_token {
+        let _: usize = con.del(rk_jwt_refresh!(refresh_token)).await?;
+    }
+
+    Ok(())
+}
+
+/// 用户已签发的 access token 和 refresh token 全部失效
+///
+/// 修改密码、禁用、删除账号后调用
+pub async fn revoke_sessions(redis_pool: &RedisPool, aud: Audience, pk: PK) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let _: u64 = con.incr(rk_jwt_ver!(aud, pk), 1).await?;
+    Ok(())
+}
+
+/// access token 是否已退出登陆，或者签发后用户的 token 版本已改变
+pub async fn verify_session(redis_pool: &RedisPool, claims: &Claims) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let (denied, ver): (bool, Option<u64>) = redis::pipe()
+        .exists(rk_jwt_deny!(claims.jti))
+        .get(rk_jwt_ver!(claims.aud, claims.id))
+        .query_async(&mut con)
+        .await?;
+
+    if denied || ver.unwrap_or_default() != claims.ver {
+        bail!(ErrKind::Auth.err("auth.invalid_token"));
+    }
+    Ok(())
+}
+
+/// 验证手机验证码，用途必须和发送时一致
+///
+/// 错误次数过多时验证码作废、手机号被锁定，见 [guard]
+pub async fn verify_phone_code(
+    redis_pool: &RedisPool,
+    db_pool: &web::Data<DbPool>,
+    purpose: SmsPurpose,
+    phone: &str,
+    code: &str,
+    ip: Option<&str>,
+) -> R<()> {
+    guard::check(redis_pool, Scope::Phone, phone, ip).await?;
 
-/// 验证手机验证码
-pub async fn verify_phone_code(redis_pool: &RedisPool, phone: &str, code: &str) -> R<()> {
     // redis key
-    let key = rk_phone_vc!(phone);
+    let key = rk_phone_vc!(purpose, phone);
 
     let mut con = redis_pool.get().await?;
 
-    // 获取发送存的code
-    let cache_code: Option<String> = con.get(&key).await?;
+    // 获取发送存的code，没有发送过时不计入失败次数，否则不用短信就能锁定任意手机号
+    let cache_code: String = match con.get(&key).await? {
+        Some(x) => x,
+        None => bail!("auth.invalid_captcha"),
+    };
 
-    // 没有或则code不对返回错误
-    if cache_code.is_none() || cache_code.unwrap_or_default().as_str() != code {
-        bail!("无效验证码");
+    // code不对返回错误
+    if cache_code != code {
+        guard::fail(redis_pool, db_pool, Scope::Phone, phone, ip).await?;
+        guard::captcha_fail(redis_pool, db_pool, &key, phone, ip).await?;
+        bail!("auth.invalid_captcha");
     }
 
     // 成功则删除缓存
-    con.del(&key).await?;
+    let _: () = con.del(&[&key, &format!("{}:fail", key)]).await?;
+    guard::success(redis_pool, Scope::Phone, phone).await?;
 
     Ok(())
 }
@@ -27,55 +210,400 @@ pub async fn verify_phone_code(redis_pool: &RedisPool, phone: &str, code: &str)
 pub async fn phone_login(
     phone: String,
     code: String,
+    ip: Option<&str>,
     db_pool: web::Data<DbPool>,
     redis_pool: web::Data<RedisPool>,
-) -> R<String> {
-    verify_phone_code(&redis_pool, &phone, &code).await?;
+) -> R<TokenPair> {
+    verify_phone_code(&redis_pool, &db_pool, SmsPurpose::Login, &phone, &code, ip).await?;
 
     let c_db_pool = db_pool.clone();
     let user: User = User::obj_with_phone(c_db_pool, phone).await?;
 
     if !user.is_active {
-        bail!("账号未激活");
+        bail!(ErrKind::Forbidden.err("auth.inactive"));
     }
 
     User::update_last_login(db_pool, user.id).await?;
 
-    Ok(Claims::new(user.id, user.user_type).jwt()?)
+    issue_tokens(&redis_pool, Audience::User, user.id, user.user_type).await
 }
 
 /// 账号+密码登录
 pub async fn account_login(
     username: String,
     password: String,
+    ip: Option<&str>,
     db_pool: web::Data<DbPool>,
-) -> R<String> {
-    let c_db_pool = db_pool.clone();
-    let user: User = User::obj_with_username(c_db_pool, username).await?;
+    redis_pool: web::Data<RedisPool>,
+) -> R<TokenPair> {
+    let user = guard::protect(&redis_pool, &db_pool, Scope::User, &username, ip, async {
+        let user: User = User::obj_with_username(db_pool.clone(), username.clone()).await?;
 
-    if !pwd_decode!(&user.password.unwrap_or_default(), password.as_ref()) {
-        bail!("账号或密码错误");
-    }
+        let encoded = user.password.clone().unwrap_or_default();
+        if !serv::password::verify(encoded, password.clone()).await? {
+            bail!(ErrKind::Auth.err("auth.bad_credentials"));
+        }
+        Ok(user)
+    })
+    .await?;
 
     if !user.is_active {
-        bail!("账号未激活");
+        bail!(ErrKind::Forbidden.err("auth.inactive"));
     }
 
+    let encoded = user.password.as_deref().unwrap_or_default();
+    serv::password::upgrade_hash(db_pool.clone(), Audience::User, user.id, encoded, &password)
+        .await;
+
     User::update_last_login(db_pool, user.id).await?;
-    Ok(Claims::new(user.id, user.user_type).jwt()?)
+    issue_tokens(&redis_pool, Audience::User, user.id, user.user_type).await
+}
+
+/// 管理员账号+密码登录
+///
+/// 开启两步验证或超级管理员强制开启时，只返回 mfa_token，见 [admin_login_2fa]
+pub async fn admin_login(
+    username: String,
+    password: String,
+    ip: Option<&str>,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+) -> R<AdminLogin> {
+    let admin = guard::protect(&redis_pool, &db_pool, Scope::Admin, &username, ip, async {
+        Admin::login(db_pool.clone(), username.clone(), password.clone()).await
+    })
+    .await?;
+    serv::password::upgrade_hash(
+        db_pool.clone(),
+        Audience::Admin,
+        admin.id,
+        &admin.password,
+        &password,
+    )
+    .await;
+
+    if serv::totp::required(db_pool.clone(), &admin).await? {
+        let mfa_token = serv::totp::create_pending(&redis_pool, admin.id).await?;
+        return Ok(AdminLogin::MfaRequired(MfaPending {
+            mfa_required: true,
+            mfa_token,
+            enroll_required: !admin.totp_enabled,
+            expires_in: serv::totp::MFA_PENDING_TTL,
+        }));
+    }
+
+    Admin::update_last_login(db_pool, admin.id).await?;
+    let tokens = issue_tokens(&redis_pool, Audience::Admin, admin.id, 0).await?;
+    Ok(AdminLogin::Tokens(tokens))
 }
 
-/// 发送手机验证码
-pub async fn send_phone_captcha(redis_pool: web::Data<RedisPool>, phone: String) -> R<()> {
+/// 管理员登陆的第二步，使用 TOTP 验证码或恢复码
+///
+/// 强制开启但还没有绑定时，先调用 [admin_login_2fa_enroll]，这里验证通过后开启并返回恢复码
+pub async fn admin_login_2fa(
+    data: ser::auth::AdminLogin2fa,
+    ip: Option<&str>,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+) -> R<MfaLogin> {
+    // 先取出并删除 mfa_token，同一个 token 的请求不能同时验证
+    let (pk, ttl_ms) = serv::totp::take_pending(&redis_pool, &data.mfa_token).await?;
+
+    let verified: R<_> = async {
+        let admin = Admin::obj(db_pool.clone(), pk).await?;
+        if !admin.is_active {
+            bail!(ErrKind::Forbidden.err("auth.inactive"));
+        }
+
+        let id = pk.to_string();
+        guard::protect(&redis_pool, &db_pool, Scope::Mfa, &id, ip, async {
+            if admin.totp_enabled {
+                serv::totp::verify_second_factor(
+                    db_pool.clone(),
+                    &redis_pool,
+                    &admin,
+                    data.code.as_deref(),
+                    data.recovery_code.as_deref(),
+                )
+                .await?;
+                Ok(None)
+            } else {
+                let code = data.code.as_deref().unwrap_or_default();
+                let codes =
+                    serv::totp::activate(db_pool.clone(), &redis_pool, &admin, code).await?;
+                Ok(Some(codes))
+            }
+        })
+        .await
+    }
+    .await;
+
+    // 验证失败时放回，剩余的有效期不变，可以继续重试，次数受 Scope::Mfa 限制
+    let recovery_codes = match verified {
+        Ok(x) => x,
+        Err(err) => {
+            serv::totp::restore_pending(&redis_pool, &data.mfa_token, pk, ttl_ms).await?;
+            return Err(err);
+        }
+    };
+
+    Admin::update_last_login(db_pool, pk).await?;
+    let tokens = issue_tokens(&redis_pool, Audience::Admin, pk, 0).await?;
+    Ok(MfaLogin {
+        tokens,
+        recovery_codes,
+    })
+}
+
+/// 强制开启两步验证时，还没有绑定的管理员登陆时绑定
+///
+/// 同一个 mfa_token 只绑定一次，见 [serv::totp::enroll_pending]
+pub async fn admin_login_2fa_enroll(
+    mfa_token: String,
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+) -> R<ser::admin::TotpEnroll> {
+    serv::totp::enroll_pending(db_pool, &redis_pool, &mfa_token).await
+}
+
+/// 发送手机验证码，放入短信队列，见 [crate::queues::sms]
+pub async fn send_phone_captcha(
+    redis_pool: web::Data<RedisPool>,
+    purpose: SmsPurpose,
+    phone: String,
+    ip: Option<&str>,
+) -> R<()> {
+    // 锁定期间不能获取新的验证码
+    guard::check(&redis_pool, Scope::Phone, &phone, ip).await?;
+
     let mut con = redis_pool.get().await?;
-    let key = rk_phone_vc!(phone);
+    let key = rk_phone_vc!(purpose, phone);
 
     // 已存在验证码不能再次发送
     if con.exists(&key).await? {
-        bail!("验证码已发送，请稍后再试");
+        bail!(ErrKind::RateLimited.err("auth.captcha_sent"));
+    }
+
+    // 同一个IP发送的次数，防止给任意手机号发短信
+    if let Some(ip) = ip {
+        limit_ip(
+            &mut con,
+            &rk_phone_vc_ip!(ip),
+            PHONE_CODE_IP_MAX,
+            PHONE_CODE_IP_WINDOW,
+        )
+        .await?;
     }
 
     let code = make_phone_code!(4);
-    con.set_ex(&key, &code, 60_usize).await?;
+    let _: () = con.set_ex(&key, &code, PHONE_CODE_TTL as usize).await?;
+
+    queues::sms::push(
+        &redis_pool,
+        &SmsMessage {
+            phone,
+            purpose,
+            code,
+            locale: i18n::current(),
+        },
+    )
+    .await
+}
+
+/// 忘记密码，发送重置链接到邮箱，或发送验证码到手机
+///
+/// 邮箱、手机号不存在时也返回成功，不能用来判断账号是否存在
+pub async fn forgot_password(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    data: ser::auth::ForgotPassword,
+    ip: Option<&str>,
+) -> R<()> {
+    if let Some(phone) = data.phone {
+        let user = match User::obj_with_phone(db_pool, phone.clone()).await {
+            Ok(user) => user,
+            Err(_) => {
+                log::debug!("重置密码的手机号不存在: {}", phone);
+                return Ok(());
+            }
+        };
+        // 已发送、锁定等错误同样返回成功，和手机号不存在时的响应一致
+        let res = send_phone_captcha(redis_pool, SmsPurpose::ResetPassword, phone.clone(), ip);
+        if let Err(err) = i18n::scope(saved_locale(&user), res).await {
+            log::debug!("重置密码的验证码没有发送: {} {}", phone, err);
+        }
+        return Ok(());
+    }
+
+    let email = match data.email {
+        Some(email) => email,
+        None => bail!("auth.reset_target_required"),
+    };
+
+    if !cfg!(feature = "mail") {
+        bail!("auth.reset_mail_disabled");
+    }
+
+    // 锁定期间不能申请，发送频率在查询用户之前限制，邮箱不存在时同样计数
+    guard::check(&redis_pool, Scope::User, &email, ip).await?;
+    limit_reset_mail(&redis_pool, &email, ip).await?;
+
+    let user = match User::obj_with_email(db_pool, email.clone()).await {
+        Ok(user) => user,
+        Err(_) => {
+            log::debug!("重置密码的邮箱不存在: {}", email);
+            return Ok(());
+        }
+    };
+
+    let mut con = redis_pool.get().await?;
+    let token = random_token!(32);
+    let user_key = rk_pwd_reset_user!(user.id);
+
+    // 一个用户只有最新的链接有效
+    let old: Option<String> = con.get(&user_key).await?;
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    if let Some(old) = old {
+        pipe.del(rk_pwd_reset!(old)).ignore();
+    }
+    let _: () = pipe
+        .set_ex(rk_pwd_reset!(token), user.id, PWD_RESET_TTL as usize)
+        .ignore()
+        .set_ex(&user_key, &token, PWD_RESET_TTL as usize)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+
+    #[cfg(feature = "mail")]
+    {
+        let locale = saved_locale(&user);
+        let link = format!(
+            "{}/reset_password?token={}",
+            conf!().site_name.trim_end_matches('/'),
+            token
+        );
+        queues::mail::push(
+            &redis_pool,
+            &email,
+            &i18n::t(locale, "mail.reset_password.subject"),
+            &link,
+            "reset_password",
+            locale,
+        )
+        .await?;
+    }
+
     Ok(())
 }
+
+/// 用户保存的语言，没有时使用当前请求的语言，请求中没有登陆时发送通知使用
+fn saved_locale(user: &User) -> i18n::Locale {
+    user.locale
+        .as_deref()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or_else(i18n::current)
+}
+
+/// 重置邮件的发送限制: 同一个邮箱 [PWD_RESET_COOLDOWN] 秒内只发一次，同一个IP每小时 [PWD_RESET_IP_MAX] 次
+///
+/// 没有IP时只按邮箱限制
+async fn limit_reset_mail(redis_pool: &RedisPool, email: &str, ip: Option<&str>) -> R<()> {
+    let mut con = redis_pool.get().await?;
+
+    if let Some(ip) = ip {
+        limit_ip(
+            &mut con,
+            &rk_pwd_reset_ip!(ip),
+            PWD_RESET_IP_MAX,
+            PWD_RESET_IP_WINDOW,
+        )
+        .await?;
+    }
+
+    let sent: Option<String> = redis::cmd("SET")
+        .arg(rk_pwd_reset_cooldown!(email))
+        .arg("")
+        .arg("NX")
+        .arg("EX")
+        .arg(PWD_RESET_COOLDOWN)
+        .query_async(&mut con)
+        .await?;
+    if sent.is_none() {
+        bail!(ErrKind::RateLimited.err("auth.reset_mail_sent"));
+    }
+    Ok(())
+}
+
+/// IP在时间窗口内的次数加一，超过 max 时返回剩余的秒数
+async fn limit_ip(con: &mut Connection, key: &str, max: u64, window: usize) -> R<()> {
+    let count: u64 = con.incr(key, 1).await?;
+    if count == 1 {
+        let _: bool = con.expire(key, window).await?;
+    }
+    if count > max {
+        let ttl: i64 = con.ttl(key).await?;
+        bail!(ErrKind::RateLimited
+            .err("auth.too_many_attempts")
+            .arg("seconds", ttl.max(1))
+            .build());
+    }
+    Ok(())
+}
+
+/// 使用邮件中的 token 或 手机号+验证码 重置密码
+///
+/// token 只能使用一次，重置后该用户已登陆的设备都需要重新登陆
+pub async fn reset_password(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<RedisPool>,
+    data: ser::auth::ResetPassword,
+    ip: Option<&str>,
+) -> R<()> {
+    let pk: PK = match (data.token, data.phone, data.captcha) {
+        (Some(token), _, _) => {
+            let mut con = redis_pool.get().await?;
+            let key = rk_pwd_reset!(token);
+            let pk: PK = match con.get(&key).await? {
+                Some(pk) => pk,
+                None => bail!("auth.reset_token_invalid"),
+            };
+
+            // 先检查新密码，不能使用时链接仍然有效
+            serv::password::check_reuse(db_pool.clone(), Audience::User, pk, data.password.clone())
+                .await?;
+
+            // 读取和删除必须是原子的，同一个 token 只能使用一次
+            let (used, _): (Option<PK>, usize) = redis::pipe()
+                .atomic()
+                .get(&key)
+                .del(&key)
+                .query_async(&mut con)
+                .await?;
+            if used != Some(pk) {
+                bail!("auth.reset_token_invalid");
+            }
+            serv::password::store(db_pool.clone(), Audience::User, pk, data.password).await?;
+            pk
+        }
+        (None, Some(phone), Some(captcha)) => {
+            verify_phone_code(
+                &redis_pool,
+                &db_pool,
+                SmsPurpose::ResetPassword,
+                &phone,
+                &captcha,
+                ip,
+            )
+            .await?;
+            let pk = User::obj_with_phone(db_pool.clone(), phone).await?.id;
+            serv::user::set_pwd(db_pool, pk, data.password).await?;
+            pk
+        }
+        _ => bail!("auth.reset_target_required"),
+    };
+
+    let mut con = redis_pool.get().await?;
+    let _: () = con.del(rk_pwd_reset_user!(pk)).await?;
+    revoke_sessions(&redis_pool, Audience::User, pk).await
+}
@@ -0,0 +1,286 @@
+/**
+ * 登陆防暴力破解
+ *
+ * 按 账号/手机号 和 IP 统计失败次数，保存在 redis:
+ *
+ * - 同一个账号连续失败 [MAX_FAILURES] 次锁定，第一次锁定 [LOCK_BASE] 秒，24小时内再次锁定时间翻倍
+ * - 同一个IP失败 [IP_MAX_FAILURES] 次后，所有账号都不能再从这个IP登陆，直到 [WINDOW] 过期
+ * - 验证码错误 [CAPTCHA_MAX_FAILURES] 次后作废，需要重新发送
+ *
+ * 锁定事件写入 auth_log 表
+ */
+use crate::models::auth_log::{AuthEvent, AuthLog, NewAuthLog};
+use crate::prelude::*;
+use std::future::Future;
+
+/// 统计失败次数的时间窗口，单位秒，最后一次失败后重新计时
+const WINDOW: usize = 15 * 60;
+
+/// 连续失败多少次锁定
+const MAX_FAILURES: u64 = 5;
+
+/// 第一次锁定的秒数
+const LOCK_BASE: u64 = 5 * 60;
+
+/// 最长锁定秒数
+const LOCK_MAX: u64 = 24 * 60 * 60;
+
+/// 锁定次数保留的秒数，过期后锁定时间从 LOCK_BASE 重新开始
+const LEVEL_TTL: usize = 24 * 60 * 60;
+
+/// 同一个IP在 WINDOW 内最多失败的次数
+const IP_MAX_FAILURES: u64 = 30;
+
+/// 一个验证码最多错误的次数
+const CAPTCHA_MAX_FAILURES: u64 = 3;
+
+/// 登陆方式，管理员和用户的账号是两张表，分开统计
+#[derive(Debug, Clone, Copy)]
+pub enum Scope {
+    Admin,
+    User,
+    Phone,
+
+    /// 管理员的两步验证，按管理员id统计
+    Mfa,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::User => "user",
+            Self::Phone => "phone",
+            Self::Mfa => "mfa",
+        }
+    }
+}
+
+/// 请求的IP，获取不到时(如 unix socket)返回 None，不按IP限制
+///
+/// 开启 TRUST_PROXY 时使用 X-Forwarded-For，反向代理必须覆盖这个请求头，否则可以伪造
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    if conf!().listen.trust_proxy {
+        req.connection_info()
+            .realip_remote_addr()
+            .map(|x| x.to_owned())
+    } else {
+        req.peer_addr().map(|x| x.ip().to_string())
+    }
+}
+
+fn locked(seconds: i64) -> OkErr {
+    ErrKind::RateLimited
+        .err("auth.locked")
+        .arg("seconds", seconds)
+        .build()
+}
+
+/// 登陆前检查账号和IP是否被锁定
+pub async fn check(redis_pool: &RedisPool, scope: Scope, id: &str, ip: Option<&str>) -> R<()> {
+    let mut con = redis_pool.get().await?;
+
+    let ttl: i64 = con.ttl(rk_login_lock!(scope, id)).await?;
+    if ttl > 0 {
+        bail!(locked(ttl));
+    }
+
+    let ip_key = match ip {
+        Some(x) => rk_login_ip!(x),
+        None => return Ok(()),
+    };
+    let ip_fails: Option<u64> = con.get(&ip_key).await?;
+    if ip_fails.unwrap_or_default() >= IP_MAX_FAILURES {
+        let ttl: i64 = con.ttl(&ip_key).await?;
+        bail!(ErrKind::RateLimited
+            .err("auth.too_many_attempts")
+            .arg("seconds", ttl.max(1))
+            .build());
+    }
+
+    Ok(())
+}
+
+/// 记录一次失败，达到次数后锁定并返回锁定的错误
+pub async fn fail(
+    redis_pool: &RedisPool,
+    db_pool: &web::Data<DbPool>,
+    scope: Scope,
+    id: &str,
+    ip: Option<&str>,
+) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let fail_key = rk_login_fail!(scope, id);
+
+    let (fails,): (u64,) = redis::pipe()
+        .atomic()
+        .incr(&fail_key, 1)
+        .expire(&fail_key, WINDOW)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+
+    // 没有IP时不按IP统计
+    if let Some(ip) = ip {
+        let ip_key = rk_login_ip!(ip);
+        let (ip_fails,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(&ip_key, 1)
+            .expire(&ip_key, WINDOW)
+            .ignore()
+            .query_async(&mut con)
+            .await?;
+
+        if ip_fails == IP_MAX_FAILURES {
+            audit(
+                db_pool,
+                AuthEvent::IpLimited,
+                "ip",
+                ip,
+                Some(ip),
+                format!("{}秒内失败{}次", WINDOW, ip_fails),
+            )
+            .await;
+        }
+    }
+
+    if fails < MAX_FAILURES {
+        return Ok(());
+    }
+
+    let level_key = rk_login_level!(scope, id);
+    let (level,): (u32,) = redis::pipe()
+        .atomic()
+        .incr(&level_key, 1)
+        .expire(&level_key, LEVEL_TTL)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+    let seconds = LOCK_BASE
+        .saturating_mul(1 << (level.max(1) - 1).min(16))
+        .min(LOCK_MAX);
+
+    let _: () = redis::pipe()
+        .atomic()
+        .set_ex(rk_login_lock!(scope, id), level, seconds as usize)
+        .ignore()
+        .del(&fail_key)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+
+    audit(
+        db_pool,
+        AuthEvent::Locked,
+        scope.as_str(),
+        id,
+        ip,
+        format!("连续失败{}次，第{}次锁定{}秒", fails, level, seconds),
+    )
+    .await;
+
+    bail!(locked(seconds as i64))
+}
+
+/// 登陆成功，清除失败次数
+pub async fn success(redis_pool: &RedisPool, scope: Scope, id: &str) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let _: () = con
+        .del(&[rk_login_fail!(scope, id), rk_login_level!(scope, id)])
+        .await?;
+    Ok(())
+}
+
+/// 检查锁定后执行登陆，返回 [ErrKind::Auth] 时记录失败
+///
+/// ```
+/// guard::protect(&redis_pool, &db_pool, Scope::Admin, &username, ip.as_deref(), async {
+///     Admin::login(db_pool.clone(), username.clone(), password).await
+/// })
+/// .await?;
+/// ```
+pub async fn protect<T, F>(
+    redis_pool: &RedisPool,
+    db_pool: &web::Data<DbPool>,
+    scope: Scope,
+    id: &str,
+    ip: Option<&str>,
+    f: F,
+) -> R<T>
+where
+    F: Future<Output = R<T>>,
+{
+    check(redis_pool, scope, id, ip).await?;
+
+    match f.await {
+        Ok(v) => {
+            success(redis_pool, scope, id).await?;
+            Ok(v)
+        }
+        Err(e) if e.downcast_ref::<OkErr>().map(|x| x.kind) == Some(ErrKind::Auth) => {
+            fail(redis_pool, db_pool, scope, id, ip).await?;
+            Err(e)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// 验证码错误，达到次数后作废
+pub async fn captcha_fail(
+    redis_pool: &RedisPool,
+    db_pool: &web::Data<DbPool>,
+    code_key: &str,
+    phone: &str,
+    ip: Option<&str>,
+) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let fail_key = format!("{}:fail", code_key);
+
+    let (fails,): (u64,) = redis::pipe()
+        .atomic()
+        .incr(&fail_key, 1)
+        .expire(&fail_key, serv::auth::PHONE_CODE_TTL as usize)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+
+    if fails >= CAPTCHA_MAX_FAILURES {
+        let _: () = con.del(&[code_key, &fail_key]).await?;
+        audit(
+            db_pool,
+            AuthEvent::CaptchaRevoked,
+            Scope::Phone.as_str(),
+            phone,
+            ip,
+            format!("验证码错误{}次", fails),
+        )
+        .await;
+        bail!(ErrKind::BadRequest.err("auth.captcha_revoked"));
+    }
+
+    Ok(())
+}
+
+/// 写入 auth_log，失败时只记录日志，不影响登陆流程
+async fn audit(
+    db_pool: &web::Data<DbPool>,
+    event: AuthEvent,
+    scope: &str,
+    principal: &str,
+    ip: Option<&str>,
+    desc: String,
+) {
+    let ip = ip.unwrap_or_default();
+    log::warn!("[auth] {} {} {} {}", scope, principal, ip, desc);
+
+    let data = NewAuthLog {
+        event: event as u8,
+        scope: scope.to_owned(),
+        principal: principal.to_owned(),
+        ip: ip.to_owned(),
+        desc,
+    };
+    if let Err(err) = AuthLog::create(db_pool.clone(), data).await {
+        log::error!("写入 auth_log 失败: {}", err);
+    }
+}
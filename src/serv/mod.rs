@@ -1,3 +1,11 @@
 pub mod admin;
+pub mod audit;
 pub mod auth;
+pub mod guard;
+pub mod order;
+pub mod password;
+pub mod post;
+pub mod rbac;
+pub mod spike;
+pub mod totp;
 pub mod user;
@@ -0,0 +1,158 @@
+/**
+ * 订单和支付
+ *
+ * - 下单时在同一个事务中扣减库存，订单号使用 [get_order_id!] 生成
+ * - 超过 ORDER_PAY_TIMEOUT 秒未支付，延迟任务取消订单并归还库存，秒杀订单同时归还活动的名额。
+ *   任务丢失时定时任务 [cancel_overdue] 兜底
+ * - 支付平台的异步通知实现 [PayNotify]，在 [notifier] 中注册，验证签名后调用 [complete]
+ */
+use crate::prelude::*;
+use models::order::{Order, OrderDetail, OrderItemNew, OrderSource, OrderStatus, Payment};
+
+/// 超时取消订单的延迟任务，data 是订单号
+pub const CANCEL_TASK: &str = "order.cancel";
+
+/// 一个订单最多几种书
+pub const MAX_ITEMS: usize = 20;
+
+/// 每次兜底取消的最多订单数
+const SWEEP_BATCH: i64 = 100;
+
+/// 支付平台的异步通知，每个支付渠道实现一个
+pub trait PayNotify {
+    /// 验证签名，返回支付成功的结果，其他通知(比如交易关闭)返回 None
+    fn verify(&self, req: &HttpRequest, body: &[u8]) -> R<Option<Payment>>;
+
+    /// 返回给支付平台的响应，失败时支付平台会重试
+    fn reply(&self, ok: bool) -> HttpResponse;
+}
+
+/// 按路由中的渠道名查找，没有开启的渠道返回 None
+pub fn notifier(channel: &str) -> Option<Box<dyn PayNotify>> {
+    match channel {
+        #[cfg(feature = "alipay")]
+        "alipay" => Some(Box::new(alipay::Notify)),
+        _ => None,
+    }
+}
+
+/// 下单，同时创建超时取消的任务，source 见 [OrderSource]
+pub async fn place(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    user_id: PK,
+    items: Vec<OrderItemNew>,
+    source: OrderSource,
+) -> R<OrderDetail> {
+    if items.is_empty() || items.len() > MAX_ITEMS {
+        bail!(ErrKind::BadRequest
+            .err("validation.invalid")
+            .arg("field", "items")
+            .build());
+    }
+
+    let order_no = {
+        let mut con = redis_pool.get().await?;
+        get_order_id!(con, "order")
+    };
+    let timeout = conf!().order_pay_timeout;
+    let expire_at = Local::now().naive_local() + chrono::Duration::seconds(timeout as i64);
+    let detail = Order::place(db_pool, order_no.clone(), user_id, items, expire_at, source).await?;
+
+    // 订单已经创建，任务发送失败只记录日志，由 [cancel_overdue] 兜底取消
+    if let Err(err) =
+        crate::tasks::delay_task::push(redis_pool, CANCEL_TASK, &order_no, timeout).await
+    {
+        log::error!("超时取消任务发送失败: {} {:?}", order_no, err);
+    }
+    Ok(detail)
+}
+
+/// 取消待支付的订单，user_id 见 [Order::cancel]。订单已经不是待支付时返回 false
+pub async fn cancel(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    order_no: String,
+    user_id: Option<PK>,
+) -> R<bool> {
+    Ok(match Order::cancel(db_pool, order_no, user_id).await? {
+        Some(detail) => {
+            release_spike(redis_pool, &detail).await;
+            true
+        }
+        None => false,
+    })
+}
+
+/// 退款，见 [Order::refund]
+///
+/// 取消后支付的订单在取消时已经归还了秒杀名额
+pub async fn refund(db_pool: web::Data<DbPool>, redis_pool: &RedisPool, pk: PK) -> R<Order> {
+    let (detail, restocked) = Order::refund(db_pool, pk).await?;
+    if restocked {
+        release_spike(redis_pool, &detail).await;
+    }
+    Ok(detail.order)
+}
+
+/// 延迟任务调用，订单已支付或已取消时不做任何事
+pub async fn cancel_expired(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    order_no: String,
+) -> R<()> {
+    if cancel(db_pool, redis_pool, order_no.clone(), None).await? {
+        log::info!("订单超时未支付，已取消: {}", order_no);
+    }
+    Ok(())
+}
+
+/// 定时任务调用，取消已经过期但仍然待支付的订单
+///
+/// 超时取消的任务丢失(发送失败、服务中断)时由这里归还库存，返回取消的数量
+pub async fn cancel_overdue(db_pool: web::Data<DbPool>, redis_pool: &RedisPool) -> R<usize> {
+    let mut count = 0;
+    for order_no in Order::overdue(db_pool.clone(), SWEEP_BATCH).await? {
+        match cancel(db_pool.clone(), redis_pool, order_no.clone(), None).await {
+            Ok(true) => count += 1,
+            Ok(false) => {}
+            Err(err) => log::error!("过期订单取消失败: {} {:?}", order_no, err),
+        }
+    }
+    if count > 0 {
+        log::warn!("取消了 {} 个没有被延迟任务取消的过期订单", count);
+    }
+    Ok(count)
+}
+
+/// 秒杀订单关闭后归还活动的库存和限购名额，订单已经关闭，失败只记录日志
+async fn release_spike(redis_pool: &RedisPool, detail: &OrderDetail) {
+    let event_id = match detail.order.spike_event_id {
+        Some(x) => x,
+        None => return,
+    };
+    let num = detail.items.iter().map(|x| x.num).sum();
+    if let Err(err) = serv::spike::release(redis_pool, event_id, detail.order.user_id, num).await {
+        log::error!(
+            "秒杀名额归还失败: {} {} {:?}",
+            detail.order.order_no,
+            event_id,
+            err
+        );
+    }
+}
+
+/// 支付完成，重复的通知直接返回成功
+///
+/// 订单已取消时仍然记录支付，返回成功，避免支付平台重试，需要管理员退款
+pub async fn complete(db_pool: web::Data<DbPool>, payment: Payment) -> R<()> {
+    let (channel, order_no) = (payment.channel, payment.order_no.clone());
+    match Order::mark_paid(db_pool, payment).await? {
+        Some(OrderStatus::PaidAfterCancel) => {
+            log::warn!("订单取消后收到支付，需要退款: {} {}", channel, order_no)
+        }
+        Some(_) => log::info!("订单已支付: {} {}", channel, order_no),
+        None => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+/**
+ * 设置密码的公共流程，管理员和用户共用
+ *
+ * - 新密码不能是当前的密码和最近 PWD_HISTORY 次用过的
+ * - 登陆成功后，argon2 参数比当前配置旧时重新加密
+ * - argon2 比较耗时，加密和验证都在线程池中执行，见 [hash] [verify]
+ */
+use crate::prelude::*;
+use crate::utils::{cache::Cached, password};
+use models::password_history::PasswordHistory;
+use schema::*;
+
+/// 加密密码
+pub async fn hash(pwd: String) -> R<String> {
+    Ok(web::block(move || password::encode(&pwd)).await?)
+}
+
+/// 验证密码
+pub async fn verify(encoded: String, pwd: String) -> R<bool> {
+    Ok(web::block(move || password::verify(&encoded, pwd.as_bytes())).await?)
+}
+
+/// 新密码不能和当前的密码、最近用过的密码一样
+pub async fn check_reuse(
+    db_pool: web::Data<DbPool>,
+    aud: Audience,
+    pk: PK,
+    new_pwd: String,
+) -> R<()> {
+    let history = conf!().pwd.history;
+    if history == 0 {
+        return Ok(());
+    }
+
+    // 历史记录从开启后才有，也可能是命令行创建的账号，当前的密码单独检查
+    let mut recent = PasswordHistory::recent(db_pool.clone(), aud, pk, history as i64).await?;
+    recent.extend(current_hash(db_pool, aud, pk).await?);
+    let reused = web::block(move || {
+        recent
+            .iter()
+            .any(|x| password::verify(x, new_pwd.as_bytes()))
+    })
+    .await?;
+    if reused {
+        bail!(ErrKind::BadRequest
+            .err("user.password_reused")
+            .arg("count", history)
+            .build());
+    }
+    Ok(())
+}
+
+/// 记录设置的密码(已加密)，创建账号和修改密码后调用
+pub async fn remember(db_pool: web::Data<DbPool>, aud: Audience, pk: PK, hash: String) -> R<()> {
+    let history = conf!().pwd.history;
+    if history == 0 {
+        return Ok(());
+    }
+    PasswordHistory::add(db_pool, aud, pk, hash, history as i64).await
+}
+
+/// 检查是否重复后保存新密码，返回修改的行数
+pub async fn set(db_pool: web::Data<DbPool>, aud: Audience, pk: PK, new_pwd: String) -> R<usize> {
+    check_reuse(db_pool.clone(), aud, pk, new_pwd.clone()).await?;
+    store(db_pool, aud, pk, new_pwd).await
+}
+
+/// 保存新密码并记录到历史，调用前已经使用 [check_reuse] 检查过
+pub async fn store(db_pool: web::Data<DbPool>, aud: Audience, pk: PK, new_pwd: String) -> R<usize> {
+    let hash = hash(new_pwd).await?;
+    let rows = save_hash(db_pool.clone(), aud, pk, hash.clone()).await?;
+    remember(db_pool, aud, pk, hash).await?;
+    Ok(rows)
+}
+
+/// 登陆成功后调用，argon2 参数已改变时使用明文密码重新加密
+///
+/// 失败只记录日志，不影响登陆
+pub async fn upgrade_hash(
+    db_pool: web::Data<DbPool>,
+    aud: Audience,
+    pk: PK,
+    encoded: &str,
+    pwd: &str,
+) {
+    if !password::needs_rehash(encoded) {
+        return;
+    }
+
+    let saved = async { save_hash(db_pool, aud, pk, hash(pwd.to_owned()).await?).await };
+    match saved.await {
+        Ok(_) => log::info!("密码已使用新的参数加密: {} {}", aud.as_str(), pk),
+        Err(err) => log::error!("密码重新加密失败: {} {} {}", aud.as_str(), pk, err),
+    }
+}
+
+/// 数据库中当前的密码，不使用缓存
+async fn current_hash(db_pool: web::Data<DbPool>, aud: Audience, pk: PK) -> R<Option<String>> {
+    web::block(move || -> R<Option<String>> {
+        let mut conn = db_pool.get()?;
+        Ok(match aud {
+            Audience::Admin => admin::table
+                .find(pk)
+                .select(admin::password)
+                .first::<String>(&mut conn)
+                .optional()?,
+            Audience::User => users::table
+                .find(pk)
+                .select(users::password)
+                .first::<Option<String>>(&mut conn)
+                .optional()?
+                .flatten(),
+        })
+    })
+    .await?
+}
+
+async fn save_hash(db_pool: web::Data<DbPool>, aud: Audience, pk: PK, hash: String) -> R<usize> {
+    let rows = web::block(move || -> R<usize> {
+        let mut conn = db_pool.get()?;
+        Ok(match aud {
+            Audience::Admin => diesel::update(admin::table.find(pk))
+                .set(admin::password.eq(hash))
+                .execute(&mut conn)?,
+            Audience::User => diesel::update(users::table.find(pk))
+                .set(users::password.eq(hash))
+                .execute(&mut conn)?,
+        })
+    })
+    .await??;
+    match aud {
+        Audience::Admin => Cached::<models::admin::Admin>::invalidate(pk).await,
+        Audience::User => Cached::<models::user::User>::invalidate(pk).await,
+    }
+    Ok(rows)
+}
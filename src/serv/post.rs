@@ -0,0 +1,89 @@
+/**
+ * 帖子
+ *
+ * 作者本人可以修改、删除自己的帖子，管理员需要 post.update、post.delete 权限，
+ * 管理员的操作记录到操作日志
+ */
+use crate::prelude::*;
+use models::{
+    admin_log::ActionFlag,
+    post::{Post, PostUpdate},
+};
+
+/// 作者本人或者有权限的管理员，返回帖子
+pub async fn editable(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    claims: &Claims,
+    pk: PK,
+    permission: &str,
+) -> R<Post> {
+    let owner = serv::rbac::owner_scope(db_pool.clone(), redis_pool, claims, permission).await?;
+    let post = Post::obj(db_pool, pk).await?;
+    if owner.map_or(false, |x| x != post.user_id) {
+        bail!(ErrKind::Forbidden.err("auth.forbidden"));
+    }
+    Ok(post)
+}
+
+/// 修改帖子
+pub async fn update(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    claims: &Claims,
+    ip: String,
+    pk: PK,
+    data: PostUpdate,
+) -> R<usize> {
+    let before = editable(
+        db_pool.clone(),
+        redis_pool,
+        claims,
+        pk,
+        crate::perm::POST_UPDATE.0,
+    )
+    .await?;
+    let rows = Post::update(db_pool.clone(), pk, data).await?;
+
+    if claims.aud == Audience::Admin {
+        let after = Post::obj(db_pool.clone(), pk).await?;
+        let mut log = actor(claims, ip).entry(ActionFlag::UPDATE, "post.update", "post", Some(pk));
+        log.changes = serv::audit::diff(Some(&before), Some(&after));
+        serv::audit::record(db_pool, vec![log]).await;
+    }
+    Ok(rows)
+}
+
+/// 删除帖子
+pub async fn del(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    claims: &Claims,
+    ip: String,
+    pk: PK,
+) -> R<usize> {
+    let before = editable(
+        db_pool.clone(),
+        redis_pool,
+        claims,
+        pk,
+        crate::perm::POST_DELETE.0,
+    )
+    .await?;
+    let rows = Post::del(db_pool.clone(), pk).await?;
+
+    if claims.aud == Audience::Admin {
+        let mut log = actor(claims, ip).entry(ActionFlag::DEL, "post.del", "post", Some(pk));
+        log.desc = format!("删除帖子 {}", before.title);
+        log.changes = serv::audit::diff(Some(&before), None);
+        serv::audit::record(db_pool, vec![log]).await;
+    }
+    Ok(rows)
+}
+
+fn actor(claims: &Claims, ip: String) -> serv::audit::Actor {
+    serv::audit::Actor {
+        admin_id: claims.id,
+        ip,
+    }
+}
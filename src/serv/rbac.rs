@@ -0,0 +1,316 @@
+/**
+ * 管理员权限
+ *
+ * 有效权限 = 直接分配的权限(m2m_admin_permission) + 所在组的权限(m2m_group_permission)
+ *
+ * 结果缓存在 redis [CACHE_TTL] 秒，超级管理员不检查权限:
+ *
+ * - 修改、删除某个管理员后调用 [invalidate]
+ * - 修改组、权限以及它们的分配后调用 [invalidate_all]，使用版本号让所有缓存失效
+ */
+use crate::prelude::*;
+use models::{
+    admin::Admin,
+    auth_group::Group,
+    auth_permission::{Permission, PermissionUpdate},
+    m2m_admin_group::AdminGroup,
+    m2m_admin_permission::AdminPerm,
+    m2m_group_permission::GroupPermission,
+};
+use std::collections::BTreeSet;
+
+/// 权限缓存的秒数
+const CACHE_TTL: usize = 60 * 60;
+
+/// 管理员的有效权限
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminPerms {
+    pub superadmin: bool,
+    pub perms: BTreeSet<String>,
+}
+
+impl AdminPerms {
+    pub fn has(&self, name: &str) -> bool {
+        self.superadmin || self.perms.contains(name)
+    }
+}
+
+/// 当前的缓存版本
+async fn cache_ver(con: &mut deadpool_redis::Connection) -> R<u64> {
+    let ver: Option<u64> = con.get(rk_perm_ver!()).await?;
+    Ok(ver.unwrap_or_default())
+}
+
+/// 获取管理员的有效权限，优先读取缓存
+pub async fn admin_perms(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+) -> R<AdminPerms> {
+    let mut con = redis_pool.get().await?;
+    let key = rk_admin_perms!(cache_ver(&mut con).await?, pk);
+
+    let cached: Option<String> = con.get(&key).await?;
+    if let Some(cached) = cached {
+        return Ok(serde_json::from_str(&cached)?);
+    }
+
+    let admin = Admin::obj(db_pool.clone(), pk).await?;
+    let perms = AdminPerms {
+        superadmin: admin.is_superadmin,
+        perms: if admin.is_superadmin {
+            BTreeSet::new()
+        } else {
+            Permission::names_of_admin(db_pool, pk)
+                .await?
+                .into_iter()
+                .collect()
+        },
+    };
+
+    let _: () = con
+        .set_ex(&key, serde_json::to_string(&perms)?, CACHE_TTL)
+        .await?;
+    Ok(perms)
+}
+
+/// 没有权限时返回 [ErrKind::Forbidden]
+pub async fn check(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+    name: &str,
+) -> R<()> {
+    if !admin_perms(db_pool, redis_pool, pk).await?.has(name) {
+        bail!(ErrKind::Forbidden
+            .err("auth.permission_denied")
+            .arg("permission", name)
+            .build());
+    }
+    Ok(())
+}
+
+/// 修改用户数据的范围: 用户只能修改自己的，返回 Some(用户id)；管理员需要有权限，返回 None 不限制
+pub async fn owner_scope(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    claims: &Claims,
+    name: &str,
+) -> R<Option<PK>> {
+    match claims.aud {
+        Audience::User => Ok(Some(claims.id)),
+        Audience::Admin => {
+            check(db_pool, redis_pool, claims.id, name).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// 某个管理员的缓存失效
+pub async fn invalidate(redis_pool: &RedisPool, pk: PK) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let key = rk_admin_perms!(cache_ver(&mut con).await?, pk);
+    let _: usize = con.del(key).await?;
+    Ok(())
+}
+
+/// 所有管理员的缓存失效，旧版本的缓存等待过期
+pub async fn invalidate_all(redis_pool: &RedisPool) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let _: u64 = con.incr(rk_perm_ver!(), 1).await?;
+    Ok(())
+}
+
+/// 非超级管理员只能分配自己拥有的权限，避免给自己或别人提升权限
+async fn ensure_grantable(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    operator: &Admin,
+    names: Vec<String>,
+) -> R<()> {
+    if operator.is_superadmin {
+        return Ok(());
+    }
+
+    let own = admin_perms(db_pool, redis_pool, operator.id).await?;
+    if let Some(name) = names.iter().find(|x| !own.has(x)) {
+        bail!(ErrKind::Forbidden
+            .err("auth.permission_denied")
+            .arg("permission", name)
+            .build());
+    }
+    Ok(())
+}
+
+/// 管理员的组、直接分配的权限和有效权限
+pub async fn admin_detail(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+) -> R<ser::rbac::AdminPermissions> {
+    let perms = admin_perms(db_pool.clone(), redis_pool, pk).await?;
+    Ok(ser::rbac::AdminPermissions {
+        superadmin: perms.superadmin,
+        groups: Group::of_admin(db_pool.clone(), pk).await?,
+        direct: Permission::of_admin(db_pool, pk).await?,
+        effective: perms.perms.into_iter().collect(),
+    })
+}
+
+/// 给管理员分配组
+pub async fn grant_admin_groups(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    operator: &Admin,
+    pk: PK,
+    group_ids: Vec<PK>,
+) -> R<usize> {
+    let names = Permission::names_of_groups(db_pool.clone(), group_ids.clone()).await?;
+    ensure_grantable(db_pool.clone(), redis_pool, operator, names).await?;
+
+    let rows = AdminGroup::grant(db_pool, pk, group_ids).await?;
+    invalidate(redis_pool, pk).await?;
+    Ok(rows)
+}
+
+/// 取消管理员的组
+pub async fn revoke_admin_groups(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+    group_ids: Vec<PK>,
+) -> R<usize> {
+    let rows = AdminGroup::revoke(db_pool, pk, group_ids).await?;
+    invalidate(redis_pool, pk).await?;
+    Ok(rows)
+}
+
+/// 给管理员直接分配权限
+pub async fn grant_admin_permissions(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    operator: &Admin,
+    pk: PK,
+    permission_ids: Vec<PK>,
+) -> R<usize> {
+    let names = Permission::names_by_ids(db_pool.clone(), permission_ids.clone()).await?;
+    ensure_grantable(db_pool.clone(), redis_pool, operator, names).await?;
+
+    let rows = AdminPerm::grant(db_pool, pk, permission_ids).await?;
+    invalidate(redis_pool, pk).await?;
+    Ok(rows)
+}
+
+/// 取消管理员直接分配的权限，通过组获得的权限不受影响
+pub async fn revoke_admin_permissions(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+    permission_ids: Vec<PK>,
+) -> R<usize> {
+    let rows = AdminPerm::revoke(db_pool, pk, permission_ids).await?;
+    invalidate(redis_pool, pk).await?;
+    Ok(rows)
+}
+
+/// 给组分配权限，组内所有管理员的缓存失效
+pub async fn grant_group_permissions(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    operator: &Admin,
+    group_id: PK,
+    permission_ids: Vec<PK>,
+) -> R<usize> {
+    let names = Permission::names_by_ids(db_pool.clone(), permission_ids.clone()).await?;
+    ensure_grantable(db_pool.clone(), redis_pool, operator, names).await?;
+
+    let rows = GroupPermission::grant(db_pool, group_id, permission_ids).await?;
+    invalidate_all(redis_pool).await?;
+    Ok(rows)
+}
+
+/// 取消组的权限
+pub async fn revoke_group_permissions(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    group_id: PK,
+    permission_ids: Vec<PK>,
+) -> R<usize> {
+    let rows = GroupPermission::revoke(db_pool, group_id, permission_ids).await?;
+    invalidate_all(redis_pool).await?;
+    Ok(rows)
+}
+
+/// 删除组，所有管理员的缓存失效
+pub async fn del_groups(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pks: Vec<PK>,
+) -> R<usize> {
+    let rows = Group::del(db_pool, pks).await?;
+    invalidate_all(redis_pool).await?;
+    Ok(rows)
+}
+
+/// 修改权限，权限名改变后所有管理员的缓存失效
+pub async fn update_permission(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+    data: PermissionUpdate,
+) -> R<usize> {
+    let renamed = data.name.is_some();
+    if renamed {
+        let old = Permission::obj(db_pool.clone(), pk).await?;
+        ensure_not_declared(&[old.name])?;
+    }
+    let rows = Permission::update(db_pool, pk, data).await?;
+    if renamed {
+        invalidate_all(redis_pool).await?;
+    }
+    Ok(rows)
+}
+
+/// 删除权限，所有管理员的缓存失效
+pub async fn del_permissions(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pks: Vec<PK>,
+) -> R<usize> {
+    ensure_not_declared(&Permission::names_by_ids(db_pool.clone(), pks.clone()).await?)?;
+    let rows = Permission::del(db_pool, pks).await?;
+    invalidate_all(redis_pool).await?;
+    Ok(rows)
+}
+
+/// 代码中声明的权限不能改名或删除，否则下次启动时又会创建，已有的分配会丢失
+fn ensure_not_declared(names: &[String]) -> R<()> {
+    let declared = names.iter().find(|x| {
+        crate::perm::CATALOG
+            .iter()
+            .any(|def| def.name == x.as_str())
+    });
+    if let Some(name) = declared {
+        bail!(ErrKind::BadRequest
+            .err("rbac.permission_declared")
+            .arg("permission", name)
+            .build());
+    }
+    Ok(())
+}
+
+/// 启动时把 [crate::perm::CATALOG] 同步到 auth_permission
+pub async fn sync_catalog(db_pool: web::Data<DbPool>) -> R<()> {
+    let catalog = crate::perm::CATALOG
+        .iter()
+        .map(|x| (x.name.to_owned(), x.desc.to_owned()))
+        .collect();
+    let res = Permission::sync_catalog(db_pool, catalog).await?;
+    log::info!(
+        "权限目录已同步: 新增 {}，更新说明 {}，新标记为孤立 {}",
+        res.created,
+        res.updated,
+        res.orphaned
+    );
+    Ok(())
+}
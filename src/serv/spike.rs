@@ -0,0 +1,289 @@
+/**
+ * 秒杀
+ *
+ * 活动保存在 spike_events，创建后和启动时预热到 redis [rk_spike!]。抢购在 lua 脚本中原子执行:
+ * 检查时间、每人限购、库存，扣减后写入队列，[crate::queues::spike] 异步创建订单
+ *
+ * 同一个用户的 request_id 只处理一次，结果保存在 [rk_spike_ticket!]，用来查询是否已生成订单
+ */
+use crate::prelude::*;
+use models::{
+    order::{OrderItemNew, OrderSource},
+    spike_event::SpikeEvent,
+};
+use once_cell::sync::Lazy;
+
+/// ticket 保存的秒数
+const TICKET_TTL: usize = 60 * 60 * 24;
+
+/// 创建订单失败的 ticket 值
+const TICKET_FAILED: &str = "failed";
+
+/// 活动结束后 redis 中的数据再保留的秒数
+const KEEP_AFTER_END: i64 = 60 * 60 * 24;
+
+/// KEYS: 活动 用户已抢数量 ticket 队列
+///
+/// ARGV: 用户id 数量 当前毫秒 request_id 活动id ticket秒数
+///
+/// 用户已抢数量在第一次抢购时才创建，过期时间和活动一致
+static GRAB: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+        if redis.call('exists', KEYS[3]) == 1 then
+            return 0
+        end
+
+        local event = redis.call('hmget', KEYS[1], 'stock', 'begin', 'end', 'limit')
+        if not event[1] then
+            return -1
+        end
+        local now = tonumber(ARGV[3])
+        if now < tonumber(event[2]) then
+            return -2
+        end
+        if now > tonumber(event[3]) then
+            return -3
+        end
+
+        local num = tonumber(ARGV[2])
+        local bought = tonumber(redis.call('hget', KEYS[2], ARGV[1]) or '0')
+        if bought + num > tonumber(event[4]) then
+            return -4
+        end
+        if tonumber(event[1]) < num then
+            return -5
+        end
+
+        redis.call('hincrby', KEYS[1], 'stock', -num)
+        redis.call('hincrby', KEYS[2], ARGV[1], num)
+        local ttl = redis.call('pttl', KEYS[1])
+        if ttl > 0 then
+            redis.call('pexpire', KEYS[2], ttl)
+        end
+        redis.call('set', KEYS[3], '', 'EX', ARGV[6])
+        redis.call('xadd', KEYS[4], '*', 'event_id', ARGV[5], 'user_id', ARGV[1], 'num', ARGV[2], 'request_id', ARGV[4])
+        return 1
+    ",
+    )
+});
+
+/// 活动还在 redis 中时归还库存和限购名额，活动已删除或过期时不处理
+///
+/// KEYS: 活动 用户已抢数量
+///
+/// ARGV: 用户id 数量
+static RELEASE: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+        if redis.call('exists', KEYS[1]) == 0 then
+            return 0
+        end
+        redis.call('hincrby', KEYS[1], 'stock', ARGV[2])
+        if redis.call('hincrby', KEYS[2], ARGV[1], -tonumber(ARGV[2])) <= 0 then
+            redis.call('hdel', KEYS[2], ARGV[1])
+        end
+        return 1
+    ",
+    )
+});
+
+/// 抢购请求
+#[derive(Validate, Deserialize, Debug)]
+pub struct Grab {
+    #[validate(range(min = 1, max = 999))]
+    pub num: u32,
+
+    /// 客户端生成，重试时使用同一个
+    #[validate(length(min = 8, max = 64))]
+    pub request_id: String,
+}
+
+/// 抢购结果
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum Ticket {
+    /// 排队创建订单中
+    Queued,
+    Ordered {
+        order_no: String,
+    },
+
+    /// 库存不足等原因没有创建订单，已归还名额
+    Failed,
+}
+
+/// 写入 redis，已经存在时不覆盖，避免把已扣减的库存恢复
+pub async fn preload(redis_pool: &RedisPool, event: &SpikeEvent) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let key = rk_spike!(event.id);
+    let ttl = (local_ms(&event.end_at) - timestamp_ms!()) / 1000 + KEEP_AFTER_END;
+
+    let created: bool = con.hset_nx(&key, "stock", event.stock).await?;
+    if created {
+        let _: () = con
+            .hset_multiple(
+                &key,
+                &[
+                    ("begin", local_ms(&event.begin_at)),
+                    ("end", local_ms(&event.end_at)),
+                    ("limit", event.per_user_limit as i64),
+                ],
+            )
+            .await?;
+    }
+    let ttl = ttl.max(1) as usize;
+    let _: bool = con.expire(&key, ttl).await?;
+    // 重启时已经存在的才有效，之后创建的在 GRAB 中设置
+    let _: bool = con.expire(rk_spike_users!(event.id), ttl).await?;
+    Ok(())
+}
+
+/// 启动时预热所有未结束的活动
+pub async fn preload_active(db_pool: web::Data<DbPool>, redis_pool: &RedisPool) -> R<()> {
+    let events = SpikeEvent::active(db_pool).await?;
+    for event in events.iter() {
+        preload(redis_pool, event).await?;
+    }
+    log::info!("秒杀活动已预热: {}", events.len());
+    Ok(())
+}
+
+/// 删除活动和 redis 中的数据
+pub async fn del(db_pool: web::Data<DbPool>, redis_pool: &RedisPool, pk: PK) -> R<usize> {
+    let rows = SpikeEvent::del(db_pool, pk).await?;
+    let mut con = redis_pool.get().await?;
+    let _: usize = con.del(&[rk_spike!(pk), rk_spike_users!(pk)]).await?;
+    Ok(rows)
+}
+
+/// 归还库存和限购名额，订单创建失败、秒杀订单取消或退款时调用
+pub async fn release(redis_pool: &RedisPool, event_id: PK, user_id: PK, num: u32) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let _: i32 = RELEASE
+        .key(rk_spike!(event_id))
+        .key(rk_spike_users!(event_id))
+        .arg(user_id)
+        .arg(num)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(())
+}
+
+/// 抢购，成功后返回排队中，重复的 request_id 返回上次的结果
+pub async fn grab(redis_pool: &RedisPool, user_id: PK, event_id: PK, data: Grab) -> R<Ticket> {
+    let mut con = redis_pool.get().await?;
+    let code: i32 = GRAB
+        .key(rk_spike!(event_id))
+        .key(rk_spike_users!(event_id))
+        .key(rk_spike_ticket!(user_id, &data.request_id))
+        .key(crate::queues::spike::key())
+        .arg(user_id)
+        .arg(data.num)
+        .arg(timestamp_ms!())
+        .arg(&data.request_id)
+        .arg(event_id)
+        .arg(TICKET_TTL)
+        .invoke_async(&mut con)
+        .await?;
+
+    let err = match code {
+        1 => return Ok(Ticket::Queued),
+        0 => return ticket(redis_pool, user_id, &data.request_id).await,
+        -1 => "common.not_found",
+        -2 => "spike.not_started",
+        -3 => "spike.ended",
+        -4 => "spike.limit",
+        _ => "spike.sold_out",
+    };
+    bail!(ErrKind::BadRequest.err(err))
+}
+
+/// 查询抢购结果
+pub async fn ticket(redis_pool: &RedisPool, user_id: PK, request_id: &str) -> R<Ticket> {
+    let mut con = redis_pool.get().await?;
+    let value: Option<String> = con.get(rk_spike_ticket!(user_id, request_id)).await?;
+    Ok(match value.as_deref() {
+        None => bail!(ErrKind::NotFound.err("common.not_found")),
+        Some("") => Ticket::Queued,
+        Some(TICKET_FAILED) => Ticket::Failed,
+        Some(order_no) => Ticket::Ordered {
+            order_no: order_no.to_owned(),
+        },
+    })
+}
+
+/// 队列中的一条抢购记录
+#[derive(Debug)]
+pub struct Entry {
+    pub event_id: PK,
+    pub user_id: PK,
+    pub num: u32,
+    pub request_id: String,
+}
+
+/// 消费者调用，使用秒杀价创建订单。业务错误(活动或书不存在、库存不足等)时归还 redis 中的库存和限购名额
+///
+/// 数据库、redis 不可用时返回错误，消息不 ack，稍后重试
+///
+/// 订单使用 request_id 在数据库中去重，写入 ticket 前中断时重复消费返回已有的订单。
+/// ticket 不为空时直接跳过
+pub async fn create_order(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    entry: Entry,
+) -> R<()> {
+    let ticket_key = rk_spike_ticket!(entry.user_id, &entry.request_id);
+    let mut con = redis_pool.get().await?;
+    let value: Option<String> = con.get(&ticket_key).await?;
+    if value.map_or(false, |x| !x.is_empty()) {
+        return Ok(());
+    }
+
+    let placed = match SpikeEvent::obj(db_pool.clone(), entry.event_id).await {
+        Ok(event) => {
+            let item = OrderItemNew {
+                book_id: event.book_id,
+                num: entry.num,
+                price: Some(event.price),
+            };
+            let source = OrderSource {
+                request_id: Some(entry.request_id.clone()),
+                spike_event_id: Some(entry.event_id),
+            };
+            serv::order::place(db_pool, redis_pool, entry.user_id, vec![item], source).await
+        }
+        Err(err) => Err(err),
+    };
+
+    match placed {
+        Ok(detail) => {
+            let _: () = con
+                .set_ex(&ticket_key, &detail.order.order_no, TICKET_TTL)
+                .await?;
+        }
+        Err(err) if !is_rejected(&err) => return Err(err),
+        Err(err) => {
+            log::warn!("秒杀订单创建失败，归还名额: {:?} {:?}", entry, err);
+            release(redis_pool, entry.event_id, entry.user_id, entry.num).await?;
+            let _: () = con.set_ex(&ticket_key, TICKET_FAILED, TICKET_TTL).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 是否是业务上的失败，重试也不会成功
+fn is_rejected(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<OkErr>().map_or(false, |x| {
+        !matches!(x.kind, ErrKind::Internal | ErrKind::Upstream)
+    })
+}
+
+/// 数据库中的时间按本地时区转换成毫秒时间戳
+fn local_ms(t: &NaiveDateTime) -> i64 {
+    Local
+        .from_local_datetime(t)
+        .single()
+        .map(|x| x.timestamp_millis())
+        .unwrap_or_else(|| t.timestamp_millis())
+}
@@ -0,0 +1,285 @@
+/**
+ * 管理员两步验证
+ *
+ * - 绑定: enroll 生成密钥，activate 验证一次验证码后开启，返回恢复码
+ * - 登陆: 密码正确后返回 mfa_token，再使用验证码或恢复码换取 token，见 [crate::serv::auth::admin_login]
+ * - 超级管理员可以强制所有管理员开启，没有绑定的管理员登陆时必须先绑定
+ */
+use crate::prelude::*;
+use crate::utils::totp;
+use models::{
+    admin::Admin,
+    sys_setting::{SysSetting, ADMIN_FORCE_2FA},
+};
+use ser::admin::TotpEnroll;
+
+/// mfa_token 有效期，单位秒
+pub const MFA_PENDING_TTL: u64 = 5 * 60;
+
+/// 周期比上次使用的大才保存，否则返回 0
+const USE_STEP_SCRIPT: &str = r"
+local last = tonumber(redis.call('GET', KEYS[1]) or '-1')
+if tonumber(ARGV[1]) <= last then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+return 1
+";
+
+/// 读取并删除 mfa_token，返回 [管理员id, 剩余毫秒数]，不存在时返回 nil
+const TAKE_PENDING_SCRIPT: &str = r"
+local pk = redis.call('GET', KEYS[1])
+if not pk then
+    return false
+end
+local ttl = redis.call('PTTL', KEYS[1])
+redis.call('DEL', KEYS[1])
+return {pk, ttl}
+";
+
+/// 是否强制所有管理员开启两步验证
+pub async fn forced(db_pool: web::Data<DbPool>) -> R<bool> {
+    SysSetting::flag(db_pool, ADMIN_FORCE_2FA).await
+}
+
+/// 超级管理员设置是否强制开启
+pub async fn set_forced(db_pool: web::Data<DbPool>, enabled: bool) -> R<()> {
+    let value = if enabled { "1" } else { "0" };
+    SysSetting::set(db_pool, ADMIN_FORCE_2FA, value.to_owned()).await
+}
+
+/// 登陆时是否需要两步验证
+pub async fn required(db_pool: web::Data<DbPool>, admin: &Admin) -> R<bool> {
+    if admin.totp_enabled {
+        return Ok(true);
+    }
+    forced(db_pool).await
+}
+
+/// 密码验证通过，保存等待两步验证的管理员，返回 mfa_token
+pub async fn create_pending(redis_pool: &RedisPool, pk: PK) -> R<String> {
+    let mut con = redis_pool.get().await?;
+    let token = random_token!(32);
+    let _: () = con
+        .set_ex(rk_mfa_pending!(token), pk, MFA_PENDING_TTL as usize)
+        .await?;
+    Ok(token)
+}
+
+/// mfa_token 对应的管理员id，不删除，绑定时使用
+pub async fn pending_admin(redis_pool: &RedisPool, token: &str) -> R<PK> {
+    let mut con = redis_pool.get().await?;
+    let pk: Option<PK> = con.get(rk_mfa_pending!(token)).await?;
+    match pk {
+        Some(pk) => Ok(pk),
+        None => bail!(ErrKind::Auth.err("auth.mfa_token_invalid")),
+    }
+}
+
+/// 取出并删除 mfa_token，返回管理员id和剩余的毫秒数
+///
+/// 同一个 token 只有一个请求能取到，验证失败后使用 [restore_pending] 放回
+pub async fn take_pending(redis_pool: &RedisPool, token: &str) -> R<(PK, i64)> {
+    let mut con = redis_pool.get().await?;
+    let pending: Option<(PK, i64)> = redis::Script::new(TAKE_PENDING_SCRIPT)
+        .key(rk_mfa_pending!(token))
+        .invoke_async(&mut con)
+        .await?;
+    match pending {
+        Some(x) => Ok(x),
+        None => bail!(ErrKind::Auth.err("auth.mfa_token_invalid")),
+    }
+}
+
+/// 放回 mfa_token，有效期为取出时剩余的时间
+pub async fn restore_pending(redis_pool: &RedisPool, token: &str, pk: PK, ttl_ms: i64) -> R<()> {
+    if ttl_ms <= 0 {
+        return Ok(());
+    }
+    let mut con = redis_pool.get().await?;
+    let _: Option<String> = redis::cmd("SET")
+        .arg(rk_mfa_pending!(token))
+        .arg(pk)
+        .arg("PX")
+        .arg(ttl_ms)
+        .arg("NX")
+        .query_async(&mut con)
+        .await?;
+    Ok(())
+}
+
+/// 生成新的密钥，已开启时需要先关闭
+///
+/// 密钥只在这里返回一次，数据库中保存加密后的
+pub async fn enroll(db_pool: web::Data<DbPool>, admin: &Admin) -> R<TotpEnroll> {
+    if admin.totp_enabled {
+        bail!("auth.totp_enabled");
+    }
+
+    let secret = totp::generate_secret();
+    Admin::set_totp_secret(db_pool, admin.id, totp::encrypt(&secret)?).await?;
+    enroll_info(admin, secret)
+}
+
+/// 登陆时绑定，同一个 mfa_token 只生成一次密钥，重复调用返回已生成的密钥
+pub async fn enroll_pending(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    token: &str,
+) -> R<TotpEnroll> {
+    let pk = pending_admin(redis_pool, token).await?;
+    let admin = Admin::obj_with_secrets(db_pool.clone(), pk).await?;
+    if admin.totp_enabled {
+        bail!("auth.totp_enabled");
+    }
+
+    let mut con = redis_pool.get().await?;
+    let first: Option<String> = redis::cmd("SET")
+        .arg(rk_mfa_enrolled!(token))
+        .arg(pk)
+        .arg("EX")
+        .arg(MFA_PENDING_TTL)
+        .arg("NX")
+        .query_async(&mut con)
+        .await?;
+    if first.is_some() {
+        return enroll(db_pool, &admin).await;
+    }
+
+    // 已经绑定过，返回还没有验证的密钥，不能覆盖
+    match admin.totp_secret {
+        Some(ref s) => enroll_info(&admin, totp::decrypt(s)?),
+        None => bail!(ErrKind::Auth.err("auth.mfa_token_invalid")),
+    }
+}
+
+fn enroll_info(admin: &Admin, secret: String) -> R<TotpEnroll> {
+    Ok(TotpEnroll {
+        otpauth_uri: totp::otpauth_uri(&admin.username, &secret)?,
+        secret,
+    })
+}
+
+/// 验证 TOTP 验证码，同一个周期的验证码只能使用一次
+///
+/// 密钥不缓存，从数据库读取
+pub async fn check_code(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    pk: PK,
+    code: &str,
+) -> R<()> {
+    let admin = Admin::obj_with_secrets(db_pool, pk).await?;
+    let secret = match admin.totp_secret {
+        Some(ref s) => totp::decrypt(s)?,
+        None => bail!("auth.totp_not_enrolled"),
+    };
+
+    let step = match totp::verify(&secret, code)? {
+        Some(step) => step,
+        None => bail!(ErrKind::Auth.err("auth.invalid_totp")),
+    };
+
+    // 保留到验证码的有效时间窗口之后
+    let mut con = redis_pool.get().await?;
+    let fresh: bool = redis::Script::new(USE_STEP_SCRIPT)
+        .key(rk_totp_step!(admin.id))
+        .arg(step)
+        .arg(120)
+        .invoke_async(&mut con)
+        .await?;
+    if !fresh {
+        bail!(ErrKind::Auth.err("auth.invalid_totp"));
+    }
+    Ok(())
+}
+
+/// 绑定后验证一次验证码，开启两步验证，返回恢复码
+pub async fn activate(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    admin: &Admin,
+    code: &str,
+) -> R<Vec<String>> {
+    if admin.totp_enabled {
+        bail!("auth.totp_enabled");
+    }
+    check_code(db_pool.clone(), redis_pool, admin.id, code).await?;
+
+    let (codes, hashes) = totp::generate_recovery_codes()?;
+    Admin::enable_totp(db_pool, admin.id, hashes).await?;
+    Ok(codes)
+}
+
+/// 验证 验证码 或 恢复码，恢复码使用后作废
+pub async fn verify_second_factor(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    admin: &Admin,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> R<()> {
+    if !admin.totp_enabled {
+        bail!("auth.totp_not_enabled");
+    }
+
+    match (code, recovery_code) {
+        (_, Some(recovery_code)) => {
+            let secrets = Admin::obj_with_secrets(db_pool.clone(), admin.id).await?;
+            let old = secrets.recovery_codes.unwrap_or_default();
+            let rest = match totp::use_recovery_code(Some(&old), recovery_code)? {
+                Some(rest) => rest,
+                None => bail!(ErrKind::Auth.err("auth.invalid_recovery_code")),
+            };
+            // 恢复码已经被其他请求修改时同样作为错误的恢复码
+            if !Admin::use_recovery_code(db_pool, admin.id, old, rest).await? {
+                bail!(ErrKind::Auth.err("auth.invalid_recovery_code"));
+            }
+            Ok(())
+        }
+        (Some(code), None) => check_code(db_pool, redis_pool, admin.id, code).await,
+        (None, None) => bail!("auth.totp_code_required"),
+    }
+}
+
+/// 重新生成恢复码，旧的全部作废
+pub async fn regenerate_recovery_codes(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    admin: &Admin,
+    code: &str,
+) -> R<Vec<String>> {
+    if !admin.totp_enabled {
+        bail!("auth.totp_not_enabled");
+    }
+    check_code(db_pool.clone(), redis_pool, admin.id, code).await?;
+
+    let (codes, hashes) = totp::generate_recovery_codes()?;
+    Admin::set_recovery_codes(db_pool, admin.id, hashes).await?;
+    Ok(codes)
+}
+
+/// 管理员自己关闭两步验证，强制开启时不能关闭
+pub async fn disable(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    admin: &Admin,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> R<()> {
+    if forced(db_pool.clone()).await? {
+        bail!(ErrKind::Forbidden.err("auth.totp_forced"));
+    }
+    verify_second_factor(db_pool.clone(), redis_pool, admin, code, recovery_code).await?;
+    Admin::disable_totp(db_pool, admin.id).await?;
+    Ok(())
+}
+
+/// 超级管理员重置其他管理员的两步验证，例如手机丢失并且没有恢复码
+///
+/// 该管理员已登陆的设备都需要重新登陆，强制开启时下次登陆需要重新绑定
+pub async fn reset(db_pool: web::Data<DbPool>, redis_pool: &RedisPool, pk: PK) -> R<usize> {
+    let rows = Admin::disable_totp(db_pool, pk).await?;
+    serv::auth::revoke_sessions(redis_pool, Audience::Admin, pk).await?;
+    Ok(rows)
+}
@@ -1,4 +1,9 @@
-use crate::{models::user::UpdateUser, prelude::*, schema::*};
+use crate::{
+    models::user::{ManageUser, UpdateUser, User},
+    prelude::*,
+    schema::*,
+    utils::cache::Cached,
+};
 
 /// 修改用户信息
 pub async fn update_user(db_pool: web::Data<DbPool>, pk: PK, data: UpdateUser) -> R<usize> {
@@ -8,6 +13,20 @@ pub async fn update_user(db_pool: web::Data<DbPool>, pk: PK, data: UpdateUser) -
         Ok(diesel::update(target).set(&data).execute(&mut conn)?)
     })
     .await??;
+    Cached::<User>::invalidate(pk).await;
+
+    Ok(rows)
+}
+
+/// 管理员修改用户
+pub async fn manage_user(db_pool: web::Data<DbPool>, pk: PK, data: ManageUser) -> R<usize> {
+    let rows = web::block(move || -> R<usize> {
+        let mut conn = db_pool.get()?;
+        let target = users::table.filter(users::id.eq(pk));
+        Ok(diesel::update(target).set(&data).execute(&mut conn)?)
+    })
+    .await??;
+    Cached::<User>::invalidate(pk).await;
 
     Ok(rows)
 }
@@ -20,21 +39,15 @@ pub async fn change_pwd(
     new_pwd: String,
 ) -> R<usize> {
     // 验证旧密码
-    let user = models::user::User::obj(db_pool.clone(), pk).await?;
-    if !pwd_decode!(&user.password.unwrap_or_default(), pwd.as_ref()) {
-        bail!("密码错误");
+    let user = User::obj_with_secrets(db_pool.clone(), pk).await?;
+    if !serv::password::verify(user.password.unwrap_or_default(), pwd).await? {
+        bail!("user.wrong_password");
     }
 
-    // 设置新密码
-    let new_password = pwd_encode!(new_pwd);
-    let rows = web::block(move || -> R<usize> {
-        let mut conn = db_pool.get()?;
-        let target = users::table.filter(users::id.eq(pk));
-        Ok(diesel::update(target)
-            .set(users::password.eq(new_password))
-            .execute(&mut conn)?)
-    })
-    .await??;
+    set_pwd(db_pool, pk, new_pwd).await
+}
 
-    Ok(rows)
+/// 设置新密码，不验证旧密码，不能使用最近用过的密码
+pub async fn set_pwd(db_pool: web::Data<DbPool>, pk: PK, new_pwd: String) -> R<usize> {
+    serv::password::set(db_pool, Audience::User, pk, new_pwd).await
 }
@@ -1,50 +1,169 @@
-use crate::prelude::*;
-use redis::streams::{StreamId, StreamKey, StreamReadOptions, StreamReadReply};
-use tokio::time::{sleep, Duration};
+use crate::{prelude::*, utils::worker::Workers};
+use tokio::time::Duration;
 
-const KEY: &str = concat!(env!("APP_NAME"), ":task:delay");
+/// 每次最多取出的到期任务数
+const BATCH: isize = 100;
 
-/// redis stream 延迟任务，发送到这个队列任务将固定延迟10s后才执行
+/// 最多执行的次数，超过后移到 [dead_key]
+const MAX_ATTEMPTS: u32 = 5;
+
+/// 失败后重试的间隔，每次失败翻倍，单位毫秒
+const RETRY_BASE_MS: i64 = 10 * 1000;
+
+/// 队列名，sorted set，分数为执行时间(毫秒)
+fn key() -> String {
+    format!("{}:task:delay_at", conf!().app_name)
+}
+
+/// 多次失败的任务，list，需要人工处理
+fn dead_key() -> String {
+    format!("{}:task:delay_dead", conf!().app_name)
+}
+
+/// 队列中的一个任务，序列化后作为 sorted set 的成员
+#[derive(Debug, Serialize, Deserialize)]
+struct Task {
+    name: String,
+    data: String,
+
+    /// 已经失败的次数
+    #[serde(default)]
+    attempts: u32,
+}
+
+/// 发送延迟任务，delay 秒后执行
+///
+/// ZADD delay_at 执行时间(毫秒) {"name":"order.cancel","data":"订单号"}
 ///
-/// XADD delay_task * name 1
-pub fn init(redis_pool: RedisPool) {
+/// 相同的任务(任务名和数据都相同)只保留一个，执行时间以最后一次发送的为准
+pub async fn push(redis_pool: &RedisPool, name: &str, data: &str, delay: u64) -> R<()> {
+    let mut con = redis_pool.get().await?;
+    let task = Task {
+        name: name.to_owned(),
+        data: data.to_owned(),
+        attempts: 0,
+    };
+    let run_at = timestamp_ms!() + delay as i64 * 1000;
+    let _: usize = con
+        .zadd(key(), serde_json::to_string(&task)?, run_at)
+        .await?;
+    Ok(())
+}
+
+/// 执行任务，失败的任务延后重试
+async fn run(
+    db_pool: web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    name: &str,
+    data: String,
+) -> R<()> {
+    match name {
+        serv::order::CANCEL_TASK => serv::order::cancel_expired(db_pool, redis_pool, data).await,
+        _ => {
+            log::error!("未知的延迟任务: {} {}", name, data);
+            Ok(())
+        }
+    }
+}
+
+/// 延迟任务，只取出执行时间已到的任务，未到期和重试中的任务不会阻塞后面的任务
+pub fn init(redis_pool: RedisPool, db_pool: web::Data<DbPool>, workers: &Workers) {
+    let mut hb = workers.register("delay_task");
     tokio::spawn(async move {
-        let mut con = redis_pool.get().await.unwrap();
-        let opts = StreamReadOptions::default().count(1).block(5000);
+        let (key, dead_key) = (key(), dead_key());
 
-        loop {
-            // 延迟 10s 执行，这个值可以从redis中获取，便于配置
-            let delay_ms = 10 * 1000;
-            let read_reply: StreamReadReply = match con.xread_options(&[KEY], &["0"], &opts).await {
+        // 收到停机信号后，处理完当前任务再退出
+        while !hb.is_shutdown() {
+            hb.beat();
+            let due: R<Vec<String>> = async {
+                let mut con = redis_pool.get().await?;
+                Ok(con
+                    .zrangebyscore_limit(&key, "-inf", timestamp_ms!(), 0, BATCH)
+                    .await?)
+            }
+            .await;
+            let due = match due {
                 Ok(x) => x,
                 Err(err) => {
-                    log::error!("读取消息失败: {:?}", err);
+                    log::error!("读取延迟任务失败: {:?}", err);
+                    hb.sleep(Duration::from_millis(1000)).await;
                     continue;
                 }
             };
 
-            for StreamKey { key: _, ids } in read_reply.keys {
-                for StreamId { id, map } in &ids {
-                    let create_ms: i64 = redis_extract_create_ms!(id);
-
-                    if create_ms == 0 {
-                        log::error!("错误的消息id: {}", &id);
-                        let _: usize = con.xdel(KEY, &[id]).await.unwrap();
-                        continue;
-                    }
-                    let cur_ms: i64 = timestamp_ms!();
-
-                    if cur_ms - create_ms >= delay_ms {
-                        // 执行任务， 发送到另一个队列执行任务，不要阻塞这个消费者
-                        log::info!("key:{} | data:{:?}", KEY, map);
-
-                        // 完成后删除
-                        let _: usize = con.xdel(KEY, &[id]).await.unwrap();
-                    }
+            for member in due.iter() {
+                if let Err(err) = handle(&db_pool, &redis_pool, &key, &dead_key, member).await {
+                    log::error!("延迟任务处理失败: {} {:?}", member, err);
                 }
             }
 
-            sleep(Duration::from_millis(1000)).await;
+            // 一批没有取完时马上继续
+            if (due.len() as isize) < BATCH {
+                hb.sleep(Duration::from_millis(1000)).await;
+            }
         }
     });
 }
+
+/// 执行一个到期的任务，成功后删除，失败时延后重试或移到 dead_key
+async fn handle(
+    db_pool: &web::Data<DbPool>,
+    redis_pool: &RedisPool,
+    key: &str,
+    dead_key: &str,
+    member: &str,
+) -> R<()> {
+    let mut task: Task = match serde_json::from_str(member) {
+        Ok(x) => x,
+        Err(err) => {
+            log::error!("错误的延迟任务，移到 {}: {} {}", dead_key, member, err);
+            let mut con = redis_pool.get().await?;
+            let _: () = redis::pipe()
+                .atomic()
+                .zrem(key, member)
+                .ignore()
+                .rpush(dead_key, member)
+                .ignore()
+                .query_async(&mut con)
+                .await?;
+            return Ok(());
+        }
+    };
+    log::info!("key:{} | name:{} | data:{}", key, task.name, task.data);
+
+    let res = run(db_pool.clone(), redis_pool, &task.name, task.data.clone()).await;
+    let mut con = redis_pool.get().await?;
+    let err = match res {
+        Ok(_) => {
+            let _: usize = con.zrem(key, member).await?;
+            return Ok(());
+        }
+        Err(err) => err,
+    };
+
+    task.attempts += 1;
+    let retry = serde_json::to_string(&task)?;
+    let mut pipe = redis::pipe();
+    pipe.atomic().zrem(key, member).ignore();
+    if task.attempts >= MAX_ATTEMPTS {
+        log::error!(
+            "延迟任务失败 {} 次，移到 {}: {} {:?}",
+            task.attempts,
+            dead_key,
+            task.name,
+            err
+        );
+        pipe.rpush(dead_key, &retry).ignore();
+    } else {
+        let delay = RETRY_BASE_MS * (1 << (task.attempts - 1));
+        log::warn!(
+            "延迟任务执行失败，{}ms 后重试: {} {:?}",
+            delay,
+            task.name,
+            err
+        );
+        pipe.zadd(key, &retry, timestamp_ms!() + delay).ignore();
+    }
+    let _: () = pipe.query_async(&mut con).await?;
+    Ok(())
+}
@@ -1,23 +1,29 @@
-use crate::prelude::*;
+use crate::{prelude::*, utils::worker::Workers};
 use redis::streams::{StreamId, StreamKey, StreamReadOptions, StreamReadReply};
-use tokio::time::{sleep, Duration};
+use tokio::time::Duration;
 
-const KEY: &str = concat!(env!("APP_NAME"), ":task:persistent");
+/// 队列名
+fn key() -> String {
+    format!("{}:task:persistent", conf!().app_name)
+}
 
 /// redis stream 实时计时器，在指定时间执行队列中所有任务
 ///
 ///  XADD persistent_task * name 1
-pub fn init(redis_pool: RedisPool) {
+pub fn init(redis_pool: RedisPool, workers: &Workers) {
+    let mut hb = workers.register("persistent_task");
     tokio::spawn(async move {
+        let key = key();
         let opts = StreamReadOptions::default().count(0);
-        loop {
+        while !hb.is_shutdown() {
+            hb.beat();
             let cur_date = Local::now();
 
             // 每分钟
             if cur_date.second() == 0 {
                 let mut con = redis_pool.get().await.unwrap();
                 let read_reply: StreamReadReply =
-                    con.xread_options(&[KEY], &["0"], &opts).await.unwrap();
+                    con.xread_options(&[&key], &["0"], &opts).await.unwrap();
                 for StreamKey { key: _, ids } in read_reply.keys {
                     for StreamId { id, map } in &ids {
                         log::info!("{:?} | id:{} | data:{:?}", cur_date, id, map);
@@ -25,7 +31,7 @@ pub fn init(redis_pool: RedisPool) {
                 }
             }
 
-            sleep(Duration::from_millis(1000)).await;
+            hb.sleep(Duration::from_millis(1000)).await;
         }
     });
 }
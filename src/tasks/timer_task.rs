@@ -1,22 +1,34 @@
-use crate::prelude::*;
+use crate::{prelude::*, utils::worker::Workers};
 use redis::streams::{StreamId, StreamKey, StreamReadOptions, StreamReadReply};
-use tokio::time::{sleep, Duration};
+use tokio::time::Duration;
 
-const KEY: &str = concat!(env!("APP_NAME"), ":task:timer");
+/// 兜底取消过期订单的间隔，单位毫秒，见 [serv::order::cancel_overdue]
+const ORDER_SWEEP_MS: i64 = 60 * 1000;
+
+/// 队列名
+fn key() -> String {
+    format!("{}:task:timer", conf!().app_name)
+}
 
 /// redis stream 重复任务，发送到这个队列任务将在指定的间隔时间执行
 ///
 /// XADD timer_task * name 1
-pub fn init(redis_pool: RedisPool) {
+pub fn init(redis_pool: RedisPool, db_pool: web::Data<DbPool>, workers: &Workers) {
+    order_sweep(redis_pool.clone(), db_pool, workers);
+
+    let mut hb = workers.register("timer_task");
     tokio::spawn(async move {
+        let key = key();
         let mut con = redis_pool.get().await.unwrap();
         let opts = StreamReadOptions::default().count(1).block(5000);
 
-        loop {
+        while !hb.is_shutdown() {
+            hb.beat();
             // 间隔执行时间
             let timer_ms = 10 * 1000;
 
-            let read_reply: StreamReadReply = match con.xread_options(&[KEY], &["0"], &opts).await {
+            let read_reply: StreamReadReply = match con.xread_options(&[&key], &["0"], &opts).await
+            {
                 Ok(x) => x,
                 Err(err) => {
                     log::error!("读取消息失败: {:?}", err);
@@ -29,14 +41,14 @@ pub fn init(redis_pool: RedisPool) {
                     let create_ms: i64 = redis_extract_create_ms!(id);
                     if create_ms == 0 {
                         log::error!("错误的消息id: {}", &id);
-                        let _: usize = con.xdel(KEY, &[id]).await.unwrap();
+                        let _: usize = con.xdel(&key, &[id]).await.unwrap();
                         continue;
                     }
                     let cur_ms: i64 = timestamp_ms!();
 
                     if cur_ms - create_ms >= timer_ms {
-                        log::info!("key:{} | data:{:?}", KEY, map);
-                        let _: usize = con.xdel(KEY, &[id]).await.unwrap();
+                        log::info!("key:{} | data:{:?}", key, map);
+                        let _: usize = con.xdel(&key, &[id]).await.unwrap();
 
                         let mut items: Vec<(&str, &str)> = Vec::new();
                         for (f, v) in map {
@@ -47,12 +59,30 @@ pub fn init(redis_pool: RedisPool) {
                             }
                         }
                         // 添加新消息，将数据全部拷贝到新消息
-                        let _id: String = con.xadd(KEY, "*", &items).await.unwrap();
+                        let _id: String = con.xadd(&key, "*", &items).await.unwrap();
                     }
                 }
             }
 
-            sleep(Duration::from_millis(1000)).await;
+            hb.sleep(Duration::from_millis(1000)).await;
+        }
+    });
+}
+
+/// 定时取消过期的订单，启动后先执行一次
+fn order_sweep(redis_pool: RedisPool, db_pool: web::Data<DbPool>, workers: &Workers) {
+    let mut hb = workers.register("order_sweep");
+    tokio::spawn(async move {
+        let mut next_ms = 0;
+        while !hb.is_shutdown() {
+            hb.beat();
+            if timestamp_ms!() >= next_ms {
+                if let Err(err) = serv::order::cancel_overdue(db_pool.clone(), &redis_pool).await {
+                    log::error!("兜底取消过期订单失败: {:?}", err);
+                }
+                next_ms = timestamp_ms!() + ORDER_SWEEP_MS;
+            }
+            hb.sleep(Duration::from_millis(1000)).await;
         }
     });
 }
@@ -0,0 +1,208 @@
+/**
+ * 模型缓存
+ *
+ * 按 模型名 + pk 把模型序列化后缓存到 redis [rk_cache!]，实现 [Cacheable] 的模型才会缓存:
+ *
+ * - 读: 有缓存直接返回，没有时获取锁 [rk_cache_lock!]，只有拿到锁的请求查数据库并写入缓存，
+ *   其他请求等待缓存写入，超时后直接查数据库
+ * - 写: 先修改数据库，然后调用 [Cached::invalidate] 删除缓存和锁，正在加载的旧数据不会再写入
+ *
+ * redis 不可用时记录日志并直接查数据库，不影响接口
+ *
+ * ```
+ * pub async fn obj(db_pool: web::Data<DbPool>, pk: PK) -> R<Book> {
+ *     Cached::<Book>::get_or_load(pk, Self::load(db_pool, pk)).await
+ * }
+ * ```
+ */
+use crate::prelude::*;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::de::DeserializeOwned;
+use std::{future::Future, marker::PhantomData};
+use tokio::time::{sleep, Duration};
+
+static POOL: OnceCell<RedisPool> = OnceCell::new();
+
+/// 默认缓存的秒数
+const DEFAULT_TTL: usize = 10 * 60;
+
+/// 加载锁的毫秒数，超过后其他请求可以重新获取
+const LOCK_MS: usize = 3000;
+
+/// 没有拿到锁时，每次等待的毫秒数
+const WAIT_MS: u64 = 50;
+
+/// 等待的次数，之后直接查数据库
+const WAIT_TIMES: usize = 20;
+
+/// KEYS: 锁 缓存
+///
+/// ARGV: 锁的值 缓存内容(为空时只释放锁) 缓存秒数
+static STORE: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+        if redis.call('get', KEYS[1]) ~= ARGV[1] then
+            return 0
+        end
+        redis.call('del', KEYS[1])
+        if ARGV[2] ~= '' then
+            redis.call('set', KEYS[2], ARGV[2], 'EX', ARGV[3])
+        end
+        return 1
+    ",
+    )
+});
+
+/// 启动时设置 redis 连接池，没有设置时(如命令行)不使用缓存
+pub fn init(redis_pool: RedisPool) {
+    let _ = POOL.set(redis_pool);
+}
+
+/// 可以缓存的模型
+pub trait Cacheable: Serialize + DeserializeOwned + Send + 'static {
+    /// 缓存 key 中的模型名
+    const NAME: &'static str;
+
+    /// 缓存的秒数
+    const TTL: usize = DEFAULT_TTL;
+
+    /// 序列化后保存到 redis，接口中不输出的字段(如密码)不缓存
+    fn encode(&self) -> R<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    fn decode(s: &str) -> R<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+/// 模型 T 的缓存操作
+pub struct Cached<T>(PhantomData<T>);
+
+impl<T: Cacheable> Cached<T> {
+    /// 读取缓存，没有时使用 load 从数据库加载
+    ///
+    /// 数据库返回错误(如不存在)时不缓存
+    pub async fn get_or_load<F>(pk: PK, load: F) -> R<T>
+    where
+        F: Future<Output = R<T>>,
+    {
+        let mut con = match POOL.get() {
+            Some(pool) => match pool.get().await {
+                Ok(c) => c,
+                Err(err) => {
+                    log::warn!("缓存获取 redis 连接失败: {}", err);
+                    return load.await;
+                }
+            },
+            None => return load.await,
+        };
+
+        let key = rk_cache!(T::NAME, pk);
+        if let Some(x) = Self::read(&mut con, &key).await {
+            return Ok(x);
+        }
+
+        let lock = rk_cache_lock!(T::NAME, pk);
+        let token = random_token!(8);
+        let locked: redis::RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(&lock)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(LOCK_MS)
+            .query_async(&mut con)
+            .await;
+
+        match locked {
+            Ok(Some(_)) => {
+                let res = load.await;
+                let value = match &res {
+                    Ok(x) => x.encode().unwrap_or_else(|err| {
+                        log::warn!("缓存序列化失败: {} {:?}", key, err);
+                        String::new()
+                    }),
+                    Err(_) => String::new(),
+                };
+                let stored: redis::RedisResult<i32> = STORE
+                    .key(&lock)
+                    .key(&key)
+                    .arg(&token)
+                    .arg(value)
+                    .arg(T::TTL)
+                    .invoke_async(&mut con)
+                    .await;
+                if let Err(err) = stored {
+                    log::warn!("写入缓存失败: {} {}", key, err);
+                }
+                res
+            }
+            Ok(None) => {
+                // 锁已释放但没有缓存时(如数据不存在)不再等待
+                for _ in 0..WAIT_TIMES {
+                    sleep(Duration::from_millis(WAIT_MS)).await;
+                    if let Some(x) = Self::read(&mut con, &key).await {
+                        return Ok(x);
+                    }
+                    if !con.exists::<_, bool>(&lock).await.unwrap_or(false) {
+                        break;
+                    }
+                }
+                load.await
+            }
+            Err(err) => {
+                log::warn!("缓存加锁失败: {} {}", lock, err);
+                load.await
+            }
+        }
+    }
+
+    /// 删除缓存，修改数据库之后调用
+    pub async fn invalidate(pk: PK) {
+        Self::invalidate_many(&[pk]).await
+    }
+
+    /// 批量删除缓存，同时删除加载锁
+    pub async fn invalidate_many(pks: &[PK]) {
+        let pool = match POOL.get() {
+            Some(x) => x,
+            None => return,
+        };
+        if pks.is_empty() {
+            return;
+        }
+
+        let keys = pks
+            .iter()
+            .flat_map(|pk| [rk_cache!(T::NAME, pk), rk_cache_lock!(T::NAME, pk)])
+            .collect::<Vec<_>>();
+        let res = match pool.get().await {
+            Ok(mut con) => con
+                .del::<_, usize>(&keys)
+                .await
+                .map_err(anyhow::Error::from),
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = res {
+            log::error!("删除缓存失败: {} {:?} {}", T::NAME, pks, err);
+        }
+    }
+
+    /// 缓存不存在或者无法解析时返回 None
+    async fn read(con: &mut deadpool_redis::Connection, key: &str) -> Option<T> {
+        let cached: Option<String> = match con.get(key).await {
+            Ok(x) => x,
+            Err(err) => {
+                log::warn!("读取缓存失败: {} {}", key, err);
+                return None;
+            }
+        };
+        match T::decode(&cached?) {
+            Ok(x) => Some(x),
+            Err(err) => {
+                log::warn!("缓存解析失败: {} {:?}", key, err);
+                None
+            }
+        }
+    }
+}
@@ -1,13 +1,45 @@
 use crate::prelude::*;
+use crate::utils::i18n::{self, Locale};
 use actix_web::{error::ResponseError, http::StatusCode, Error as ActixError};
 pub use diesel::result::{DatabaseErrorKind, Error as DbError};
-use serde_json::to_string_pretty;
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use validator::{ValidationErrors, ValidationErrorsKind};
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
 
 /// http json error
-pub fn ej<E: Display>(e: E) -> OkErr {
-    OkErr::new::<E>(e)
+///
+/// 会识别 [OkErr]、包装了 [OkErr] 的 anyhow::Error 和数据库错误，`bail!("消息代码")` 的字符串消息当作 [ErrKind::BadRequest]，
+/// 其他错误(redis、连接池、序列化等)都当作 [ErrKind::Internal]，不返回细节
+pub fn ej<E: Display + 'static>(e: E) -> OkErr {
+    let any = &e as &dyn Any;
+
+    if let Some(err) = any.downcast_ref::<OkErr>() {
+        return err.clone();
+    }
+
+    if let Some(err) = any.downcast_ref::<anyhow::Error>() {
+        if let Some(err) = err.downcast_ref::<OkErr>() {
+            return err.clone();
+        }
+        if let Some(err) = err.downcast_ref::<DbError>() {
+            return OkErr::from_db(err);
+        }
+        if err.downcast_ref::<&str>().is_some() || err.downcast_ref::<String>().is_some() {
+            return OkErr::new(err);
+        }
+        return ErrKind::Internal.err(err);
+    }
+
+    if let Some(err) = any.downcast_ref::<DbError>() {
+        return OkErr::from_db(err);
+    }
+
+    if any.is::<&str>() || any.is::<String>() {
+        return OkErr::new(e);
+    }
+
+    ErrKind::Internal.err(e)
 }
 
 /// error to string
@@ -22,63 +54,179 @@ pub fn eany<E: Display>(e: E) -> anyhow::Error {
 
 pub fn db_unique_err(e: DbError, err_msg: &'static str) -> anyhow::Error {
     match e {
-        DbError::DatabaseError(kind, _) => match kind {
-            DatabaseErrorKind::UniqueViolation => anyhow!(err_msg),
-            _ => anyhow!(e),
-        },
+        DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            anyhow!(ErrKind::Conflict.err(err_msg))
+        }
         _ => anyhow!(e),
     }
 }
 
 pub fn db_not_found_err(e: DbError, err_msg: &'static str) -> anyhow::Error {
+    db_not_found_as(e, ErrKind::NotFound, err_msg)
+}
+
+/// 未找到时使用指定的错误类型，例如登陆时账号不存在应该是 [ErrKind::Auth]
+pub fn db_not_found_as(e: DbError, kind: ErrKind, err_msg: &'static str) -> anyhow::Error {
     match e {
-        DbError::NotFound => anyhow!(err_msg),
+        DbError::NotFound => anyhow!(kind.err(err_msg)),
         _ => anyhow!(e),
     }
 }
 
-#[derive(Debug, Serialize)]
+/// 错误类型，决定 http 状态码和返回给前端的 errorCode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrKind {
+    /// 普通的业务错误
+    BadRequest,
+
+    /// 请求参数验证失败
+    Validation,
+
+    NotFound,
+
+    /// 数据重复
+    Conflict,
+
+    /// 需要登陆
+    Auth,
+
+    /// 没有权限
+    Forbidden,
+
+    /// 请求太频繁
+    RateLimited,
+
+    /// 请求实体太大
+    PayloadTooLarge,
+
+    /// 调用第三方服务失败
+    Upstream,
+
+    /// 服务器内部错误
+    Internal,
+}
+
+impl ErrKind {
+    /// 稳定的错误码，前端根据这个判断错误类型
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "BAD_REQUEST",
+            Self::Validation => "VALIDATION",
+            Self::NotFound => "NOT_FOUND",
+            Self::Conflict => "CONFLICT",
+            Self::Auth => "AUTH",
+            Self::Forbidden => "FORBIDDEN",
+            Self::RateLimited => "RATE_LIMITED",
+            Self::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            Self::Upstream => "UPSTREAM",
+            Self::Internal => "INTERNAL",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Validation => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::Auth => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Upstream => StatusCode::BAD_GATEWAY,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// 创建这个类型的错误
+    ///
+    /// ```
+    /// bail!(ErrKind::Auth.err("账号或密码错误"));
+    /// ```
+    pub fn err<E: Display>(self, e: E) -> OkErr {
+        OkErr::new(e).kind(self).build()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct OkErr {
-    /// 1普通错误，2需要登陆
-    pub error_code: char,
+    #[serde(skip)]
+    pub kind: ErrKind,
 
-    /// 错误消息
+    /// 错误消息，可以是消息代码(例如 auth.bad_credentials)，返回时按请求的语言翻译
     pub error_message: String,
 
+    /// 消息中的 {name} 参数
+    #[serde(skip)]
+    pub args: Vec<(String, String)>,
+
     /// 0无声,1警告消息,2错误消息,4通知,9页面
     pub show_type: i8,
+
+    /// 参数验证失败时，每个字段的所有错误消息
+    pub details: Option<BTreeMap<String, Vec<String>>>,
 }
 
 impl Display for OkErr {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}", to_string_pretty(self).unwrap())
+        write!(f, "[{}] {}", self.kind.code(), self.error_message)
     }
 }
 
+impl std::error::Error for OkErr {}
+
 impl ResponseError for OkErr {
     fn error_response(&self) -> HttpResponse {
         // https://pro.ant.design/zh-CN/docs/request/
         // https://github.com/actix/examples/blob/master/json/json-error/src/main.rs
-        HttpResponse::Ok().status(StatusCode::BAD_GATEWAY).json(json!({
+        if self.kind == ErrKind::Internal {
+            log::error!("{}", self);
+        }
+
+        let mut body = json!({
             "success": false,
             "data": null,
-            "errorCode": self.error_code,
-            "errorMessage": self.error_message,
+            "errorCode": self.kind.code(),
+            "errorMessage": self.message(i18n::current()),
             "showType": self.show_type,
-        }))
+        });
+        if let Some(details) = &self.details {
+            body["details"] = json!(details);
+        }
+        HttpResponse::build(self.status_code()).json(body)
     }
 
     fn status_code(&self) -> StatusCode {
-        StatusCode::OK
+        self.kind.status()
     }
 }
 
 impl OkErr {
     fn new<E: Display>(e: E) -> Self {
         Self {
-            error_code: '1',
+            kind: ErrKind::BadRequest,
             error_message: e.to_string(),
+            args: Vec::new(),
             show_type: 2,
+            details: None,
+        }
+    }
+
+    /// 翻译后的消息，内部错误不返回细节
+    pub fn message(&self, locale: Locale) -> String {
+        if self.kind == ErrKind::Internal {
+            return i18n::t(locale, "error.internal");
+        }
+        i18n::tf(locale, &self.error_message, &self.args)
+    }
+
+    fn from_db(e: &DbError) -> Self {
+        match e {
+            DbError::NotFound => ErrKind::NotFound.err(e),
+            DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ErrKind::Conflict.err(e)
+            }
+            _ => ErrKind::Internal.err(e),
         }
     }
 
@@ -88,22 +236,28 @@ impl OkErr {
         self
     }
 
-    pub fn error_code(&mut self, error_code: char) -> &mut Self {
-        self.error_code = error_code;
+    pub fn kind(&mut self, kind: ErrKind) -> &mut Self {
+        self.kind = kind;
+        self
+    }
+
+    /// 设置消息参数
+    ///
+    /// ```
+    /// ErrKind::Upstream.err("upload.etag_mismatch").arg("expected", etag).arg("actual", hash).build()
+    /// ```
+    pub fn arg<V: Display>(&mut self, name: &str, value: V) -> &mut Self {
+        self.args.push((name.to_owned(), value.to_string()));
         self
     }
 
     /// 需要登陆
     pub fn auth(&mut self) -> &mut Self {
-        self.error_code('2')
+        self.kind(ErrKind::Auth)
     }
 
     pub fn build(&self) -> Self {
-        Self {
-            error_code: self.error_code,
-            error_message: self.error_message.clone(),
-            show_type: self.show_type,
-        }
+        self.clone()
     }
 
     pub fn actix(&self) -> ActixError {
@@ -111,36 +265,89 @@ impl OkErr {
     }
 
     /// 针对请求参数验证失败的错误
+    ///
+    /// errorMessage 是每个字段的第一条消息，details 中是每个字段的所有消息，嵌套的字段使用 `.` 连接
+    ///
+    /// `#[validate(message = "...")]` 写消息代码，未写时使用 `validation.{code}`，按当前请求的语言翻译
     pub fn ev(e: ValidationErrors) -> Self {
-        // { phone: [...ValidationError], other: [] }
-
-        let err_messages = e
-            .into_errors()
-            .iter()
-            .filter_map(|(k, v)| {
-                if let ValidationErrorsKind::Field(errors) = v {
-                    let first_err = match errors.first() {
-                        Some(x) => x,
-                        _ => {
-                            return Some(format!("{} 参数错误", k));
-                        }
-                    };
-
-                    let first_err_message: String = match &first_err.message {
-                        Some(m) => m.to_string(),
-                        _ => format!("{} 参数错误", k),
-                    };
-                    Some(first_err_message)
-                } else {
-                    None
-                }
-            })
+        // { phone: [...ValidationError], user: Struct(...), items: List(...) }
+        let mut details = BTreeMap::new();
+        flatten_validation(i18n::current(), "", e, &mut details);
+
+        let err_messages = details
+            .values()
+            .filter_map(|messages| messages.first().cloned())
             .collect::<Vec<String>>();
 
         Self {
-            error_code: '1',
+            kind: ErrKind::Validation,
             error_message: err_messages.join(","),
             show_type: 2,
+            args: Vec::new(),
+            details: Some(details),
+        }
+    }
+}
+
+/// 翻译一条验证错误，参数包括 validator 的 min、max、equal、value 和字段名 field
+fn validation_message(locale: Locale, field: &str, err: &ValidationError) -> String {
+    let code = match &err.message {
+        Some(m) => m.to_string(),
+        None => format!("validation.{}", err.code),
+    };
+
+    if i18n::lookup(locale, &code).is_none() {
+        return match &err.message {
+            Some(m) => m.to_string(),
+            None => i18n::tf(locale, "validation.invalid", &[("field", field)]),
+        };
+    }
+
+    let mut args = err
+        .params
+        .iter()
+        .map(|(k, v)| {
+            let v = match v {
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            (k.as_ref(), v)
+        })
+        .collect::<Vec<_>>();
+    args.push(("field", field.to_owned()));
+    i18n::tf(locale, &code, &args)
+}
+
+fn flatten_validation(
+    locale: Locale,
+    prefix: &str,
+    e: ValidationErrors,
+    out: &mut BTreeMap<String, Vec<String>>,
+) {
+    for (field, kind) in e.into_errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                let mut messages = errors
+                    .iter()
+                    .map(|err| validation_message(locale, field, err))
+                    .collect::<Vec<String>>();
+                if messages.is_empty() {
+                    messages.push(i18n::tf(locale, "validation.invalid", &[("field", field)]));
+                }
+                out.insert(path, messages);
+            }
+            ValidationErrorsKind::Struct(errors) => flatten_validation(locale, &path, *errors, out),
+            ValidationErrorsKind::List(list) => {
+                for (i, errors) in list {
+                    flatten_validation(locale, &format!("{}[{}]", path, i), *errors, out);
+                }
+            }
         }
     }
 }
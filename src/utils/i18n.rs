@@ -0,0 +1,164 @@
+/**
+ * 多语言消息目录
+ *
+ * 消息保存在 locales/{语言}.toml，编译时嵌入二进制文件
+ *
+ * 错误消息、验证消息在代码中只写消息代码(例如 auth.bad_credentials)，返回给前端时才按请求的语言翻译
+ */
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+/// 支持的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locale {
+    ZhCn,
+    En,
+}
+
+impl Default for Locale {
+    /// 配置中的 DEFAULT_LOCALE
+    fn default() -> Self {
+        crate::config::try_conf()
+            .map(|c| c.default_locale)
+            .unwrap_or(Self::ZhCn)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// 只比较主语言，zh、zh-CN、zh-TW 都是中文，en-US、en-GB 都是英文
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lang = s
+            .trim()
+            .split(|c| c == '-' || c == '_')
+            .next()
+            .unwrap_or_default();
+        match lang.to_lowercase().as_str() {
+            "zh" => Ok(Self::ZhCn),
+            "en" => Ok(Self::En),
+            _ => Err(format!("{}，可选值 zh-CN en", s)),
+        }
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.tag())
+    }
+}
+
+impl Locale {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::ZhCn => "zh-CN",
+            Self::En => "en",
+        }
+    }
+
+    /// 解析 Accept-Language，按 q 值选择第一个支持的语言
+    ///
+    /// ```
+    /// Locale::from_accept_language("en-US,en;q=0.9,zh-CN;q=0.8") // Some(Locale::En)
+    /// ```
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut langs = header
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim();
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((tag, q))
+            })
+            .filter(|(_, q)| *q > 0.0)
+            .collect::<Vec<_>>();
+
+        // 稳定排序，q 值相同时保持原来的顺序
+        langs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        langs.into_iter().find_map(|(tag, _)| tag.parse().ok())
+    }
+
+    fn bundle(&self) -> &'static HashMap<String, String> {
+        static ZH_CN: Lazy<HashMap<String, String>> =
+            Lazy::new(|| load(include_str!("../../locales/zh-CN.toml")));
+        static EN: Lazy<HashMap<String, String>> =
+            Lazy::new(|| load(include_str!("../../locales/en.toml")));
+
+        match self {
+            Self::ZhCn => &ZH_CN,
+            Self::En => &EN,
+        }
+    }
+}
+
+/// 将 toml 的表展开为 `表名.键名`
+fn load(content: &str) -> HashMap<String, String> {
+    fn flatten(prefix: &str, table: toml::value::Table, out: &mut HashMap<String, String>) {
+        for (k, v) in table {
+            let key = if prefix.is_empty() {
+                k
+            } else {
+                format!("{}.{}", prefix, k)
+            };
+            match v {
+                toml::Value::Table(t) => flatten(&key, t, out),
+                toml::Value::String(s) => {
+                    out.insert(key, s);
+                }
+                v => {
+                    out.insert(key, v.to_string());
+                }
+            }
+        }
+    }
+
+    let table: toml::value::Table = toml::from_str(content).expect("消息目录格式错误");
+    let mut out = HashMap::new();
+    flatten("", table, &mut out);
+    out
+}
+
+tokio::task_local! {
+    /// 当前请求的语言，由 [crate::middleware::I18n] 设置
+    static LOCALE: Locale;
+}
+
+/// 当前请求的语言，不在请求中时使用默认语言
+pub fn current() -> Locale {
+    LOCALE.try_with(|l| *l).unwrap_or_default()
+}
+
+/// 在指定语言下运行，[current] 会返回这个语言
+pub async fn scope<F: std::future::Future>(locale: Locale, f: F) -> F::Output {
+    LOCALE.scope(locale, f).await
+}
+
+/// 查找消息，当前语言没有时使用中文
+pub fn lookup(locale: Locale, code: &str) -> Option<&'static str> {
+    locale
+        .bundle()
+        .get(code)
+        .or_else(|| Locale::ZhCn.bundle().get(code))
+        .map(|s| s.as_str())
+}
+
+/// 翻译消息代码，未找到时原样返回，兼容直接写文字的消息
+pub fn t(locale: Locale, code: &str) -> String {
+    lookup(locale, code).unwrap_or(code).to_owned()
+}
+
+/// 翻译并替换 {name} 参数
+///
+/// ```
+/// i18n::tf(Locale::En, "validation.password_len", &[("min", "3"), ("max", "20")])
+/// ```
+pub fn tf<K: AsRef<str>, V: Display>(locale: Locale, code: &str, args: &[(K, V)]) -> String {
+    let mut text = t(locale, code);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name.as_ref()), &value.to_string());
+    }
+    text
+}
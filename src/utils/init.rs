@@ -1,28 +1,102 @@
+use crate::config::{AppConfig, ListenConfig};
 use crate::prelude::{DbPool, RedisPool};
+use anyhow::{anyhow, bail, Result as R};
 use diesel::mysql::MysqlConnection;
 use diesel::r2d2::{ConnectionManager, Pool};
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+use std::future::Future;
+use std::os::unix::{fs::PermissionsExt, net::UnixStream};
+use std::path::Path;
+use tokio::time::{sleep, Duration};
+
+/// 最长的重试间隔
+const MAX_RETRY_MS: u64 = 30 * 1000;
+
+/// 依赖在启动时不可用，按指数退避重试 [AppConfig::startup_retries] 次
+async fn retry<T, F, Fut>(name: &str, config: &AppConfig, mut f: F) -> R<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = R<T>>,
+{
+    let mut delay_ms = config.startup_retry_ms;
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Ok(x) => return Ok(x),
+            Err(err) if attempt < config.startup_retries => {
+                attempt += 1;
+                log::warn!(
+                    "{} 连接失败({}/{})，{}ms 后重试: {}",
+                    name,
+                    attempt,
+                    config.startup_retries,
+                    delay_ms,
+                    err
+                );
+                sleep(Duration::from_millis(delay_ms)).await;
+                delay_ms = (delay_ms * 2).min(MAX_RETRY_MS);
+            }
+            Err(err) => return Err(anyhow!("{} 连接失败: {}", name, err)),
+        }
+    }
+}
 
 /// 获取数据库连接池
-pub fn mysql_pool() -> DbPool {
-    let mysql_manager = ConnectionManager::<MysqlConnection>::new(env!("DATABASE_URL"));
-    Pool::builder()
-        .build(mysql_manager)
-        .expect("mysql 池创建失败")
+pub async fn mysql_pool(config: &AppConfig) -> R<DbPool> {
+    retry("mysql", config, || async {
+        let mysql_manager = ConnectionManager::<MysqlConnection>::new(&config.database_url);
+        Ok(Pool::builder().build(mysql_manager)?)
+    })
+    .await
+}
+
+/// 获取redis连接池，创建后会 PING 一次确认可用
+pub async fn redis_pool(config: &AppConfig) -> R<RedisPool> {
+    let cfg = deadpool_redis::Config::from_url(&config.redis_url);
+    let pool = cfg.create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
+
+    retry("redis", config, || async {
+        let mut con = pool.get().await?;
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut con)
+            .await?;
+        Ok(())
+    })
+    .await?;
+
+    Ok(pool)
 }
 
-/// 获取redis连接池
-pub fn redis_pool() -> RedisPool {
-    let cfg = deadpool_redis::Config::from_url(env!("REDIS_URL"));
-    cfg.create_pool(Some(deadpool_redis::Runtime::Tokio1))
-        .expect("redis 池创建失败")
+/// 绑定 unix socket 前清理上次异常退出残留的 socket 文件
+///
+/// 文件仍能连接说明有其他进程在使用，启动失败
+pub fn clean_stale_socket(path: &str) -> R<()> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    if UnixStream::connect(path).is_ok() {
+        bail!("{} 正在被其他进程使用", path);
+    }
+    log::warn!("删除残留的 socket 文件: {}", path);
+    std::fs::remove_file(path)?;
+    Ok(())
 }
 
-#[cfg(feature = "dev")]
-pub fn env() {
-    dotenv::from_filename(".env").ok();
+/// 设置 socket 文件权限，不然 nginx 没有权限访问
+pub fn set_socket_mode(path: &str, mode: u32) -> R<()> {
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
 }
 
-#[cfg(not(feature = "dev"))]
-pub fn env() {
-    dotenv::from_filename("prod.env").ok();
+/// 读取 https 证书和私钥
+pub fn ssl_acceptor(listen: &ListenConfig) -> R<SslAcceptorBuilder> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder
+        .set_private_key_file(&listen.tls_key, SslFiletype::PEM)
+        .map_err(|e| anyhow!("读取私钥 {} 失败: {}", listen.tls_key, e))?;
+    builder
+        .set_certificate_chain_file(&listen.tls_cert)
+        .map_err(|e| anyhow!("读取证书 {} 失败: {}", listen.tls_cert, e))?;
+    builder.check_private_key()?;
+    Ok(builder)
 }
@@ -1,48 +1,285 @@
+/**
+ * jwt 签名和验证
+ *
+ * HS256 使用 JWT_SECRET，其他服务需要同一个密匙才能验证
+ *
+ * RS256/ES256 使用 JWT_KEY_DIR 中的密钥，文件名就是 kid，token 头部带有 kid:
+ *
+ * - `{JWT_KID}.pem` 私钥，用于签名
+ * - 其他 `*.pem` 可以是私钥或公钥，只用于验证，轮换密钥时保留旧的公钥，直到旧 token 全部过期(JWT_EXP)再删除
+ *
+ * 所有公钥通过 `/.well-known/jwks.json` 公开，其他服务不需要密匙就能验证 token
+ */
+use crate::config::JwtConfig;
 use crate::prelude::*;
 use jsonwebtoken as jwt;
 use jwt::errors::ErrorKind;
+use jwt::Algorithm;
+use once_cell::sync::OnceCell;
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::EcGroup,
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+};
+use std::path::Path;
+
+static KEYS: OnceCell<Keys> = OnceCell::new();
+
+/// 签名和验证使用的密钥
+struct Keys {
+    alg: Algorithm,
+
+    /// 签名时写入 token 头部，HS256 没有
+    kid: Option<String>,
+    encoding: jwt::EncodingKey,
+
+    /// kid => 公钥，HS256 时 kid 为空字符串
+    decoding: HashMap<String, jwt::DecodingKey>,
+
+    /// 公开的 jwks，HS256 时为空
+    jwks: Vec<serde_json::Value>,
+}
+
+/// 启动时加载密钥，必须在签发或验证 token 之前调用
+pub fn init(config: &JwtConfig) -> R<()> {
+    let keys = match config.alg {
+        Algorithm::HS256 => Keys {
+            alg: config.alg,
+            kid: None,
+            encoding: jwt::EncodingKey::from_secret(config.secret.as_ref()),
+            decoding: HashMap::from([(
+                String::new(),
+                jwt::DecodingKey::from_secret(config.secret.as_ref()),
+            )]),
+            jwks: vec![],
+        },
+        _ => load_keys(config)?,
+    };
+
+    log::info!(
+        "jwt {:?} 签名密钥 {}，验证密钥 {}个",
+        keys.alg,
+        keys.kid.as_deref().unwrap_or("-"),
+        keys.decoding.len()
+    );
+    KEYS.set(keys)
+        .map_err(|_| anyhow!("jwt 密钥只能初始化一次"))?;
+    Ok(())
+}
+
+fn keys() -> &'static Keys {
+    KEYS.get().expect("jwt 密钥尚未初始化")
+}
+
+/// 公开的 jwks，给其他服务验证 token
+pub fn jwks() -> serde_json::Value {
+    json!({ "keys": keys().jwks })
+}
+
+/// 读取密钥目录中所有的 .pem
+fn load_keys(config: &JwtConfig) -> R<Keys> {
+    let mut encoding = None;
+    let mut decoding = HashMap::new();
+    let mut jwks = vec![];
+
+    for entry in std::fs::read_dir(&config.key_dir)
+        .map_err(|e| anyhow!("读取 JWT_KEY_DIR {} 失败: {}", config.key_dir, e))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|x| x.to_str()) != Some("pem") {
+            continue;
+        }
+        let kid = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| anyhow!("密钥文件名错误: {}", path.display()))?
+            .to_owned();
+
+        let (public, private) = read_pem(&path)?;
+        check_key_type(config.alg, &public).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+        if kid == config.kid {
+            let private =
+                private.ok_or_else(|| anyhow!("JWT_KID 必须是私钥: {}", path.display()))?;
+            encoding = Some(match config.alg {
+                Algorithm::RS256 => {
+                    jwt::EncodingKey::from_rsa_pem(&private.rsa()?.private_key_to_pem()?)?
+                }
+                _ => jwt::EncodingKey::from_ec_pem(&private.private_key_to_pem_pkcs8()?)?,
+            });
+        }
+
+        let public_pem = public.public_key_to_pem()?;
+        decoding.insert(
+            kid.clone(),
+            match config.alg {
+                Algorithm::RS256 => jwt::DecodingKey::from_rsa_pem(&public_pem)?,
+                _ => jwt::DecodingKey::from_ec_pem(&public_pem)?,
+            },
+        );
+        jwks.push(jwk(config.alg, &kid, &public)?);
+    }
+
+    // 按 kid 排序，输出稳定
+    jwks.sort_by(|a, b| a["kid"].as_str().cmp(&b["kid"].as_str()));
+
+    Ok(Keys {
+        alg: config.alg,
+        kid: Some(config.kid.clone()),
+        encoding: encoding.ok_or_else(|| anyhow!("未找到 JWT_KID 的密钥: {}", config.kid))?,
+        decoding,
+        jwks,
+    })
+}
+
+/// 读取私钥或公钥，私钥同时返回公钥
+fn read_pem(path: &Path) -> R<(PKey<Public>, Option<PKey<openssl::pkey::Private>>)> {
+    let pem = std::fs::read(path)?;
+    if let Ok(private) = PKey::private_key_from_pem(&pem) {
+        let public = PKey::public_key_from_pem(&private.public_key_to_pem()?)?;
+        return Ok((public, Some(private)));
+    }
+    let public = PKey::public_key_from_pem(&pem)
+        .map_err(|e| anyhow!("读取密钥 {} 失败: {}", path.display(), e))?;
+    Ok((public, None))
+}
+
+/// RS256 需要 RSA 密钥，ES256 需要 P-256 曲线的 EC 密钥
+fn check_key_type(alg: Algorithm, key: &PKey<Public>) -> R<()> {
+    match alg {
+        Algorithm::RS256 if key.id() == Id::RSA => Ok(()),
+        Algorithm::ES256
+            if key.id() == Id::EC
+                && key.ec_key()?.group().curve_name() == Some(Nid::X9_62_PRIME256V1) =>
+        {
+            Ok(())
+        }
+        _ => bail!("密钥类型和 JWT_ALG {:?} 不匹配", alg),
+    }
+}
+
+/// 公钥转为 jwk，https://www.rfc-editor.org/rfc/rfc7518#section-6
+fn jwk(alg: Algorithm, kid: &str, key: &PKey<Public>) -> R<serde_json::Value> {
+    let b64 = |bytes: Vec<u8>| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+    Ok(match alg {
+        Algorithm::RS256 => {
+            let rsa = key.rsa()?;
+            json!({
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": kid,
+                "n": b64(rsa.n().to_vec()),
+                "e": b64(rsa.e().to_vec()),
+            })
+        }
+        _ => {
+            let ec = key.ec_key()?;
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+            let mut ctx = BigNumContext::new()?;
+            let mut x = BigNum::new()?;
+            let mut y = BigNum::new()?;
+            ec.public_key()
+                .affine_coordinates_gfp(&group, &mut x, &mut y, &mut ctx)?;
+            json!({
+                "kty": "EC",
+                "use": "sig",
+                "alg": "ES256",
+                "kid": kid,
+                "crv": "P-256",
+                "x": b64(x.to_vec_padded(32)?),
+                "y": b64(y.to_vec_padded(32)?),
+            })
+        }
+    })
+}
+
+/// token 的使用者，管理员和用户的 id 是两张表，不能混用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Audience {
+    /// admin 表
+    Admin,
+
+    /// users 表
+    User,
+}
+
+impl Audience {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::User => "user",
+        }
+    }
+}
 
 /// 生成token的payload
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
+    pub aud: Audience,
     pub id: u64,
     pub user_type: u8,
     /// 必需（validate_exp 在验证中默认为 true）。 到期时间（作为 UTC 时间戳）
     pub exp: usize,
+
+    /// token 的唯一id，退出登陆时加入黑名单
+    pub jti: String,
+
+    /// 签发时用户的 token 版本，修改密码、禁用账号后版本号增加，旧的 token 全部失效
+    pub ver: u64,
 }
 
 impl Claims {
-    pub fn new(user_id: u64, user_type: u8) -> Self {
-        let exp: usize = (Utc::now() + chrono::Duration::seconds(env!("JWT_EXP").parse().unwrap()))
-            .timestamp() as usize;
+    pub fn new(aud: Audience, user_id: u64, user_type: u8, ver: u64) -> Self {
+        let exp: usize =
+            (Utc::now() + chrono::Duration::seconds(conf!().jwt.exp)).timestamp() as usize;
         Self {
+            aud,
             id: user_id,
             user_type,
             exp,
+            jti: random_token!(16),
+            ver,
         }
     }
 
-    /// 创建 jwt
+    /// 距离过期的秒数
+    pub fn ttl(&self) -> usize {
+        self.exp.saturating_sub(Utc::now().timestamp() as usize)
+    }
+
+    /// 创建 jwt，使用 JWT_KID 的密钥签名
     pub fn jwt(&self) -> R<String> {
-        Ok(jwt::encode(
-            &jwt::Header::default(),
-            self,
-            &jwt::EncodingKey::from_secret(env!("JWT_SECRET").as_ref()),
-        )?)
-    }
-
-    /// 解码验证 jwt token
-    pub fn decode(token: &str) -> R<jwt::TokenData<Self>> {
-        let validation = jwt::Validation::new(jwt::Algorithm::HS256);
-        let token_data = jwt::decode::<Self>(
-            token,
-            &jwt::DecodingKey::from_secret(env!("JWT_SECRET").as_ref()),
-            &validation,
-        )
-        .map_err(|err| {
+        let keys = keys();
+        let mut header = jwt::Header::new(keys.alg);
+        header.kid = keys.kid.clone();
+        Ok(jwt::encode(&header, self, &keys.encoding)?)
+    }
+
+    /// 解码验证 jwt token，aud 必须是 audiences 之一
+    ///
+    /// 按 token 头部的 kid 选择公钥，轮换期间旧密钥签发的 token 仍然有效
+    pub fn decode(token: &str, audiences: &[Audience]) -> R<jwt::TokenData<Self>> {
+        let keys = keys();
+        let header = jwt::decode_header(token).map_err(|_| anyhow!("token 无效"))?;
+        let key = keys
+            .decoding
+            .get(header.kid.as_deref().unwrap_or_default())
+            .ok_or_else(|| anyhow!("未知的 kid: {:?}", header.kid))?;
+
+        let mut validation = jwt::Validation::new(keys.alg);
+        validation.set_audience(&audiences.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+        let token_data = jwt::decode::<Self>(token, key, &validation).map_err(|err| {
             let err = match *err.kind() {
                 ErrorKind::InvalidToken => "token 无效",
                 ErrorKind::InvalidIssuer => "发行人无效",
+                // 管理员的 token 访问用户接口，或者反过来
+                ErrorKind::InvalidAudience => {
+                    return anyhow!(ErrKind::Forbidden.err("auth.forbidden"))
+                }
                 ErrorKind::ExpiredSignature => "登录过期",
                 _ => "登录验证失败",
             };
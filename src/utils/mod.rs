@@ -1,4 +1,10 @@
 pub mod jwt;
+
+/// 列表接口的分页、排序和过滤
+pub mod page;
+
+pub mod password;
+pub mod totp;
 pub mod valid;
 
 /// 自定义的接口返回错误
@@ -6,6 +12,14 @@ pub mod error;
 
 pub mod init;
 
+/// 多语言消息
+pub mod i18n;
+
+/// 后台任务的运行状态
+pub mod worker;
+
+pub mod cache;
+
 #[cfg(feature = "dev")]
 /// GET的404请求重新处理
 pub async fn all_try_files(
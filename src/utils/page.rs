@@ -0,0 +1,400 @@
+/**
+ * 列表接口的分页、排序和过滤
+ *
+ * 查询参数:
+ *
+ * - `page`(或 `current`) 从1开始，默认1；`limit`(或 `pageSize`) 默认 [DEFAULT_LIMIT]，最大 [MAX_LIMIT]
+ * - `sort=-create_at,id` 逗号分隔，`-` 表示倒序，只能使用接口声明的字段
+ * - `字段=值` 等于，`字段__like=值` 模糊匹配，`字段__in=1,2,3`，`字段__range=开始~结束`(可以省略一边)
+ * - `cursor` keyset 分页，第一页传空值，之后传上一页返回的 `cursor`，不返回总数，不能和 `sort` 一起使用
+ *
+ * ```
+ * #[http_get("list")]
+ * pub async fn list(db_pool: web::Data<DbPool>, page: PageQuery) -> HttpResult {
+ *     res_page!(Admin::page(db_pool, page).await.map_err(ej)?)
+ * }
+ *
+ * // 在 web::block 中
+ * let build = || -> R<_> {
+ *     Ok(page_filter!(admin::table.into_boxed(), page, {
+ *         "username" => admin::username: String [Eq, Like],
+ *         "last_login" => admin::last_login: NaiveDateTime [Range],
+ *     }))
+ * };
+ * let total: i64 = build()?.count().get_result(&mut conn)?;
+ * let data = page_sort!(build()?, page, { "id" => admin::id })
+ *     .limit(page.limit)
+ *     .offset(page.offset())
+ *     .load::<Admin>(&mut conn)?;
+ * Ok(Paginated::new(data, total, &page))
+ * ```
+ */
+use crate::prelude::*;
+use actix_web::{dev::Payload, FromRequest};
+use futures_util::future::{ready, Ready};
+
+/// 默认每页条数
+pub const DEFAULT_LIMIT: i64 = 20;
+
+/// 每页最大条数
+pub const MAX_LIMIT: i64 = 100;
+
+/// 最大页数，更深的翻页使用 cursor
+pub const MAX_PAGE: i64 = 10000;
+
+/// 最多几个排序字段
+const MAX_SORT: usize = 3;
+
+/// in 最多几个值
+const MAX_IN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sort {
+    pub field: String,
+    pub order: Order,
+}
+
+impl Sort {
+    pub fn unsupported(&self) -> anyhow::Error {
+        anyhow!(ErrKind::BadRequest
+            .err("validation.sort_field")
+            .arg("field", &self.field)
+            .build())
+    }
+}
+
+/// 过滤操作符
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    Like,
+    In,
+    Range,
+}
+
+impl FilterOp {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "eq" => Some(Self::Eq),
+            "like" => Some(Self::Like),
+            "in" => Some(Self::In),
+            "range" => Some(Self::Range),
+            _ => None,
+        }
+    }
+}
+
+/// 过滤条件的值，从查询参数的字符串解析
+pub trait FilterValue: Sized {
+    fn parse_value(s: &str) -> Option<Self>;
+}
+
+macro_rules! impl_filter_value {
+    ( $( $ty:ty ),* ) => {
+        $(
+            impl FilterValue for $ty {
+                fn parse_value(s: &str) -> Option<Self> {
+                    s.trim().parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_filter_value!(u8, u32, u64, i32, i64, BigDecimal, NaiveDate);
+
+impl FilterValue for String {
+    fn parse_value(s: &str) -> Option<Self> {
+        Some(s.to_owned())
+    }
+}
+
+impl FilterValue for bool {
+    fn parse_value(s: &str) -> Option<Self> {
+        match s.trim() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// 2022-07-01 12:00:00、2022-07-01T12:00:00 或 2022-07-01
+impl FilterValue for NaiveDateTime {
+    fn parse_value(s: &str) -> Option<Self> {
+        let s = s.trim();
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+            .ok()
+            .or_else(|| NaiveDate::parse_value(s).map(|d| d.and_hms(0, 0, 0)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub field: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+impl Filter {
+    pub fn value<T: FilterValue>(&self) -> R<T> {
+        T::parse_value(&self.value).ok_or_else(|| self.invalid())
+    }
+
+    /// 逗号分隔
+    pub fn values<T: FilterValue>(&self) -> R<Vec<T>> {
+        let values = self
+            .value
+            .split(',')
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| T::parse_value(x).ok_or_else(|| self.invalid()))
+            .collect::<R<Vec<T>>>()?;
+        if values.is_empty() || values.len() > MAX_IN {
+            return Err(self.invalid());
+        }
+        Ok(values)
+    }
+
+    /// `开始~结束`，可以省略一边
+    pub fn range<T: FilterValue>(&self) -> R<(Option<T>, Option<T>)> {
+        let (from, to) = self.value.split_once('~').ok_or_else(|| self.invalid())?;
+        let parse = |s: &str| -> R<Option<T>> {
+            match s.trim() {
+                "" => Ok(None),
+                s => T::parse_value(s).map(Some).ok_or_else(|| self.invalid()),
+            }
+        };
+        Ok((parse(from)?, parse(to)?))
+    }
+
+    /// `%值%`，转义 % 和 _
+    pub fn like(&self) -> String {
+        let escaped = self
+            .value
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    }
+
+    pub fn unsupported(&self) -> anyhow::Error {
+        anyhow!(ErrKind::BadRequest
+            .err("validation.filter_field")
+            .arg("field", &self.field)
+            .build())
+    }
+
+    fn invalid(&self) -> anyhow::Error {
+        anyhow!(ErrKind::BadRequest
+            .err("validation.invalid")
+            .arg("field", &self.field)
+            .build())
+    }
+}
+
+/// 列表接口的查询参数，作为提取器使用
+#[derive(Debug, Clone)]
+pub struct PageQuery {
+    pub page: i64,
+    pub limit: i64,
+    pub sort: Vec<Sort>,
+    pub filters: Vec<Filter>,
+
+    /// 使用 keyset 分页，请求中有 cursor 参数
+    pub keyset: bool,
+    cursor: Option<String>,
+}
+
+impl Default for PageQuery {
+    fn default() -> Self {
+        Self {
+            page: 1,
+            limit: DEFAULT_LIMIT,
+            sort: vec![],
+            filters: vec![],
+            keyset: false,
+            cursor: None,
+        }
+    }
+}
+
+impl PageQuery {
+    pub fn parse(query: &str) -> Result<Self, OkErr> {
+        let invalid = |field: &str| {
+            ErrKind::BadRequest
+                .err("validation.invalid")
+                .arg("field", field)
+                .build()
+        };
+
+        let mut res = Self::default();
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()).into_owned() {
+            match key.as_str() {
+                "page" | "current" => {
+                    res.page = value.parse().map_err(|_| invalid("page"))?;
+                    if !(1..=MAX_PAGE).contains(&res.page) {
+                        return Err(invalid("page"));
+                    }
+                }
+                "limit" | "pageSize" => {
+                    res.limit = value.parse().map_err(|_| invalid("limit"))?;
+                    if !(1..=MAX_LIMIT).contains(&res.limit) {
+                        return Err(ErrKind::BadRequest
+                            .err("validation.limit")
+                            .arg("max", MAX_LIMIT)
+                            .build());
+                    }
+                }
+                "sort" => {
+                    res.sort = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|x| !x.is_empty())
+                        .map(|x| match x.strip_prefix('-') {
+                            Some(field) => Sort {
+                                field: field.to_owned(),
+                                order: Order::Desc,
+                            },
+                            None => Sort {
+                                field: x.trim_start_matches('+').to_owned(),
+                                order: Order::Asc,
+                            },
+                        })
+                        .collect();
+                    if res.sort.len() > MAX_SORT {
+                        return Err(invalid("sort"));
+                    }
+                }
+                "cursor" => {
+                    res.keyset = true;
+                    res.cursor = Some(value).filter(|x| !x.is_empty());
+                }
+                // 前端加的时间戳
+                "_" => {}
+                _ => {
+                    let (field, op) = match key.split_once("__") {
+                        Some((field, op)) => {
+                            (field, FilterOp::parse(op).ok_or_else(|| invalid(&key))?)
+                        }
+                        None => (key.as_str(), FilterOp::Eq),
+                    };
+                    // 空值表示不过滤
+                    if !value.is_empty() {
+                        res.filters.push(Filter {
+                            field: field.to_owned(),
+                            op,
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+
+        if res.keyset && !res.sort.is_empty() {
+            return Err(ErrKind::BadRequest.err("validation.cursor_sort"));
+        }
+        Ok(res)
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.limit
+    }
+
+    /// 取出不是过滤字段的参数，比如全文搜索的 `q`，之后不再作为过滤条件
+    pub fn take_param(&mut self, name: &str) -> Option<String> {
+        let index = self
+            .filters
+            .iter()
+            .position(|x| x.field == name && x.op == FilterOp::Eq)?;
+        Some(self.filters.remove(index).value)
+    }
+
+    /// keyset 分页时上一页最后一条的 id
+    pub fn after(&self) -> R<Option<PK>> {
+        let cursor = match self.cursor {
+            Some(ref x) => x,
+            None => return Ok(None),
+        };
+        base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|x| String::from_utf8(x).ok())
+            .and_then(|x| x.parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                anyhow!(ErrKind::BadRequest
+                    .err("validation.invalid")
+                    .arg("field", "cursor")
+                    .build())
+            })
+    }
+}
+
+impl FromRequest for PageQuery {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Self::parse(req.query_string()).map_err(Into::into))
+    }
+}
+
+/// 分页的结果，使用 [res_page!] 返回
+#[derive(Debug)]
+pub struct Paginated<T> {
+    pub data: Vec<T>,
+
+    /// keyset 分页时为 None
+    pub total: Option<i64>,
+    pub current: Option<i64>,
+    pub page_size: i64,
+
+    /// 下一页的 cursor，没有下一页时为 None
+    pub cursor: Option<String>,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(data: Vec<T>, total: i64, page: &PageQuery) -> Self {
+        Self {
+            data,
+            total: Some(total),
+            current: Some(page.page),
+            page_size: page.limit,
+            cursor: None,
+        }
+    }
+
+    /// keyset 分页，data 需要多查询一条，用来判断是否还有下一页
+    pub fn keyset<F: Fn(&T) -> PK>(mut data: Vec<T>, page: &PageQuery, key: F) -> Self {
+        let mut cursor = None;
+        if data.len() as i64 > page.limit {
+            data.truncate(page.limit as usize);
+            cursor = data
+                .last()
+                .map(|x| base64::encode_config(key(x).to_string(), base64::URL_SAFE_NO_PAD));
+        }
+        Self {
+            data,
+            total: None,
+            current: None,
+            page_size: page.limit,
+            cursor,
+        }
+    }
+
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Paginated<U> {
+        Paginated {
+            data: self.data.into_iter().map(f).collect(),
+            total: self.total,
+            current: self.current,
+            page_size: self.page_size,
+            cursor: self.cursor,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+/**
+ * 密码加密和密码策略
+ *
+ * 使用 argon2id，每个密码随机生成盐。参数由 PWD_MEM_COST、PWD_TIME_COST、PWD_LANES 配置，
+ * 修改后旧的密码仍然可以验证，登陆成功时重新加密，见 [needs_rehash]
+ *
+ * 密码策略: 长度、字符种类、常用密码，不能重复使用最近的密码见 [crate::serv::password]
+ */
+use crate::prelude::*;
+use once_cell::sync::Lazy;
+use std::{borrow::Cow, collections::HashSet};
+use validator::ValidationError;
+
+/// 内置的常用密码
+static COMMON: &str = include_str!("../../config/common_passwords.txt");
+
+/// 内置列表 + PWD_DENY_FILE，全部转成小写
+static DENY_LIST: Lazy<HashSet<String>> = Lazy::new(|| {
+    let extra = match conf!().pwd.deny_file.as_str() {
+        "" => String::new(),
+        path => std::fs::read_to_string(path).unwrap_or_else(|e| {
+            log::error!("读取 PWD_DENY_FILE 失败: {} {}", path, e);
+            String::new()
+        }),
+    };
+
+    COMMON
+        .lines()
+        .chain(extra.lines())
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+});
+
+fn config<'a>() -> argon2::Config<'a> {
+    let pwd = &conf!().pwd;
+    argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        version: argon2::Version::Version13,
+        mem_cost: pwd.mem_cost,
+        time_cost: pwd.time_cost,
+        lanes: pwd.lanes,
+        ..argon2::Config::default()
+    }
+}
+
+/// 加密密码
+pub fn encode(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    thread_rng().fill(&mut salt[..]);
+    argon2::hash_encoded(password.as_bytes(), &salt, &config()).unwrap()
+}
+
+/// 验证密码
+pub fn verify(encoded: &str, password: &[u8]) -> bool {
+    argon2::verify_encoded(encoded, password).unwrap_or(false)
+}
+
+/// 加密使用的参数和当前配置不一样，需要重新加密
+///
+/// $argon2id$v=19$m=19456,t=2,p=1$salt$hash
+pub fn needs_rehash(encoded: &str) -> bool {
+    let config = config();
+    let expected = format!(
+        "${}$v={}$m={},t={},p={}$",
+        config.variant, config.version, config.mem_cost, config.time_cost, config.lanes
+    );
+    !encoded.starts_with(&expected)
+}
+
+/// 随机生成满足密码策略的密码，包含所有种类的字符
+pub fn generate() -> String {
+    const CLASSES: [&[u8]; 4] = [
+        b"abcdefghijkmnopqrstuvwxyz",
+        b"ABCDEFGHJKLMNPQRSTUVWXYZ",
+        b"23456789",
+        b"!@#$%^&*-_=+",
+    ];
+    let pwd = &conf!().pwd;
+    let len = pwd.min_len.max(16).min(pwd.max_len);
+
+    let mut rng = thread_rng();
+    let mut chars: Vec<u8> = CLASSES
+        .iter()
+        .map(|x| *x.choose(&mut rng).unwrap())
+        .collect();
+    let all = CLASSES.concat();
+    while chars.len() < len {
+        chars.push(*all.choose(&mut rng).unwrap());
+    }
+    chars.shuffle(&mut rng);
+    String::from_utf8(chars).unwrap()
+}
+
+/// 密码策略，新密码必须满足，当前密码不验证
+pub fn check(password: &str) -> Result<(), ValidationError> {
+    let pwd = &conf!().pwd;
+
+    let len = password.chars().count();
+    if len < pwd.min_len || len > pwd.max_len {
+        let mut err = ValidationError::new("password_len");
+        err.add_param(Cow::from("min"), &pwd.min_len);
+        err.add_param(Cow::from("max"), &pwd.max_len);
+        return Err(err);
+    }
+
+    let classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .iter()
+    .filter(|x| **x)
+    .count();
+    if classes < pwd.min_classes {
+        let mut err = ValidationError::new("password_classes");
+        err.add_param(Cow::from("min"), &pwd.min_classes);
+        return Err(err);
+    }
+
+    if DENY_LIST.contains(&password.to_lowercase()) {
+        return Err(ValidationError::new("password_common"));
+    }
+
+    Ok(())
+}
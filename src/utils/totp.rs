@@ -0,0 +1,303 @@
+/**
+ * TOTP 两步验证 (RFC 6238)
+ *
+ * HMAC-SHA1，30秒一个周期，6位数字，兼容 Google Authenticator 等应用
+ *
+ * 密钥使用 AES-256-GCM 加密后保存到数据库，加密的密钥由 TOTP_KEY 派生
+ */
+use crate::prelude::*;
+use openssl::{
+    hash::MessageDigest,
+    pkey::PKey,
+    sign::Signer,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+
+/// 一个周期的秒数
+const STEP: i64 = 30;
+
+/// 验证码位数
+const DIGITS: u32 = 6;
+
+/// 允许前后各偏差几个周期，容忍手机和服务器的时间误差
+const SKEW: i64 = 1;
+
+/// 恢复码的数量
+const RECOVERY_CODES: usize = 10;
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 生成新的密钥，base32 编码
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    thread_rng().fill(&mut bytes[..]);
+    base32_encode(&bytes)
+}
+
+/// 绑定用的 URI，前端生成二维码
+///
+/// otpauth://totp/ab:admin?secret=XXX&issuer=ab&algorithm=SHA1&digits=6&period=30
+pub fn otpauth_uri(account: &str, secret: &str) -> R<String> {
+    let issuer = &conf!().app_name;
+    let mut url = Url::parse("otpauth://totp/")?;
+    url.set_path(&format!("{}:{}", issuer, account));
+    url.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP.to_string());
+    Ok(url.to_string())
+}
+
+/// 当前的周期
+pub fn current_step() -> i64 {
+    Utc::now().timestamp() / STEP
+}
+
+/// 计算某个周期的验证码
+fn code_at(key: &[u8], step: i64) -> R<String> {
+    let pkey = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &pkey)?;
+    signer.update(&step.to_be_bytes())?;
+    let hash = signer.sign_to_vec()?;
+
+    // 动态截取
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    Ok(format!(
+        "{:0width$}",
+        bin % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// 验证验证码，成功时返回匹配的周期，用来防止同一个验证码被重复使用
+pub fn verify(secret: &str, code: &str) -> R<Option<i64>> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+
+    let key = base32_decode(secret).ok_or_else(|| anyhow!("TOTP 密钥格式错误"))?;
+    let now = current_step();
+    for step in (now - SKEW)..=(now + SKEW) {
+        if openssl::memcmp::eq(code_at(&key, step)?.as_bytes(), code.as_bytes()) {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// 加密密钥，nonce + 密文 + tag 使用 base64 编码
+pub fn encrypt(secret: &str) -> R<String> {
+    encrypt_with(&cipher_key(), secret)
+}
+
+/// 解密数据库中保存的密钥
+pub fn decrypt(encrypted: &str) -> R<String> {
+    decrypt_with(&cipher_key(), encrypted)
+}
+
+fn encrypt_with(key: &[u8; 32], secret: &str) -> R<String> {
+    let mut nonce = [0u8; 12];
+    thread_rng().fill(&mut nonce[..]);
+    let mut tag = [0u8; 16];
+    let data = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &[],
+        secret.as_bytes(),
+        &mut tag,
+    )?;
+
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&data);
+    out.extend_from_slice(&tag);
+    Ok(base64::encode(out))
+}
+
+fn decrypt_with(key: &[u8; 32], encrypted: &str) -> R<String> {
+    let raw = base64::decode(encrypted)?;
+    if raw.len() < 12 + 16 {
+        bail!("TOTP 密钥格式错误");
+    }
+    let (nonce, rest) = raw.split_at(12);
+    let (data, tag) = rest.split_at(rest.len() - 16);
+    let plain = decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), &[], data, tag)
+        .map_err(|_| anyhow!("TOTP 密钥解密失败，TOTP_KEY 是否被修改"))?;
+    Ok(String::from_utf8(plain)?)
+}
+
+/// TOTP_KEY 为空时使用 PWD_SECRET，sha256 得到 32 字节的密钥
+fn cipher_key() -> [u8; 32] {
+    let config = conf!();
+    let key = if config.totp_key.is_empty() {
+        &config.pwd_secret
+    } else {
+        &config.totp_key
+    };
+    openssl::sha::sha256(key.as_bytes())
+}
+
+/// 生成一组恢复码，返回 (明文, 保存到数据库的 sha256 json 数组)
+///
+/// 明文只在生成时返回一次，格式 xxxxx-xxxxx
+pub fn generate_recovery_codes() -> R<(Vec<String>, String)> {
+    let codes = (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = random_token!(5);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect::<Vec<_>>();
+    let hashes = codes
+        .iter()
+        .map(|x| hash_recovery_code(x))
+        .collect::<Vec<_>>();
+    Ok((codes, serde_json::to_string(&hashes)?))
+}
+
+/// 使用一个恢复码，成功时返回剩余恢复码的 json 数组
+pub fn use_recovery_code(stored: Option<&str>, code: &str) -> R<Option<String>> {
+    let mut hashes: Vec<String> = match stored {
+        Some(s) if !s.is_empty() => serde_json::from_str(s)?,
+        _ => return Ok(None),
+    };
+
+    let hash = hash_recovery_code(code);
+    match hashes.iter().position(|x| *x == hash) {
+        Some(i) => {
+            hashes.remove(i);
+            Ok(Some(serde_json::to_string(&hashes)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// 忽略大小写、空格和 -
+fn hash_recovery_code(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    hex::encode(openssl::sha::sha256(code.as_bytes()))
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() * 8 + 4) / 5);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &b in data {
+        buffer = (buffer << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in s.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+        let v = BASE32
+            .iter()
+            .position(|x| *x as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | v;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 4648 第 10 节，编码时不输出 `=`
+    const BASE32_VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "MY======"),
+        ("fo", "MZXQ===="),
+        ("foo", "MZXW6==="),
+        ("foob", "MZXW6YQ="),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI======"),
+    ];
+
+    #[test]
+    fn base32_rfc4648() {
+        for (plain, encoded) in BASE32_VECTORS {
+            assert_eq!(
+                base32_encode(plain.as_bytes()),
+                encoded.trim_end_matches('=')
+            );
+            assert_eq!(base32_decode(encoded).unwrap(), plain.as_bytes());
+        }
+        assert_eq!(base32_decode("mzxw 6ytb").unwrap(), b"fooba");
+        assert!(base32_decode("MZXW1").is_none());
+    }
+
+    /// RFC 6238 附录 B 的 SHA1 向量，8 位验证码取后 6 位
+    #[test]
+    fn totp_rfc6238_sha1() {
+        let key = b"12345678901234567890";
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (time, code) in vectors {
+            assert_eq!(code_at(key, time / STEP).unwrap(), code, "time {}", time);
+        }
+    }
+
+    #[test]
+    fn secret_round_trip() {
+        let secret = generate_secret();
+        assert_eq!(base32_decode(&secret).unwrap().len(), 20);
+
+        let key = [7u8; 32];
+        let encrypted = encrypt_with(&key, &secret).unwrap();
+        assert_ne!(encrypt_with(&key, &secret).unwrap(), encrypted);
+        assert_eq!(decrypt_with(&key, &encrypted).unwrap(), secret);
+
+        // 密钥不对或者密文被修改时解密失败
+        assert!(decrypt_with(&[8u8; 32], &encrypted).is_err());
+        let mut raw = base64::decode(&encrypted).unwrap();
+        for i in [0, 12, raw.len() - 1] {
+            raw[i] ^= 1;
+            assert!(decrypt_with(&key, &base64::encode(&raw)).is_err());
+            raw[i] ^= 1;
+        }
+        assert!(decrypt_with(&key, &base64::encode(&raw[..20])).is_err());
+    }
+
+    #[test]
+    fn recovery_code_once() {
+        let (codes, stored) = generate_recovery_codes().unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+
+        let code = codes[0].to_uppercase().replace('-', " ");
+        let rest = use_recovery_code(Some(&stored), &code).unwrap().unwrap();
+        assert!(use_recovery_code(Some(&rest), &codes[0]).unwrap().is_none());
+        assert!(use_recovery_code(Some(&rest), &codes[1]).unwrap().is_some());
+    }
+}
@@ -41,3 +41,16 @@ pub fn default_false() -> bool {
 pub fn default_true() -> bool {
     true
 }
+
+/// 支持的语言，见 [crate::utils::i18n::Locale]
+pub fn locale(data: &str) -> Result<(), ValidationError> {
+    match data.parse::<crate::utils::i18n::Locale>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("locale")),
+    }
+}
+
+/// 新密码必须满足密码策略，见 [crate::utils::password::check]
+pub fn password(data: &str) -> Result<(), ValidationError> {
+    crate::utils::password::check(data)
+}
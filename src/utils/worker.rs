@@ -0,0 +1,156 @@
+use crate::prelude::*;
+use std::sync::Mutex;
+use tokio::{
+    sync::watch,
+    time::{sleep, Duration, Instant},
+};
+
+/// 超过这个时间没有心跳，认为后台任务已经卡住
+const STALE_MS: i64 = 30 * 1000;
+
+/// 后台任务(tasks::*, queues::*)的运行状态和停机协调
+///
+/// 用于 /readyz 检查，以及 HttpServer 停止后等待后台任务退出
+#[derive(Clone)]
+pub struct Workers {
+    inner: Arc<Mutex<HashMap<String, WorkerState>>>,
+
+    /// 停机信号，自己持有一个接收端保证通道不会关闭
+    shutdown_tx: Arc<watch::Sender<bool>>,
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+#[derive(Debug, Clone)]
+struct WorkerState {
+    last_beat: i64,
+    exited: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkerStatus {
+    pub name: String,
+    pub running: bool,
+
+    /// 距离上次心跳的毫秒数
+    pub last_beat_ms: i64,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(false);
+        Self {
+            inner: Default::default(),
+            shutdown_tx: Arc::new(tx),
+            shutdown_rx: rx,
+        }
+    }
+}
+
+impl Workers {
+    /// 注册一个后台任务
+    ///
+    /// 任务每次循环都应该调用 [WorkerHandle::beat]，并在 [WorkerHandle::is_shutdown] 后退出循环
+    pub fn register(&self, name: &str) -> WorkerHandle {
+        self.inner.lock().unwrap().insert(
+            name.to_owned(),
+            WorkerState {
+                last_beat: timestamp_ms!(),
+                exited: false,
+            },
+        );
+        WorkerHandle {
+            name: name.to_owned(),
+            workers: self.clone(),
+            shutdown: self.shutdown_rx.clone(),
+        }
+    }
+
+    pub fn status(&self) -> Vec<WorkerStatus> {
+        let now = timestamp_ms!();
+        let mut list = self
+            .inner
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, state)| WorkerStatus {
+                name: name.clone(),
+                running: !state.exited && now - state.last_beat < STALE_MS,
+                last_beat_ms: now - state.last_beat,
+            })
+            .collect::<Vec<_>>();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// 还没有退出的任务
+    fn alive(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| !state.exited)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// 通知所有后台任务停止读取新消息，等待它们处理完当前消息后退出
+    pub async fn shutdown(&self, timeout: Duration) {
+        let _ = self.shutdown_tx.send(true);
+
+        let begin = Instant::now();
+        loop {
+            let alive = self.alive();
+            if alive.is_empty() {
+                log::info!("后台任务已全部退出");
+                return;
+            }
+            if begin.elapsed() >= timeout {
+                log::warn!("等待后台任务退出超时，强制退出: {:?}", alive);
+                return;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// 任务退出(包括 panic)时自动标记为已退出
+pub struct WorkerHandle {
+    name: String,
+    workers: Workers,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl WorkerHandle {
+    pub fn beat(&self) {
+        if let Some(state) = self.workers.inner.lock().unwrap().get_mut(&self.name) {
+            state.last_beat = timestamp_ms!();
+        }
+    }
+
+    /// 是否收到停机信号
+    pub fn is_shutdown(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// 空闲等待，收到停机信号时立即返回
+    pub async fn sleep(&mut self, duration: Duration) {
+        if self.is_shutdown() {
+            return;
+        }
+        tokio::select! {
+            _ = sleep(duration) => (),
+            _ = self.shutdown.changed() => (),
+        }
+    }
+}
+
+impl Drop for WorkerHandle {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.workers.inner.lock() {
+            if let Some(state) = inner.get_mut(&self.name) {
+                state.exited = true;
+            }
+        }
+        log::info!("后台任务已退出: {}", self.name);
+    }
+}